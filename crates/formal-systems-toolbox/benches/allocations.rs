@@ -0,0 +1,110 @@
+// Counts the allocations made by a search, and by updating a deep subject,
+// rather than timing them:
+//
+//     cargo bench --bench allocations
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use formal_systems_toolbox::*;
+
+use formal_systems_toolbox::fixtures::{empty, hgt_rules, max_rules, node, rules, succ, zero};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn hgt() -> FormalSystem {
+    FormalSystem::new(rules(&[max_rules, hgt_rules]), 16)
+}
+
+fn deep_copy(judgement: &Judgement) -> Judgement {
+    match judgement {
+        Judgement::Operator {
+            predicate,
+            subjects,
+        } => Judgement::operator(
+            predicate,
+            subjects.iter().map(|subject| deep_copy(subject)).collect(),
+        ),
+        _ => judgement.clone(),
+    }
+}
+
+// Copies every node of the term, as updating a subject did before subjects
+// were shared
+fn rebuild_with(judgement: &Judgement, path: &[usize], subject: &Judgement) -> Judgement {
+    match (judgement, path) {
+        (_, []) => subject.clone(),
+        (
+            Judgement::Operator {
+                predicate,
+                subjects,
+            },
+            [index, rest @ ..],
+        ) => Judgement::operator(
+            predicate,
+            subjects
+                .iter()
+                .enumerate()
+                .map(|(i, inner)| {
+                    if i == *index {
+                        rebuild_with(inner, rest, subject)
+                    } else {
+                        deep_copy(inner)
+                    }
+                })
+                .collect(),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+fn full_tree(depth: usize) -> Judgement {
+    (0..depth).fold(empty(), |tree, _| node(tree.clone(), tree))
+}
+
+fn count(name: &str, run: impl FnOnce()) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run();
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    println!("{}: {} allocations", name, after - before);
+}
+
+fn main() {
+    let system = hgt();
+    // A right comb, whose height has to be computed through every node
+    let tree = (0..6).fold(atom("empty"), |tree, _| node(atom("empty"), tree));
+
+    count("verify hgt with an open height", || {
+        system.verify(&op!("hgt", tree.clone(), var("x"))).unwrap();
+    });
+    count("verify hgt with a given height", || {
+        let height = (0..6).fold(zero(), |n, _| succ(n));
+        system.verify(&op!("hgt", tree.clone(), height)).unwrap();
+    });
+
+    // Only the spine to the updated subject is allocated again when the
+    // rest of the tree is shared
+    let tree = full_tree(12);
+    let path = [0; 12];
+    count("update rebuilding", || {
+        rebuild_with(&tree, &path, &succ(zero()));
+    });
+    count("update sharing", || {
+        tree.with_subject_at(&path, succ(zero()));
+    });
+}
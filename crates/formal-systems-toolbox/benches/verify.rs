@@ -0,0 +1,160 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use formal_systems_toolbox::*;
+
+use formal_systems_toolbox::fixtures::{empty, nat, node, succ, zero};
+
+fn goals() -> Vec<Judgement> {
+    vec![
+        op!("nat", succ(succ(succ(zero())))),
+        op!("sum", succ(zero()), var("x"), succ(succ(succ(zero())))),
+        op!(
+            "max",
+            succ(succ(succ(zero()))),
+            succ(succ(zero())),
+            succ(succ(succ(zero())))
+        ),
+        op!(
+            "hgt",
+            node(empty(), node(empty(), empty())),
+            succ(succ(zero()))
+        ),
+        op!("hgt", node(empty(), node(empty(), empty())), var("x")),
+    ]
+}
+
+fn repeated_verification(c: &mut Criterion) {
+    let goals = goals();
+
+    let raw = nat();
+    c.bench_function("verify raw", |b| {
+        b.iter(|| {
+            for goal in &goals {
+                raw.verify(goal).unwrap();
+            }
+        })
+    });
+
+    let compiled = nat().compile();
+    c.bench_function("verify compiled", |b| {
+        b.iter(|| {
+            for goal in &goals {
+                compiled.verify(goal).unwrap();
+            }
+        })
+    });
+}
+
+fn ground_verification(c: &mut Criterion) {
+    let numeral = |n: usize| (0..n).fold(zero(), |m, _| succ(m));
+    let goals = vec![
+        op!("sum", numeral(6), numeral(6), numeral(12)),
+        op!("max", numeral(7), numeral(5), numeral(7)),
+        op!(
+            "hgt",
+            node(empty(), node(empty(), node(empty(), empty()))),
+            numeral(3)
+        ),
+    ];
+
+    // The same goals with and without the fast path for ground premises
+    let system = nat().with_max_derivation_height(16);
+    let general = system.clone().with_ground_fast_path(false);
+    c.bench_function("verify ground", |b| {
+        b.iter(|| {
+            for goal in &goals {
+                system.verify(goal).unwrap();
+            }
+        })
+    });
+    c.bench_function("verify ground without fast path", |b| {
+        b.iter(|| {
+            for goal in &goals {
+                general.verify(goal).unwrap();
+            }
+        })
+    });
+}
+
+fn full_tree(depth: usize) -> Judgement {
+    (0..depth).fold(empty(), |tree, _| node(tree.clone(), tree))
+}
+
+fn pattern_matching(c: &mut Criterion) {
+    let s2 = op!("sum", var("n"), succ(var("m")), succ(var("p")));
+    let pattern = compiled::CompiledPattern::new(&s2);
+
+    let numerals: Vec<Judgement> = (0..8)
+        .scan(zero(), |n, _| {
+            *n = succ(n.clone());
+            Some(n.clone())
+        })
+        .collect();
+    let goals: Vec<Judgement> = numerals
+        .iter()
+        .flat_map(|n| {
+            numerals
+                .iter()
+                .map(|m| op!("sum", n.clone(), m.clone(), succ(m.clone())))
+        })
+        .collect();
+
+    c.bench_function("match by unification", |b| {
+        b.iter(|| goals.iter().filter(|goal| s2.unify(goal).is_ok()).count())
+    });
+    c.bench_function("match compiled pattern", |b| {
+        b.iter(|| {
+            goals
+                .iter()
+                .filter(|goal| pattern.match_against(goal).is_some())
+                .count()
+        })
+    });
+}
+
+// Unifies deep terms with copies of themselves where some subterms were
+// replaced by variables, so that every node is visited
+fn deep_unification(c: &mut Criterion) {
+    let tree = full_tree(10);
+    // The right subtrees hanging from the leftmost branch
+    let pattern = (0..10).fold(tree.clone(), |pattern, depth| {
+        let mut path = vec![0; depth];
+        path.push(1);
+        pattern.with_subject_at(&path, var(&format!("x{}", depth)))
+    });
+    let chain = (0..512).fold(zero(), |n, _| succ(n));
+    let open_chain = (0..511).fold(var("n"), |n, _| succ(n));
+
+    c.bench_function("unify deep tree", |b| {
+        b.iter(|| pattern.unify(&tree).unwrap())
+    });
+    c.bench_function("unify deep numeral", |b| {
+        b.iter(|| open_chain.unify(&chain).unwrap())
+    });
+}
+
+fn open_enumeration(c: &mut Criterion) {
+    let system = nat().with_max_derivation_height(6);
+    let goals = vec![
+        op!("nat", var("x")),
+        op!("sum", var("x"), var("y"), succ(succ(succ(zero())))),
+        op!("max", var("x"), var("y"), succ(succ(zero()))),
+    ];
+
+    c.bench_function("enumerate open goals", |b| {
+        b.iter(|| {
+            for goal in &goals {
+                system.verify_all(goal);
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    repeated_verification,
+    ground_verification,
+    pattern_matching,
+    deep_unification,
+    open_enumeration
+);
+criterion_main!(benches);
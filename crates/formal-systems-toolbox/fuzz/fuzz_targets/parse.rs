@@ -0,0 +1,24 @@
+// Parsing any text returns, with a result or an error:
+//
+//     cargo fuzz run parse
+#![no_main]
+
+use formal_systems_toolbox::parser::{self, IncrementalRules};
+use formal_systems_toolbox::{FormalSystem, Judgement, Rule};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Ok(judgement) = input.parse::<Judgement>() {
+        // What is printed reads back as the same judgement, unless numerals
+        // and lists written short made it too deep to read
+        if let Ok(printed) = judgement.to_string().parse::<Judgement>() {
+            assert_eq!(printed, judgement);
+        }
+    }
+    let _ = input.parse::<Rule>();
+    let _ = FormalSystem::try_from(input);
+    let _ = IncrementalRules::parse(input);
+    let _ = Judgement::from_sexp(input);
+    let _ = parser::predicate(input);
+    let _ = parser::rule(input);
+});
@@ -0,0 +1,43 @@
+// Unifiers of arbitrary judgements make both sides equal:
+//
+//     cargo fuzz run unify
+#![no_main]
+
+use formal_systems_toolbox::{atom, op, var, Judgement};
+use libfuzzer_sys::fuzz_target;
+
+// Reads a judgement from the front of `bytes`, a few variables and
+// constructors being enough to reach every case of the unifier
+fn judgement(bytes: &mut &[u8], depth: usize) -> Judgement {
+    let Some((&byte, rest)) = bytes.split_first() else {
+        return atom("a");
+    };
+    *bytes = rest;
+    match byte % if depth == 0 { 5 } else { 8 } {
+        0 => var("x"),
+        1 => var("y"),
+        2 => var("z"),
+        3 => atom("a"),
+        4 => atom("b"),
+        5 => op!("g", judgement(bytes, depth - 1)),
+        6 => op!("f", judgement(bytes, depth - 1), judgement(bytes, depth - 1)),
+        _ => op!(
+            "f",
+            judgement(bytes, depth - 1),
+            judgement(bytes, depth - 1),
+            judgement(bytes, depth - 1)
+        ),
+    }
+}
+
+fuzz_target!(|bytes: &[u8]| {
+    let mut bytes = bytes;
+    let left = judgement(&mut bytes, 16);
+    let right = judgement(&mut bytes, 16);
+    if let Ok(substitutions) = left.unify(&right) {
+        assert_eq!(
+            left.apply_substitution(&substitutions),
+            right.apply_substitution(&substitutions)
+        );
+    }
+});
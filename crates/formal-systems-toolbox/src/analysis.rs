@@ -0,0 +1,567 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{rename_apart, Derivation, FormalSystem, Judgement, Rule, UnificationTable};
+
+// Why two rules of a system make one another, or their names, redundant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redundancy {
+    // The same rule up to the names of its variables and the order of its
+    // premises, so either can be deleted
+    AlphaEqual,
+    // Whatever the rule at `specific` derives, the one at `more_general` derives
+    // too, from a subset of its premises, so `specific` can be deleted
+    Subsumes {
+        more_general: usize,
+        specific: usize,
+    },
+    // Unrelated rules sharing a name, one of which should be renamed
+    NameClash,
+}
+
+// Visits a derivation bottom up, each premise before the judgement it supports
+pub struct ProvenGoals<'a> {
+    // Derivations being visited, with how many of their premises were entered
+    stack: Vec<(&'a Derivation, usize)>,
+}
+
+impl<'a> Iterator for ProvenGoals<'a> {
+    type Item = (&'a Judgement, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (derivation, entered) = self.stack.last_mut()?;
+            let derivation: &'a Derivation = derivation;
+            if let Some(premise) = derivation.premises.get(*entered) {
+                *entered += 1;
+                self.stack.push((premise, 0));
+            } else {
+                self.stack.pop();
+                return Some((&derivation.conclusion, derivation.rule_label.as_str()));
+            }
+        }
+    }
+}
+
+impl Derivation {
+    // Every judgement of the derivation with the rule that proved it, in the
+    // order the search completed them
+    pub fn proven_goals(&self) -> ProvenGoals<'_> {
+        ProvenGoals {
+            stack: vec![(self, 0)],
+        }
+    }
+
+    pub fn rule_names(&self) -> HashSet<String> {
+        let mut names = HashSet::from([self.rule_label.clone()]);
+        for premise in &self.premises {
+            names.extend(premise.rule_names());
+        }
+        names
+    }
+}
+
+impl FormalSystem {
+    // Names of the rules appearing in the derivation found for `goal`
+    pub fn used_axioms(&self, goal: &Judgement) -> Option<HashSet<String>> {
+        self.verify(goal).map(|proof| proof.rule_names())
+    }
+
+    // Starting from the rules used by a derivation of `goal`, drops every rule
+    // the goal can still be proved without. The result is minimal, in the sense
+    // that no single rule can be removed, but not necessarily the smallest
+    pub fn minimal_axiom_set(&self, goal: &Judgement) -> Option<HashSet<String>> {
+        let used = self.used_axioms(goal)?;
+
+        let mut system = self.clone();
+        system.axioms.retain(|axiom| used.contains(&axiom.name));
+
+        let mut position = 0;
+        while position < system.axioms.len() {
+            let mut candidate = system.clone();
+            candidate.axioms.remove(position);
+            if candidate.verify(goal).is_some() {
+                system = candidate;
+            } else {
+                position += 1;
+            }
+        }
+
+        Some(system.axioms.into_iter().map(|axiom| axiom.name).collect())
+    }
+
+    // Pairs of rule positions, in declaration order, with why they are redundant
+    pub fn redundant_rules(&self) -> Vec<(usize, usize, Redundancy)> {
+        let mut redundant: Vec<(usize, usize, Redundancy)> = vec![];
+
+        for (i, first) in self.axioms.iter().enumerate() {
+            for (j, second) in self.axioms.iter().enumerate().skip(i + 1) {
+                let redundancy = match (subsumes(first, second), subsumes(second, first)) {
+                    (true, true) if first.premises.len() == second.premises.len() => {
+                        Redundancy::AlphaEqual
+                    }
+                    (true, _) => Redundancy::Subsumes {
+                        more_general: i,
+                        specific: j,
+                    },
+                    (_, true) => Redundancy::Subsumes {
+                        more_general: j,
+                        specific: i,
+                    },
+                    _ if first.name == second.name => Redundancy::NameClash,
+                    _ => continue,
+                };
+                redundant.push((i, j, redundancy));
+            }
+        }
+
+        redundant
+    }
+
+    // Pairs of rules, in declaration order, that can both be applied to some
+    // goal. A system without any is deterministic
+    pub fn overlapping_conclusions(&self) -> Vec<(String, String)> {
+        let mut overlapping: Vec<(String, String)> = vec![];
+
+        for (i, first) in self.axioms.iter().enumerate() {
+            let taken = first.get_variables();
+            for second in &self.axioms[i + 1..] {
+                let second = rename_apart(second, &taken);
+                let unifiable = if self.commutative.is_empty() {
+                    first.conclusion.unify(&second.conclusion).is_ok()
+                } else {
+                    !first
+                        .conclusion
+                        .unify_modulo(&second.conclusion, &self.commutative)
+                        .is_empty()
+                };
+                if unifiable {
+                    overlapping.push((first.name.clone(), second.name.clone()));
+                }
+            }
+        }
+
+        overlapping
+    }
+
+    // Edges from the predicate of each conclusion to the predicates of the
+    // premises, the latter being negative for negated premises. Predicates
+    // concluded by rules without premises have no edges, only an entry
+    fn dependency_edges(&self) -> HashMap<String, HashMap<String, bool>> {
+        let mut edges: HashMap<String, HashMap<String, bool>> = HashMap::new();
+
+        for axiom in &self.axioms {
+            let Judgement::Operator { predicate, .. } = &axiom.conclusion else {
+                continue;
+            };
+            let dependencies = edges.entry(predicate.clone()).or_default();
+            for premise in &axiom.premises {
+                let (premise, negative) = match premise.as_negation() {
+                    Some(negated) => (negated, true),
+                    None => (premise, false),
+                };
+                if let Judgement::Operator { predicate, .. } = premise {
+                    *dependencies.entry(predicate.clone()).or_default() |= negative;
+                }
+            }
+        }
+
+        edges
+    }
+
+    // Maps the predicate of each conclusion to the predicates of the premises
+    // of the rules concluding it, looking through negations
+    pub fn predicate_dependency_graph(&self) -> HashMap<String, HashSet<String>> {
+        self.dependency_edges()
+            .into_iter()
+            .map(|(predicate, dependencies)| (predicate, dependencies.into_keys().collect()))
+            .collect()
+    }
+
+    // A system is stratified when no predicate depends on itself through a
+    // negated premise. Otherwise, returns a cycle `p1, p2, .., pn` where each
+    // predicate depends on the next, `pn` on `p1`, and `p1` negatively on `p2`
+    pub fn is_stratified(&self) -> Result<(), Vec<String>> {
+        let edges = self.dependency_edges();
+
+        let mut negative: Vec<(&String, &String)> = edges
+            .iter()
+            .flat_map(|(predicate, dependencies)| {
+                dependencies
+                    .iter()
+                    .filter(|(_, negative)| **negative)
+                    .map(move |(dependency, _)| (predicate, dependency))
+            })
+            .collect();
+        negative.sort();
+
+        for (predicate, dependency) in negative {
+            if let Some(path) = shortest_path(&edges, dependency, predicate) {
+                let mut cycle = vec![predicate.clone()];
+                cycle.extend(path.into_iter().take_while(|step| step != predicate));
+                return Err(cycle);
+            }
+        }
+        Ok(())
+    }
+
+    // The dependency graph in Graphviz syntax, with nodes and edges sorted and
+    // negative edges dashed
+    pub fn predicate_dependency_dot(&self) -> String {
+        let edges = self.dependency_edges();
+        let quote = |predicate: &str| format!("\"{}\"", predicate.replace('"', "\\\""));
+
+        let mut dot = String::from("digraph predicates {\n");
+        let mut predicates: Vec<&String> = edges.keys().collect();
+        predicates.sort();
+        for predicate in predicates {
+            dot += &format!("    {};\n", quote(predicate));
+            let mut dependencies: Vec<(&String, &bool)> = edges[predicate].iter().collect();
+            dependencies.sort();
+            for (dependency, negative) in dependencies {
+                dot += &format!(
+                    "    {} -> {}{};\n",
+                    quote(predicate),
+                    quote(dependency),
+                    if *negative { " [style=dashed]" } else { "" }
+                );
+            }
+        }
+        dot + "}\n"
+    }
+}
+
+// Whether an instance of `general` has the conclusion of `specific` and only
+// premises of `specific`. The variables of `specific` are fixed, so that
+// unification only instantiates `general`
+fn subsumes(general: &Rule, specific: &Rule) -> bool {
+    fn premises_match(
+        general: &[Judgement],
+        specific: &[Judgement],
+        substitutions: &UnificationTable,
+    ) -> bool {
+        let Some((premise, rest)) = general.split_first() else {
+            return true;
+        };
+        specific.iter().any(|candidate| {
+            premise
+                .would_unify(candidate, substitutions)
+                .is_some_and(|substitutions| premises_match(rest, specific, &substitutions))
+        })
+    }
+
+    let variables: Vec<String> = specific.get_variables().into_iter().collect();
+    let fixed: Vec<&str> = variables.iter().map(String::as_str).collect();
+    let premises: Vec<Judgement> = specific
+        .premises
+        .iter()
+        .map(|premise| premise.fix_universal(&fixed))
+        .collect();
+
+    general
+        .conclusion
+        .would_unify(
+            &specific.conclusion.fix_universal(&fixed),
+            &UnificationTable::new(),
+        )
+        .is_some_and(|substitutions| premises_match(&general.premises, &premises, &substitutions))
+}
+
+// The predicates on a shortest path from `start` to `goal`, both included
+fn shortest_path(
+    edges: &HashMap<String, HashMap<String, bool>>,
+    start: &String,
+    goal: &String,
+) -> Option<Vec<String>> {
+    let mut previous: HashMap<&String, &String> = HashMap::new();
+    let mut queue: VecDeque<&String> = VecDeque::from([start]);
+
+    while let Some(predicate) = queue.pop_front() {
+        if predicate == goal {
+            let mut path = vec![predicate.clone()];
+            let mut step = predicate;
+            while let Some(before) = previous.get(step) {
+                path.push((*before).clone());
+                step = before;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let Some(dependencies) = edges.get(predicate) else {
+            continue;
+        };
+        let mut dependencies: Vec<&String> = dependencies.keys().collect();
+        dependencies.sort();
+        for dependency in dependencies {
+            if dependency != start && !previous.contains_key(dependency) {
+                previous.insert(dependency, predicate);
+                queue.push_back(dependency);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{hgt_rules, max_rules, nat_rules, rules, succ, sum_rules, zero};
+    use crate::{atom, op, var, Rule};
+
+    fn nat() -> Vec<Rule> {
+        rules(&[nat_rules, sum_rules])
+    }
+
+    #[test]
+    fn used_and_minimal_axioms() {
+        let system = FormalSystem::new(nat(), 8);
+        let goal = op!("nat", succ(zero()));
+        let expected = HashSet::from([String::from("zero"), String::from("succ")]);
+
+        assert_eq!(system.used_axioms(&goal), Some(expected.clone()));
+        assert_eq!(system.minimal_axiom_set(&goal), Some(expected));
+        assert_eq!(system.used_axioms(&op!("nat", var("x"))).unwrap().len(), 1);
+        assert_eq!(system.minimal_axiom_set(&op!("max", zero())), None);
+    }
+
+    #[test]
+    fn proven_goals_in_post_order() {
+        let empty = || atom("empty");
+        let axioms = rules(&[nat_rules, sum_rules, max_rules, hgt_rules]);
+        let system = FormalSystem::new(axioms, 8);
+        let proof = system
+            .verify(&op!(
+                "hgt",
+                op!("node", empty(), op!("node", empty(), empty())),
+                succ(succ(zero()))
+            ))
+            .unwrap();
+
+        let goals: Vec<String> = proof
+            .proven_goals()
+            .map(|(goal, rule)| format!("{} {}", rule, goal))
+            .collect();
+        assert_eq!(
+            goals,
+            vec![
+                "h1 hgt(empty(), zero())",
+                "h1 hgt(empty(), zero())",
+                "h1 hgt(empty(), zero())",
+                "max1 max(zero(), zero(), zero())",
+                "h2 hgt(node(empty(), empty()), succ(zero()))",
+                "max2 max(zero(), succ(zero()), succ(zero()))",
+                "h2 hgt(node(empty(), node(empty(), empty())), succ(succ(zero())))",
+            ]
+        );
+
+        let maxima: Vec<&Judgement> = proof
+            .proven_goals()
+            .filter(|(goal, _)| matches!(goal, Judgement::Operator { predicate, .. } if predicate == "max"))
+            .map(|(goal, _)| goal)
+            .collect();
+        assert_eq!(
+            maxima,
+            vec![
+                &op!("max", zero(), zero(), zero()),
+                &op!("max", zero(), succ(zero()), succ(zero()))
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_rules() {
+        let max = FormalSystem::new(max_rules(), 8);
+        assert_eq!(
+            max.overlapping_conclusions(),
+            vec![(String::from("max1"), String::from("max2"))]
+        );
+
+        assert!(FormalSystem::new(nat(), 8)
+            .overlapping_conclusions()
+            .is_empty());
+
+        // Shared variable names do not constrain rules against each other
+        let pairs = FormalSystem::new(
+            vec![
+                Rule::taut("same", op!("pair", var("x"), var("x"))),
+                Rule::taut("left", op!("pair", zero(), succ(var("x")))),
+            ],
+            8,
+        );
+        assert!(pairs.overlapping_conclusions().is_empty());
+        let pairs = FormalSystem::new(
+            vec![
+                Rule::taut("left", op!("pair", zero(), var("x"))),
+                Rule::taut("right", op!("pair", var("x"), succ(zero()))),
+            ],
+            8,
+        );
+        assert_eq!(pairs.overlapping_conclusions().len(), 1);
+    }
+
+    #[test]
+    fn predicate_dependencies() {
+        let mut axioms = rules(&[nat_rules, sum_rules, max_rules, hgt_rules]);
+        axioms.extend([Rule::new(
+            "positive",
+            vec![Judgement::diseq(var("n"), zero()), op!("nat", var("n"))],
+            op!("positive", var("n")),
+        )]);
+        let system = FormalSystem::new(axioms, 8);
+        let set = |predicates: &[&str]| -> HashSet<String> {
+            predicates
+                .iter()
+                .map(|predicate| predicate.to_string())
+                .collect()
+        };
+
+        let graph = system.predicate_dependency_graph();
+        assert_eq!(graph["hgt"], set(&["hgt", "max"]));
+        assert_eq!(graph["max"], set(&["max"]));
+        assert_eq!(graph["nat"], set(&["nat"]));
+        assert_eq!(graph["positive"], set(&["nat"]));
+        assert_eq!(graph.len(), 5);
+
+        assert_eq!(
+            system.predicate_dependency_dot(),
+            r#"digraph predicates {
+    "hgt";
+    "hgt" -> "hgt";
+    "hgt" -> "max";
+    "max";
+    "max" -> "max";
+    "nat";
+    "nat" -> "nat";
+    "positive";
+    "positive" -> "nat";
+    "sum";
+    "sum" -> "sum";
+}
+"#
+        );
+    }
+
+    #[test]
+    fn stratification() {
+        let p = |name: &str| op!(name, var("x"));
+        let rule = |name: &str, premises: Vec<Judgement>, conclusion: &str| {
+            Rule::new(name, premises, p(conclusion))
+        };
+
+        let safe = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("even", zero())),
+                rule("odd", vec![Judgement::negation(p("even"))], "odd"),
+                rule(
+                    "strange",
+                    vec![p("odd"), Judgement::negation(p("even"))],
+                    "strange",
+                ),
+            ],
+            8,
+        );
+        assert_eq!(safe.is_stratified(), Ok(()));
+        assert!(safe
+            .predicate_dependency_dot()
+            .contains("\"odd\" -> \"even\" [style=dashed];"));
+
+        let direct = FormalSystem::new(vec![rule("p", vec![Judgement::negation(p("p"))], "p")], 8);
+        assert_eq!(direct.is_stratified(), Err(vec![String::from("p")]));
+
+        let transitive = FormalSystem::new(
+            vec![
+                rule("p", vec![p("q")], "p"),
+                rule("q", vec![p("s"), Judgement::negation(p("r"))], "q"),
+                rule("r", vec![p("p")], "r"),
+                rule("s", vec![p("q")], "s"),
+            ],
+            8,
+        );
+        assert_eq!(
+            transitive.is_stratified(),
+            Err(vec![
+                String::from("q"),
+                String::from("r"),
+                String::from("p")
+            ])
+        );
+        // Positive cycles are harmless
+        assert_eq!(
+            FormalSystem::new(
+                vec![rule("p", vec![p("q")], "p"), rule("q", vec![p("p")], "q")],
+                8
+            )
+            .is_stratified(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn redundant_rules() {
+        let mut axioms = nat();
+        axioms.extend([
+            // `succ` with its variable renamed
+            Rule::new(
+                "successor",
+                vec![op!("nat", var("m"))],
+                op!("nat", succ(var("m"))),
+            ),
+            Rule::taut("one", op!("nat", succ(zero()))),
+            Rule::new(
+                "even",
+                vec![op!("nat", var("n")), op!("even", var("n"))],
+                op!("nat", succ(var("n"))),
+            ),
+            Rule::taut("zero", op!("even", zero())),
+        ]);
+        let system = FormalSystem::new(axioms, 8);
+
+        assert_eq!(
+            system.redundant_rules(),
+            vec![
+                (0, 7, Redundancy::NameClash),
+                (1, 4, Redundancy::AlphaEqual),
+                (
+                    1,
+                    6,
+                    Redundancy::Subsumes {
+                        more_general: 1,
+                        specific: 6
+                    }
+                ),
+                (
+                    4,
+                    6,
+                    Redundancy::Subsumes {
+                        more_general: 4,
+                        specific: 6
+                    }
+                ),
+            ]
+        );
+        assert!(FormalSystem::new(nat(), 8).redundant_rules().is_empty());
+    }
+
+    #[test]
+    fn redundant_axioms() {
+        // `two` shortcuts the derivation, so `zero` is never needed
+        let mut axioms = vec![Rule::taut("two", op!("nat", succ(succ(zero()))))];
+        axioms.extend(nat());
+        let system = FormalSystem::new(axioms, 8);
+        let goal = op!("nat", succ(succ(succ(zero()))));
+
+        assert_eq!(
+            system.used_axioms(&goal),
+            Some(HashSet::from([String::from("two"), String::from("succ")]))
+        );
+
+        let minimal = system.minimal_axiom_set(&goal).unwrap();
+        assert_eq!(minimal.len(), 2);
+        let mut sufficient = system.clone();
+        sufficient
+            .axioms
+            .retain(|axiom| minimal.contains(&axiom.name));
+        assert!(sufficient.verify(&goal).is_some());
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use super::{Cancelled, Derivation, FormalSystem, Judgement};
+
+// Raises the cancellation flag when the future driving the search is dropped
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl FormalSystem {
+    // Runs the search on the blocking thread pool so the runtime stays responsive.
+    // Cancelling `token` or dropping the returned future stops the search.
+    pub async fn verify_async(
+        self: Arc<Self>,
+        judgement: Judgement,
+        token: CancellationToken,
+    ) -> Result<Option<Derivation>, Cancelled> {
+        let cancellation = Arc::new(AtomicBool::new(false));
+        let _guard = CancelOnDrop(cancellation.clone());
+
+        let search =
+            tokio::task::spawn_blocking(move || self.verify_cancellable(&judgement, &cancellation));
+
+        tokio::select! {
+            result = search => match result {
+                Ok(result) => result,
+                Err(error) => std::panic::resume_unwind(error.into_panic()),
+            },
+            _ = token.cancelled() => Err(Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::fixtures::nat_rules;
+    use crate::{atom, op, var, Rule};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn async_verification() {
+        let nat = Arc::new(FormalSystem::new(nat_rules(), 8));
+
+        let result = runtime().block_on(nat.verify_async(
+            op!("nat", op!("succ", atom("zero"))),
+            CancellationToken::new(),
+        ));
+        assert!(matches!(result, Ok(Some(_))));
+    }
+
+    #[test]
+    fn async_cancellation() {
+        // Every goal branches into two fresh, ever larger goals
+        let explosive = Arc::new(FormalSystem::new(
+            vec![
+                Rule::new("f", vec![op!("p", op!("f", var("x")))], op!("p", var("x"))),
+                Rule::new("g", vec![op!("p", op!("g", var("x")))], op!("p", var("x"))),
+            ],
+            40,
+        ));
+
+        let token = CancellationToken::new();
+        let result = runtime().block_on(async {
+            let canceller = token.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                canceller.cancel();
+            });
+            explosive.verify_async(op!("p", atom("a")), token).await
+        });
+        assert_eq!(result.err(), Some(Cancelled));
+    }
+}
@@ -0,0 +1,20 @@
+use formal_systems_toolbox::repl::repl;
+use formal_systems_toolbox::*;
+
+fn main() {
+    let mut system = FormalSystem::new(vec![], DEFAULT_MAX_DERIVATION_HEIGHT);
+
+    if let Some(path) = std::env::args().nth(1) {
+        let source = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("Could not read {}: {}", path, e);
+            std::process::exit(1);
+        });
+        system = FormalSystem::try_from(source.as_str()).unwrap_or_else(|e| {
+            eprintln!("{}:{}", path, e);
+            std::process::exit(1);
+        });
+    }
+
+    let stdin = std::io::stdin();
+    repl(system, stdin.lock(), &mut std::io::stdout()).unwrap();
+}
@@ -0,0 +1,175 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use super::{Derivation, FormalSystem, Judgement};
+
+// Derivations of the goals proved so far, meant to be saved and loaded again
+// by later runs. The cache remembers a digest of the rules its proofs were
+// found with: when used with other rules, each proof is checked again and the
+// ones that no longer hold are dropped
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofCache {
+    rules: u64,
+    // Keyed by the goal as written
+    proofs: BTreeMap<String, Derivation>,
+}
+
+impl ProofCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+
+    pub fn get(&self, goal: &Judgement) -> Option<&Derivation> {
+        self.proofs.get(&goal.to_string())
+    }
+}
+
+impl FormalSystem {
+    // The digest is only compared with others computed by the same build, as
+    // the standard hasher may change between releases; a mismatch merely
+    // costs a check of every cached proof. Sort inference and constraint
+    // solvers are functions, which the digest cannot tell apart
+    fn rules_digest(&self) -> u64 {
+        fn sorted<'s>(items: impl IntoIterator<Item = &'s String>) -> Vec<&'s String> {
+            let mut items: Vec<&String> = items.into_iter().collect();
+            items.sort();
+            items
+        }
+
+        let mut hasher = DefaultHasher::new();
+        for axiom in &self.axioms {
+            axiom.name.hash(&mut hasher);
+            axiom.to_string().hash(&mut hasher);
+            let mut sorts: Vec<_> = axiom.sorts.iter().collect();
+            sorts.sort();
+            sorts.hash(&mut hasher);
+            axiom.max_uses.hash(&mut hasher);
+            axiom.committed.hash(&mut hasher);
+        }
+        sorted(&self.commutative).hash(&mut hasher);
+        sorted(&self.equality).hash(&mut hasher);
+        sorted(&self.constraints).hash(&mut hasher);
+        for fact in &self.negative_facts {
+            fact.to_string().hash(&mut hasher);
+        }
+        self.max_derivation_height.hash(&mut hasher);
+        let mut heights: Vec<_> = self.predicate_heights.iter().collect();
+        heights.sort();
+        heights.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Like `verify`, answering from `cache` when it has a proof of `goal` and
+    // recording the proofs it finds there
+    pub fn verify_cached(&self, goal: &Judgement, cache: &mut ProofCache) -> Option<Derivation> {
+        let digest = self.rules_digest();
+        if cache.rules != digest {
+            cache
+                .proofs
+                .retain(|_, proof| self.check_derivation(proof).is_ok());
+            cache.rules = digest;
+        }
+
+        if let Some(proof) = cache.get(goal) {
+            return Some(proof.clone());
+        }
+        let proof = self.verify(goal)?;
+        cache.proofs.insert(goal.to_string(), proof.clone());
+        Some(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{nat_rules, succ, zero};
+    use crate::{op, var, Rule};
+
+    #[test]
+    fn cached_proofs() {
+        let goal = op!("nat", succ(succ(succ(zero()))));
+        let mut cache = ProofCache::new();
+        let proof = FormalSystem::new(nat_rules(), 8)
+            .verify_cached(&goal, &mut cache)
+            .unwrap();
+        assert_eq!(cache.len(), 1);
+
+        // Too shallow to find the proof again, but not to reuse it
+        let shallow = FormalSystem::new(nat_rules(), 1);
+        assert!(shallow.verify(&goal).is_none());
+        let cached = shallow.verify_cached(&goal, &mut cache).unwrap();
+        assert_eq!(cached.to_string_tree(), proof.to_string_tree());
+
+        // Proofs that the edited rules no longer support are dropped
+        let renamed = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", zero())),
+                Rule::new("s", vec![op!("nat", var("n"))], op!("nat", succ(var("n")))),
+            ],
+            1,
+        );
+        assert!(renamed.verify_cached(&goal, &mut cache).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn changed_settings() {
+        let goal = op!("nat", succ(succ(zero())));
+        let mut cache = ProofCache::new();
+        FormalSystem::new(nat_rules(), 8)
+            .verify_cached(&goal, &mut cache)
+            .unwrap();
+
+        // The same rules, with a step of the cached proof now refuted
+        let refuted =
+            FormalSystem::new(nat_rules(), 8).with_negative_facts(&[op!("nat", succ(zero()))]);
+        assert!(refuted.verify_cached(&goal, &mut cache).is_none());
+        assert!(cache.is_empty());
+
+        // A new side condition on the rule the proof used
+        FormalSystem::new(nat_rules(), 8)
+            .verify_cached(&goal, &mut cache)
+            .unwrap();
+        let guarded = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", zero())),
+                Rule::new(
+                    "succ",
+                    vec![
+                        Judgement::diseq(var("n"), succ(zero())),
+                        op!("nat", var("n")),
+                    ],
+                    op!("nat", succ(var("n"))),
+                ),
+            ],
+            8,
+        );
+        assert!(guarded.verify_cached(&goal, &mut cache).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_cache() {
+        let system = FormalSystem::new(nat_rules(), 8);
+        let goal = op!("nat", succ(succ(zero())));
+        let mut cache = ProofCache::new();
+        system.verify_cached(&goal, &mut cache).unwrap();
+
+        let saved = serde_json::to_string(&cache).unwrap();
+        let mut loaded: ProofCache = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.len(), 1);
+        let shallow = FormalSystem::new(nat_rules(), 0);
+        assert!(shallow.verify_cached(&goal, &mut loaded).is_some());
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    premise_orders, Derivation, FormalSystem, Judgement, Premise, Rule, Symbol, UnificationTable,
+};
+
+pub(crate) struct RuleIndex<S = String> {
+    // Positions of the axioms whose conclusion may unify with a given predicate,
+    // in declaration order
+    by_predicate: HashMap<S, Vec<usize>>,
+    // Axioms whose conclusion is a bare variable unify with any predicate
+    unindexed: Vec<usize>,
+    all: Vec<usize>,
+    pub(crate) variables: Vec<HashSet<S>>,
+    pub(crate) premise_orders: Vec<Vec<Vec<usize>>>,
+    // The conclusion of each axiom, compiled to reject ground goals quickly
+    pub(crate) patterns: Vec<CompiledPattern<S>>,
+}
+
+impl<S: Symbol> RuleIndex<S> {
+    fn new(system: &FormalSystem<S>) -> Self {
+        let mut by_predicate: HashMap<S, Vec<usize>> = HashMap::new();
+        let mut unindexed: Vec<usize> = vec![];
+
+        for (position, axiom) in system.axioms.iter().enumerate() {
+            match &axiom.conclusion {
+                Judgement::Operator { predicate, .. } => by_predicate
+                    .entry(predicate.clone())
+                    .or_default()
+                    .push(position),
+                _ => unindexed.push(position),
+            }
+        }
+
+        for positions in by_predicate.values_mut() {
+            positions.extend(&unindexed);
+            positions.sort();
+        }
+
+        Self {
+            by_predicate,
+            unindexed,
+            all: (0..system.axioms.len()).collect(),
+            variables: system.axioms.iter().map(Rule::get_variables).collect(),
+            premise_orders: system.axioms.iter().map(premise_orders).collect(),
+            patterns: system
+                .axioms
+                .iter()
+                .map(|axiom| CompiledPattern::new(&axiom.conclusion))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn candidates(
+        &self,
+        judgement: &Judgement<S>,
+        substitutions: &UnificationTable<S>,
+    ) -> &[usize] {
+        match judgement.resolve(substitutions) {
+            Judgement::Operator { predicate, .. } => {
+                self.by_predicate.get(predicate).unwrap_or(&self.unindexed)
+            }
+            _ => &self.all,
+        }
+    }
+}
+
+// A formal system together with the indices needed to answer repeated queries
+// without recomputing them on each call
+pub struct CompiledSystem<S = String> {
+    system: FormalSystem<S>,
+    index: RuleIndex<S>,
+}
+
+impl<S: Symbol> CompiledSystem<S> {
+    pub fn verify(&self, judgement: &Judgement<S>) -> Option<Derivation<S>> {
+        self.system.verify_indexed(Some(&self.index), judgement)
+    }
+
+    pub fn system(&self) -> &FormalSystem<S> {
+        &self.system
+    }
+
+    pub fn into_system(self) -> FormalSystem<S> {
+        self.system
+    }
+}
+
+impl<S: Symbol> FormalSystem<S> {
+    pub fn compile(self) -> CompiledSystem<S> {
+        CompiledSystem {
+            index: RuleIndex::new(&self),
+            system: self,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Instruction<S> {
+    // The next term must be an operator; its subjects are checked next
+    Operator { predicate: S, arity: usize },
+    // The same for a premise
+    Premise { premise: Premise, arity: usize },
+    // First occurrence of a variable
+    Bind(usize),
+    // Later occurrence of a variable, which must match the bound term
+    Check(usize),
+}
+
+// A judgement flattened in preorder, to be matched against many goals.
+// Matching is one way: variables of the pattern are bound to subterms of the
+// goal, while variables of the goal only match pattern variables
+#[derive(Debug, Clone)]
+pub struct CompiledPattern<S = String> {
+    pattern: Judgement<S>,
+    instructions: Vec<Instruction<S>>,
+    variables: Vec<S>,
+    // Rest variables need the alignment done by `Judgement::matches`
+    has_rest: bool,
+}
+
+impl<S: Symbol> CompiledPattern<S> {
+    pub fn new(pattern: &Judgement<S>) -> Self {
+        fn compile<S: Symbol>(
+            judgement: &Judgement<S>,
+            instructions: &mut Vec<Instruction<S>>,
+            variables: &mut Vec<S>,
+        ) {
+            match judgement {
+                Judgement::Operator {
+                    predicate,
+                    subjects,
+                } => {
+                    instructions.push(Instruction::Operator {
+                        predicate: predicate.clone(),
+                        arity: subjects.len(),
+                    });
+                    for subject in subjects {
+                        compile(subject, instructions, variables);
+                    }
+                }
+                Judgement::Premise(premise, subjects) => {
+                    instructions.push(Instruction::Premise {
+                        premise: *premise,
+                        arity: subjects.len(),
+                    });
+                    for subject in subjects {
+                        compile(subject, instructions, variables);
+                    }
+                }
+                Judgement::Variable(symbol, _) | Judgement::Rest(symbol) => {
+                    match variables.iter().position(|variable| variable == symbol) {
+                        Some(slot) => instructions.push(Instruction::Check(slot)),
+                        None => {
+                            instructions.push(Instruction::Bind(variables.len()));
+                            variables.push(symbol.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut instructions: Vec<Instruction<S>> = vec![];
+        let mut variables: Vec<S> = vec![];
+        compile(pattern, &mut instructions, &mut variables);
+
+        Self {
+            pattern: pattern.clone(),
+            instructions,
+            variables,
+            has_rest: Self::contains_rest(pattern),
+        }
+    }
+
+    fn contains_rest(judgement: &Judgement<S>) -> bool {
+        match judgement {
+            Judgement::Rest(_) => true,
+            Judgement::Variable(..) => false,
+            Judgement::Operator { subjects, .. } | Judgement::Premise(_, subjects) => {
+                subjects.iter().any(|subject| Self::contains_rest(subject))
+            }
+        }
+    }
+
+    pub fn pattern(&self) -> &Judgement<S> {
+        &self.pattern
+    }
+
+    pub fn match_against(&self, goal: &Judgement<S>) -> Option<UnificationTable<S>> {
+        if self.has_rest {
+            return self.pattern.matches(goal);
+        }
+
+        let mut terms: Vec<&Judgement<S>> = vec![goal];
+        let mut bindings: Vec<Option<&Judgement<S>>> = vec![None; self.variables.len()];
+
+        for instruction in &self.instructions {
+            let term = terms.pop()?;
+            match instruction {
+                Instruction::Operator { predicate, arity } => match term {
+                    Judgement::Operator {
+                        predicate: goal_predicate,
+                        subjects,
+                    } if goal_predicate == predicate && subjects.len() == *arity => {
+                        terms.extend(subjects.iter().rev().map(|subject| subject.as_ref()));
+                    }
+                    _ => return None,
+                },
+                Instruction::Premise { premise, arity } => match term {
+                    Judgement::Premise(goal_premise, subjects)
+                        if goal_premise == premise && subjects.len() == *arity =>
+                    {
+                        terms.extend(subjects.iter().rev().map(|subject| subject.as_ref()));
+                    }
+                    _ => return None,
+                },
+                Instruction::Bind(slot) => bindings[*slot] = Some(term),
+                Instruction::Check(slot) => {
+                    if bindings[*slot] != Some(term) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(
+            self.variables
+                .iter()
+                .cloned()
+                .zip(bindings.into_iter().map(|binding| binding.unwrap().clone()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atom, op, var};
+
+    #[test]
+    fn pattern_matching() {
+        let s2 = op!(
+            "sum",
+            var("n"),
+            op!("succ", var("m")),
+            op!("succ", var("p"))
+        );
+        let pattern = CompiledPattern::new(&s2);
+
+        let goal = op!(
+            "sum",
+            atom("zero"),
+            op!("succ", atom("zero")),
+            op!("succ", atom("zero"))
+        );
+        assert_eq!(pattern.match_against(&goal), s2.unify(&goal).ok());
+
+        // Variables of the goal are bound like any other subterm
+        let goal = op!(
+            "sum",
+            atom("zero"),
+            op!("succ", atom("zero")),
+            op!("succ", var("x"))
+        );
+        let matched = pattern.match_against(&goal).unwrap();
+        assert_eq!(matched.get("p"), Some(&var("x")));
+        assert_eq!(s2.apply_substitution(&matched), goal);
+        assert_eq!(
+            pattern.match_against(&op!("sum", atom("zero"), atom("zero"), atom("zero"))),
+            None
+        );
+        assert_eq!(pattern.match_against(&op!("sum", atom("zero"))), None);
+
+        let repeated = CompiledPattern::new(&op!("pair", var("x"), var("x")));
+        assert!(repeated
+            .match_against(&op!("pair", atom("a"), atom("a")))
+            .is_some());
+        assert!(repeated
+            .match_against(&op!("pair", atom("a"), atom("b")))
+            .is_none());
+
+        let rest = CompiledPattern::new(&op!("ctx", atom("a"), crate::rest("xs")));
+        assert!(rest
+            .match_against(&op!("ctx", atom("a"), atom("b"), atom("c")))
+            .is_some());
+        // Matching stays one way with rest variables: the goal is not bound
+        assert!(rest
+            .match_against(&op!("ctx", var("x"), atom("b")))
+            .is_none());
+    }
+
+    #[test]
+    fn compiled_matches_raw() {
+        use crate::fixtures::{nat_rules, rules, succ, sum_rules, zero};
+
+        let axioms = rules(&[nat_rules, sum_rules]);
+        let raw = FormalSystem::new(axioms.clone(), 8);
+        let compiled = FormalSystem::new(axioms, 8).compile();
+
+        for goal in [
+            op!("nat", succ(succ(zero()))),
+            op!("sum", succ(zero()), succ(zero()), succ(succ(zero()))),
+            op!("sum", succ(zero()), var("x"), succ(succ(succ(zero())))),
+            op!("sum", zero(), succ(zero()), zero()),
+            op!("max", zero(), zero(), zero()),
+            var("x"),
+        ] {
+            assert_eq!(
+                raw.verify(&goal).map(|proof| proof.to_string_tree()),
+                compiled.verify(&goal).map(|proof| proof.to_string_tree())
+            );
+        }
+    }
+}
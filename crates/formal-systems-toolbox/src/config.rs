@@ -0,0 +1,301 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::parser::{parse_complete, predicate, ws, ParseError};
+use super::{FormalSystem, Rule, DEFAULT_MAX_DERIVATION_HEIGHT};
+
+// A formal system as structured data, the way tools generating rule sets tend
+// to emit it. Judgements are kept as text, written as in a rules file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_derivation_height: Option<u16>,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    #[serde(default)]
+    pub premises: Vec<String>,
+    pub conclusion: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    // The document is malformed, or does not describe a system
+    Document(ParseError),
+    // A premise or the conclusion of a rule is not a judgement; the position is
+    // relative to the judgement text
+    Judgement { rule: String, error: ParseError },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Document(error) => write!(f, "{}", error),
+            ConfigError::Judgement { rule, error } => write!(f, "In rule {}: {}", rule, error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ParseError> for ConfigError {
+    fn from(error: ParseError) -> Self {
+        ConfigError::Document(error)
+    }
+}
+
+impl SystemConfig {
+    pub fn from_toml(input: &str) -> Result<Self, ParseError> {
+        toml::from_str(input).map_err(|error| {
+            let start = error.span().map_or(0, |span| span.start);
+            ParseError::at(input, &input[start..], error.message())
+        })
+    }
+
+    pub fn from_yaml(input: &str) -> Result<Self, ParseError> {
+        // An empty document is an empty system, as in TOML
+        if input.trim().is_empty() {
+            return Ok(SystemConfig::default());
+        }
+        serde_yaml::from_str(input).map_err(|error| {
+            let (line, column) = error
+                .location()
+                .map_or((1, 1), |location| (location.line(), location.column()));
+            // The message of the error ends with the same location
+            let message = error.to_string();
+            let suffix = format!(" at line {} column {}", line, column);
+            ParseError {
+                line,
+                column,
+                message: String::from(message.strip_suffix(&suffix).unwrap_or(&message)),
+            }
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("a system config is always valid TOML")
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("a system config is always valid YAML")
+    }
+}
+
+impl RuleConfig {
+    fn to_rule(&self) -> Result<Rule, ConfigError> {
+        let judgement = |text: &str| {
+            parse_complete(ws(predicate), text, "expected a judgement").map_err(|error| {
+                ConfigError::Judgement {
+                    rule: self.name.clone(),
+                    error,
+                }
+            })
+        };
+        Ok(Rule::new(
+            &self.name,
+            self.premises
+                .iter()
+                .map(|premise| judgement(premise))
+                .collect::<Result<_, _>>()?,
+            judgement(&self.conclusion)?,
+        ))
+    }
+}
+
+impl FormalSystem {
+    // Each judgement must be an operator, as parsed by `parser::predicate`
+    pub fn from_config(config: &SystemConfig) -> Result<Self, ConfigError> {
+        Ok(FormalSystem::new(
+            config
+                .rules
+                .iter()
+                .map(RuleConfig::to_rule)
+                .collect::<Result<_, _>>()?,
+            config
+                .max_derivation_height
+                .unwrap_or(DEFAULT_MAX_DERIVATION_HEIGHT),
+        ))
+    }
+
+    pub fn from_toml(input: &str) -> Result<Self, ConfigError> {
+        Self::from_config(&SystemConfig::from_toml(input)?)
+    }
+
+    pub fn from_yaml(input: &str) -> Result<Self, ConfigError> {
+        Self::from_config(&SystemConfig::from_yaml(input)?)
+    }
+
+    // Only the rules and the height limit are kept, and premise labels and
+    // sorts are dropped
+    pub fn to_config(&self) -> SystemConfig {
+        SystemConfig {
+            max_derivation_height: Some(self.max_derivation_height),
+            rules: self
+                .axioms
+                .iter()
+                .map(|axiom| RuleConfig {
+                    name: axiom.name.clone(),
+                    premises: axiom
+                        .premises
+                        .iter()
+                        .map(|premise| premise.to_string())
+                        .collect(),
+                    conclusion: axiom.conclusion.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atom, op, Judgement};
+
+    fn nat() -> FormalSystem {
+        FormalSystem::try_from(
+            "
+------ zero
+nat(zero())
+
+nat(n)
+------------ succ
+nat(succ(n))
+
+------------------ s1
+sum(n, zero(), n)
+
+sum(n, m, p)
+------------------------------ s2
+sum(n, succ(m), succ(p))
+",
+        )
+        .unwrap()
+        .with_max_derivation_height(8)
+    }
+
+    fn rules(system: &FormalSystem) -> Vec<String> {
+        system.axioms.iter().map(Rule::to_string).collect()
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let toml = nat().to_config().to_toml();
+        assert_eq!(
+            toml.lines().take(7).collect::<Vec<&str>>(),
+            vec![
+                "max_derivation_height = 8",
+                "",
+                "[[rules]]",
+                "name = \"zero\"",
+                "premises = []",
+                "conclusion = \"nat(zero())\"",
+                "",
+            ]
+        );
+
+        let system = FormalSystem::from_toml(&toml).unwrap();
+        assert_eq!(rules(&system), rules(&nat()));
+        assert_eq!(system.to_config(), nat().to_config());
+        assert!(system
+            .verify(&op!("nat", op!("succ", op!("succ", atom("zero")))))
+            .is_some());
+
+        let handwritten = FormalSystem::from_toml(
+            r#"
+# Natural numbers
+max_derivation_height = 8
+
+[[rules]]
+name = "zero"
+conclusion = "nat(zero())"
+
+[[rules]]
+name = 'succ'
+premises = [
+    "nat(n)", # the predecessor
+]
+conclusion = "nat(succ(n))"
+"#,
+        )
+        .unwrap();
+        assert_eq!(rules(&handwritten), rules(&nat())[..2]);
+    }
+
+    #[test]
+    fn yaml_systems() {
+        let yaml = nat().to_config().to_yaml();
+        assert_eq!(
+            FormalSystem::from_yaml(&yaml).unwrap().to_config(),
+            nat().to_config()
+        );
+
+        let handwritten = FormalSystem::from_yaml(
+            "
+max_derivation_height: 8
+rules:
+- name: zero
+  conclusion: nat(zero())
+- name: succ
+  premises:
+  - nat(n)  # the predecessor
+  conclusion: nat(succ(n))
+- name: s1
+  premises: []
+  conclusion: 'sum(n, zero(), n)'
+- name: s2
+  premises: ['sum(n, m, p)']
+  conclusion: \"sum(n, succ(m), succ(p))\"
+",
+        )
+        .unwrap();
+        assert_eq!(rules(&handwritten), rules(&nat()));
+    }
+
+    #[test]
+    fn config_errors() {
+        assert_eq!(
+            FormalSystem::from_toml("[[rules]]\nname = \"zero\"\nconclusion = \"zero\"\n").err(),
+            Some(ConfigError::Judgement {
+                rule: String::from("zero"),
+                error: ParseError {
+                    line: 1,
+                    column: 5,
+                    message: String::from("expected a judgement"),
+                },
+            })
+        );
+
+        let located = |error: ParseError| (error.line, error.column);
+        assert_eq!(
+            located(SystemConfig::from_toml("\n[[rules]]\nnam = \"zero\"\n").unwrap_err()),
+            (3, 1)
+        );
+        assert_eq!(
+            SystemConfig::from_toml("[[rules]]\nconclusion = \"nat(zero())\"\n")
+                .unwrap_err()
+                .message,
+            "missing field `name`"
+        );
+        assert_eq!(
+            located(SystemConfig::from_toml("max_derivation_height = \"high\"").unwrap_err()),
+            (1, 25)
+        );
+        assert_eq!(
+            located(SystemConfig::from_toml("[[rules]]\nname = zero\n").unwrap_err()),
+            (2, 8)
+        );
+        let error =
+            SystemConfig::from_yaml("rules:\n  - name: zero\n     conclusion: nat(zero())\n")
+                .unwrap_err();
+        assert_eq!(located(error.clone()), (3, 16));
+        assert!(!error.message.contains("line"));
+    }
+}
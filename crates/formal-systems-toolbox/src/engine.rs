@@ -0,0 +1,1687 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use super::compiled::RuleIndex;
+use super::report::{Contradiction, FailedGoal, PartialDerivation};
+use super::{
+    premise_orders, Completeness, Derivation, FormalSystem, Judgement, LoopCheck, Provenance, Rule,
+    SearchStrategy, Symbol, UnificationTable, CANCELLATION_CHECK_INTERVAL,
+};
+
+// Given a goal and the rules applicable to it, the indices of those to try
+pub(crate) type Selector<'a, S = String> = dyn FnMut(&Judgement<S>, &[Rule<S>]) -> Vec<usize> + 'a;
+
+// A derivation found when enumerating, with the bindings it makes
+pub(crate) type Solution<S = String> = (Derivation<S>, UnificationTable<S>);
+
+// The state of a proof search. Open goals live on an explicit stack instead of
+// the call stack, so the height of a derivation is not bounded by the stack
+// and the search can be stopped between any two steps. Terms are still
+// unified and substituted recursively. The state borrows the system and may
+// hold a selector closure, so it is resumed in place rather than serialized
+pub(crate) struct Search<'a, S = String> {
+    index: Option<&'a RuleIndex<S>>,
+    // Rules tried instead of the axioms of the system
+    pub(crate) rules: Option<&'a [Rule<S>]>,
+    // Goals being proved, innermost last
+    goals: Vec<Goal<'a, S>>,
+    bin: FailureCache,
+    // Goals enclosing the current one, for `LoopCheck::PerBranch`
+    branch: Vec<String>,
+    // Number of enclosing goals for each predicate on the current branch
+    predicate_heights: HashMap<S, u16>,
+    // Conditions on the bindings of the current branch
+    pending: Pending<S>,
+    pub(crate) cancellation: Option<&'a AtomicBool>,
+    pub(crate) cancelled: bool,
+    // Whether ground premises may be proved apart from the enclosing goals
+    pub(crate) ground_fast_path: bool,
+    pub(crate) trace: Option<Trace<S>>,
+    // Whether some goal was given up because of a height limit or cancellation
+    pub(crate) cut_off: bool,
+    // The height bound of the current round of iterative deepening
+    depth_limit: Option<u16>,
+    // Number of goals for which rules were left out, having been used as many
+    // times as allowed on the branch
+    pruned: usize,
+    // Number of goals given up at a height bound
+    cuts: usize,
+    // Whether derivations record the bindings made by each rule
+    pub(crate) annotate: bool,
+    // Whether proved goals keep their choice points, so that the search can be
+    // resumed for further derivations
+    pub(crate) enumerate: bool,
+    // The root goal of the last derivation found when enumerating, and the
+    // bindings it made
+    solved: Option<Goal<'a, S>>,
+    pub(crate) bindings: UnificationTable<S>,
+    // Expansions of the rules with alternative premises, by position
+    alternatives: HashMap<usize, Vec<Rule<S>>>,
+    // Chooses which of the applicable rules are tried on a goal, in order
+    pub(crate) selector: Option<&'a mut Selector<'a, S>>,
+    steps: usize,
+}
+
+// What a search went through, for reports on failed queries
+#[derive(Default)]
+pub(crate) struct Trace<S = String> {
+    pub(crate) height_reached: u16,
+    // Goals that ran out of rules at the largest height where any goal did
+    pub(crate) frontier: Vec<FailedGoal>,
+    // The enclosing derivations of the first goal to fail at that height
+    pub(crate) partial: Option<PartialDerivation<S>>,
+    // Rules that introduced the variables of the derivations completed so far
+    pub(crate) provenance: HashMap<S, Arc<Provenance>>,
+    // Bindings of the derivation found, if any
+    pub(crate) substitutions: UnificationTable<S>,
+    // Normalized goals given up on for repeating a failed or enclosing goal
+    pub(crate) looped: Vec<String>,
+    // Goals that failed for unifying with a negative fact
+    pub(crate) contradicted: Vec<Contradiction<S>>,
+    // The first goal given up at a height bound, and the first given up for
+    // repeating a failed or enclosing goal
+    pub(crate) first_cut: Option<Judgement<S>>,
+    pub(crate) first_loop: Option<Judgement<S>>,
+}
+
+impl<'a, S: Symbol> Search<'a, S> {
+    pub(crate) fn new(index: Option<&'a RuleIndex<S>>) -> Self {
+        Self {
+            index,
+            rules: None,
+            goals: vec![],
+            bin: FailureCache::default(),
+            branch: vec![],
+            predicate_heights: HashMap::new(),
+            pending: Pending::default(),
+            cancellation: None,
+            cancelled: false,
+            ground_fast_path: true,
+            trace: None,
+            cut_off: false,
+            depth_limit: None,
+            pruned: 0,
+            cuts: 0,
+            annotate: false,
+            enumerate: false,
+            solved: None,
+            bindings: UnificationTable::new(),
+            alternatives: HashMap::new(),
+            selector: None,
+            steps: 0,
+        }
+    }
+
+    // Gives up on a goal for exceeding a height bound
+    fn cut(&mut self, judgement: &Judgement<S>, substitutions: &UnificationTable<S>) {
+        self.cut_off = true;
+        self.cuts += 1;
+        if let Some(trace) = &mut self.trace {
+            trace
+                .first_cut
+                .get_or_insert_with(|| judgement.apply_substitution(substitutions));
+        }
+    }
+
+    // Keeps a goal proved when enumerating, to be reopened for its other
+    // derivations
+    fn keep(&mut self, goal: Goal<'a, S>) {
+        let Some(parent) = self.goals.last_mut() else {
+            self.solved = Some(goal);
+            return;
+        };
+        parent.attempt.frames.push(Frame {
+            goal,
+            premise: parent.attempt.premise,
+            proofs: parent.attempt.proofs.len(),
+            substitutions: parent.attempt.substitutions.clone(),
+            disequalities: self.pending.disequalities.len(),
+            constraints: self.pending.constraints.len(),
+        });
+    }
+
+    fn reopen(&mut self, goal: Goal<'a, S>) {
+        if let Some((predicate, height)) = &goal.predicate_height {
+            self.predicate_heights.insert(predicate.clone(), height + 1);
+        }
+        self.goals.push(goal);
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.steps += 1;
+        if let Some(cancellation) = self.cancellation {
+            if !self.cancelled && self.steps.is_multiple_of(CANCELLATION_CHECK_INTERVAL) {
+                self.cancelled = cancellation.load(Ordering::Relaxed);
+            }
+        }
+        self.cancelled
+    }
+}
+
+// Where a failed goal is known to fail again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    Always,
+    // Having run into a height bound, only where it is met at least as high
+    From(u16),
+}
+
+impl Failure {
+    fn holds_at(self, height: u16) -> bool {
+        match self {
+            Failure::Always => true,
+            Failure::From(failed_from) => height >= failed_from,
+        }
+    }
+
+    fn merge(self, other: Failure) -> Failure {
+        match (self, other) {
+            (Failure::From(left), Failure::From(right)) => Failure::From(left.min(right)),
+            _ => Failure::Always,
+        }
+    }
+}
+
+// Normalized goals known to fail. With a capacity, the least recently used ones
+// are forgotten first
+#[derive(Default)]
+struct FailureCache {
+    capacity: Option<usize>,
+    // Each goal with the time it was last inserted or found
+    entries: HashMap<String, (u64, Failure)>,
+    // The same goals ordered by time, only kept when there is a capacity
+    uses: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl FailureCache {
+    // How `goal` is known to fail, if it is at `height`
+    fn find(&mut self, goal: &str, height: u16) -> Option<Failure> {
+        let (used, failure) = self.entries.get_mut(goal)?;
+        if !failure.holds_at(height) {
+            return None;
+        }
+        if self.capacity.is_some() {
+            self.clock += 1;
+            let goal = self.uses.remove(used).unwrap();
+            *used = self.clock;
+            self.uses.insert(self.clock, goal);
+        }
+        Some(*failure)
+    }
+
+    fn insert(&mut self, goal: String, failure: Failure) {
+        let failure = match self.entries.get(&goal) {
+            Some((_, known)) => known.merge(failure),
+            None => failure,
+        };
+        let Some(capacity) = self.capacity else {
+            self.entries.insert(goal, (0, failure));
+            return;
+        };
+        if capacity == 0 {
+            return;
+        }
+
+        self.clock += 1;
+        if let Some((used, _)) = self.entries.insert(goal.clone(), (self.clock, failure)) {
+            self.uses.remove(&used);
+        }
+        self.uses.insert(self.clock, goal);
+        if self.entries.len() > capacity {
+            let (_, oldest) = self.uses.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+// A goal with its choice points: the rules whose conclusion unifies with it and,
+// for the rule being tried, the orders in which its premises can be proved.
+// Once a premise is proved its own choice points are dropped, so each premise
+// is committed to its first derivation
+struct Goal<'a, S> {
+    judgement: Judgement<S>,
+    height: u16,
+    normalized: String,
+    // Predicate counted on entry, with the height to restore on exit
+    predicate_height: Option<(S, u16)>,
+    // Proved with its own substitutions, which are applied to its derivation and
+    // dropped on exit
+    isolated: bool,
+    // Values of `Search::pruned` and `Search::cuts` on entry
+    pruned: usize,
+    cuts: usize,
+    // Variables of the goal still unbound on entry, with `Search::annotate`
+    open: Vec<S>,
+    paths: Vec<(UnificationTable<S>, usize, Rule<S>)>,
+    path: usize,
+    orders: Cow<'a, [Vec<usize>]>,
+    order: usize,
+    attempt: Attempt<'a, S>,
+}
+
+// A premise proved when enumerating, kept with its choice points along with
+// what its parent goal looked like when the premise was entered
+struct Frame<'a, S> {
+    goal: Goal<'a, S>,
+    premise: usize,
+    proofs: usize,
+    substitutions: UnificationTable<S>,
+    // Pending disequalities and constraints once the premise was proved
+    disequalities: usize,
+    constraints: usize,
+}
+
+// Premises that are not proved by rules but kept to be checked against the
+// bindings made as the branch grows
+pub(crate) struct Pending<S = String> {
+    // Disequalities that could not be decided yet
+    disequalities: Vec<(Judgement<S>, Judgement<S>)>,
+    // Premises with a constraint predicate, for the constraint solver
+    constraints: Vec<Judgement<S>>,
+}
+
+impl<S> Default for Pending<S> {
+    fn default() -> Self {
+        Self {
+            disequalities: vec![],
+            constraints: vec![],
+        }
+    }
+}
+
+impl<S: Symbol> Pending<S> {
+    fn is_empty(&self) -> bool {
+        self.disequalities.is_empty() && self.constraints.is_empty()
+    }
+
+    // The variables the pending premises still mention, which no rule may be
+    // renamed into while they wait
+    pub(crate) fn variables(&self) -> impl Iterator<Item = S> + '_ {
+        self.disequalities
+            .iter()
+            .flat_map(|(left, right)| [left, right])
+            .chain(&self.constraints)
+            .flat_map(Judgement::get_variables)
+    }
+}
+
+// Progress through the premises of the current rule, in the current order
+struct Attempt<'a, S> {
+    substitutions: UnificationTable<S>,
+    proofs: Vec<Derivation<S>>,
+    // Premises proved so far that may have other derivations, when enumerating
+    frames: Vec<Frame<'a, S>>,
+    // Whether some proof still refers to variables bound in `substitutions`
+    unresolved: bool,
+    premise: usize,
+    // Pending disequalities and constraints when the attempt started
+    disequalities: usize,
+    constraints: usize,
+}
+
+impl<S> Default for Attempt<'_, S> {
+    fn default() -> Self {
+        Self {
+            substitutions: UnificationTable::new(),
+            proofs: vec![],
+            frames: vec![],
+            unresolved: false,
+            premise: 0,
+            disequalities: 0,
+            constraints: 0,
+        }
+    }
+}
+
+impl<S: Symbol> Attempt<'_, S> {
+    fn new(substitutions: &UnificationTable<S>, pending: &Pending<S>) -> Self {
+        Self {
+            substitutions: substitutions.clone(),
+            proofs: vec![],
+            frames: vec![],
+            unresolved: false,
+            premise: 0,
+            disequalities: pending.disequalities.len(),
+            constraints: pending.constraints.len(),
+        }
+    }
+}
+
+impl<S: Symbol> Trace<S> {
+    fn record_provenance(&mut self, judgement: &Judgement<S>) {
+        match judgement {
+            Judgement::Variable(symbol, Some(provenance)) => {
+                self.provenance.insert(symbol.clone(), provenance.clone());
+            }
+            Judgement::Operator { subjects, .. } => {
+                for subject in subjects {
+                    self.record_provenance(subject);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn record(&mut self, goal: &Goal<S>) {
+        match self.frontier.first() {
+            Some(failed) if failed.height > goal.height => return,
+            Some(failed) if failed.height < goal.height => self.frontier.clear(),
+            _ => {}
+        }
+        if self
+            .frontier
+            .iter()
+            .all(|failed| failed.goal != goal.normalized)
+        {
+            self.frontier.push(FailedGoal {
+                goal: goal.normalized.clone(),
+                height: goal.height,
+                rules: goal
+                    .paths
+                    .iter()
+                    .map(|(_, _, rule)| rule.name.clone())
+                    .collect(),
+            });
+        }
+    }
+}
+
+enum Step<S> {
+    // The innermost goal has work left
+    Pending,
+    // Substitutions are missing when the derivation is already resolved
+    Proved(Derivation<S>, Option<UnificationTable<S>>),
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Disequality {
+    // Both sides are already the same term
+    Violated,
+    // Both sides could still become equal
+    Suspended,
+    // Both sides can never be equal
+    Entailed,
+}
+
+impl<S: Symbol> FormalSystem<S> {
+    pub(crate) fn search(
+        &self,
+        search: &mut Search<S>,
+        judgement: &Judgement<S>,
+    ) -> Option<Derivation<S>> {
+        if self.strategy == SearchStrategy::IterativeDeepening {
+            for depth_limit in 0..=self.max_derivation_height {
+                // Failures at a lower bound may not be failures at this one
+                search.bin = FailureCache::default();
+                search.cut_off = false;
+                search.depth_limit = Some(depth_limit);
+                let proof = self.search_bounded(search, judgement);
+                // A round that gave up on no goal at its bound is final
+                if proof.is_some() || !search.cut_off || search.cancelled {
+                    return proof;
+                }
+            }
+            return None;
+        }
+        self.search_bounded(search, judgement)
+    }
+
+    fn search_bounded(
+        &self,
+        search: &mut Search<S>,
+        judgement: &Judgement<S>,
+    ) -> Option<Derivation<S>> {
+        search.ground_fast_path &=
+            self.ground_fast_path && preserves_groundness(search.rules.unwrap_or(&self.axioms));
+        search.bin.capacity = self.failure_cache_capacity;
+
+        let step = self.enter(search, &UnificationTable::new(), judgement, 0, false);
+        self.run(search, step)
+    }
+
+    // The next derivation of the goal of an enumerating search, after the
+    // last one found
+    pub(crate) fn resume(&self, search: &mut Search<S>) -> Option<Derivation<S>> {
+        let goal = search.solved.take()?;
+        search.reopen(goal);
+        self.fail(search);
+        self.run(search, Step::Pending)
+    }
+
+    fn run(&self, search: &mut Search<S>, mut step: Step<S>) -> Option<Derivation<S>> {
+        loop {
+            step = match step {
+                Step::Pending => self.advance(search),
+                Step::Proved(proof, substitutions) => {
+                    let Some(goal) = search.goals.last_mut() else {
+                        if let Some(substitutions) = &substitutions {
+                            if let Some(trace) = &mut search.trace {
+                                trace.substitutions = substitutions.clone();
+                            }
+                            if search.enumerate {
+                                search.bindings = substitutions.clone();
+                            }
+                        }
+                        return Some(match substitutions {
+                            Some(substitutions) => proof.apply_substitution(&substitutions),
+                            None => proof,
+                        });
+                    };
+                    if let Some(substitutions) = substitutions {
+                        goal.attempt.substitutions.extend(substitutions);
+                        goal.attempt.unresolved = true;
+                    }
+                    goal.attempt.proofs.push(proof);
+                    if self.pending_hold(&search.pending, &goal.attempt.substitutions)
+                        && self.sorts_hold(&goal.paths[goal.path].2, &goal.attempt.substitutions)
+                    {
+                        goal.attempt.premise += 1;
+                    } else {
+                        self.fail(search);
+                    }
+                    Step::Pending
+                }
+                Step::Failed => {
+                    if search.goals.is_empty() {
+                        return None;
+                    }
+                    self.fail(search);
+                    Step::Pending
+                }
+            };
+        }
+    }
+
+    // When every rule variable occurs in its conclusion, a derivation of a
+    // ground judgement is ground too and cannot bind anything outside it
+    pub(crate) fn preserves_groundness(&self) -> bool {
+        preserves_groundness(&self.axioms)
+    }
+
+    // Gives up on a goal for exceeding a height bound. When enumerating, the
+    // derivations found are only incomplete if some rule concludes the goal
+    fn cut(
+        &self,
+        search: &mut Search<S>,
+        judgement: &Judgement<S>,
+        substitutions: &UnificationTable<S>,
+    ) {
+        if search.enumerate
+            && self
+                .get_possible_derivation_paths(
+                    search.rules.unwrap_or(&self.axioms),
+                    search.index,
+                    &mut search.alternatives,
+                    substitutions,
+                    &search.pending,
+                    judgement,
+                    false,
+                )
+                .is_empty()
+        {
+            return;
+        }
+        search.cut(judgement, substitutions);
+    }
+
+    // Opens a new goal, unless it is out of bounds or known to fail
+    fn enter(
+        &self,
+        search: &mut Search<S>,
+        substitutions: &UnificationTable<S>,
+        judgement: &Judgement<S>,
+        height: u16,
+        isolated: bool,
+    ) -> Step<S> {
+        if search.should_stop() {
+            search.cut_off = true;
+            return Step::Failed;
+        }
+
+        if !self.negative_facts.is_empty() {
+            let goal = judgement.apply_substitution(substitutions);
+            if let Some(fact) = self
+                .negative_facts
+                .iter()
+                .find(|fact| fact.rename_apart(&goal).unify(&goal).is_ok())
+            {
+                if let Some(trace) = &mut search.trace {
+                    let contradiction = Contradiction {
+                        goal,
+                        fact: fact.clone(),
+                    };
+                    if !trace.contradicted.contains(&contradiction) {
+                        trace.contradicted.push(contradiction);
+                    }
+                }
+                return Step::Failed;
+            }
+        }
+
+        if let Judgement::Operator {
+            predicate,
+            subjects,
+        } = judgement.resolve(substitutions)
+        {
+            if let [left, right] = subjects.as_slice() {
+                if self.equality.contains(predicate) {
+                    return self.reflexivity(judgement, left, right, substitutions);
+                }
+            }
+        }
+
+        if height > search.depth_limit.unwrap_or(self.max_derivation_height) {
+            self.cut(search, judgement, substitutions);
+            return Step::Failed;
+        }
+        let predicate_height = match judgement.resolve(substitutions) {
+            Judgement::Operator { predicate, .. } if !self.predicate_heights.is_empty() => {
+                let limit = self
+                    .predicate_heights
+                    .get(predicate)
+                    .copied()
+                    .unwrap_or(self.max_derivation_height);
+                let predicate_height = search
+                    .predicate_heights
+                    .get(predicate)
+                    .copied()
+                    .unwrap_or(0);
+                if predicate_height > limit {
+                    self.cut(search, judgement, substitutions);
+                    return Step::Failed;
+                }
+
+                search
+                    .predicate_heights
+                    .insert(predicate.clone(), predicate_height + 1);
+                Some((predicate.clone(), predicate_height))
+            }
+            _ => None,
+        };
+
+        let normalized = judgement
+            .apply_substitution(substitutions)
+            .rename_variables(&mut HashMap::<S, S>::new(), &|state, symbol| {
+                if let Some(new_symbol) = state.get(&symbol) {
+                    new_symbol.clone()
+                } else {
+                    let counter = state.len() + 1;
+                    let new_symbol = S::from(&format!("x{}", counter));
+                    state.insert(symbol, new_symbol.clone());
+                    new_symbol
+                }
+            })
+            .to_string();
+
+        // Other derivations of a repeated goal are still wanted when enumerating
+        let repeated = !search.enumerate
+            && match self.loop_check {
+                LoopCheck::Global => match search.bin.find(&normalized, height) {
+                    // Failing at a height bound makes this goal fail at one too
+                    Some(Failure::From(_)) => {
+                        self.cut(search, judgement, substitutions);
+                        true
+                    }
+                    found => found.is_some(),
+                },
+                LoopCheck::PerBranch => search.branch.contains(&normalized),
+            };
+        if repeated {
+            if let Some(trace) = &mut search.trace {
+                trace
+                    .first_loop
+                    .get_or_insert_with(|| judgement.apply_substitution(substitutions));
+                if !trace.looped.contains(&normalized) {
+                    trace.looped.push(normalized);
+                }
+            }
+            if let Some((predicate, height)) = predicate_height {
+                search.predicate_heights.insert(predicate, height);
+            }
+            return Step::Failed;
+        }
+        if self.loop_check == LoopCheck::PerBranch && !search.enumerate {
+            search.branch.push(normalized.clone());
+        }
+
+        let mut paths = self.get_possible_derivation_paths(
+            search.rules.unwrap_or(&self.axioms),
+            search.index,
+            &mut search.alternatives,
+            substitutions,
+            &search.pending,
+            judgement,
+            search.trace.is_some(),
+        );
+        let pruned = search.pruned;
+        let candidates = paths.len();
+        paths.retain(|(_, position, rule)| {
+            rule.max_uses.is_none_or(|max_uses| {
+                let uses = search
+                    .goals
+                    .iter()
+                    .filter(|goal| {
+                        goal.paths
+                            .get(goal.path)
+                            .is_some_and(|(_, used, _)| used == position)
+                    })
+                    .count();
+                uses < max_uses
+            })
+        });
+        if paths.len() < candidates {
+            search.pruned += 1;
+        }
+        // Rules after a committed one are never tried, while every way of
+        // applying the committed rule itself still is
+        if let Some(committed) = paths.iter().position(|(_, _, rule)| rule.committed) {
+            let position = paths[committed].1;
+            let last = paths
+                .iter()
+                .rposition(|(_, used, _)| *used == position)
+                .unwrap();
+            paths.truncate(last + 1);
+        }
+        // A goal failing for rules the selector left out may hold when it
+        // selects differently, so it is not remembered as a failure
+        if let Some(selector) = &mut search.selector {
+            let rules: Vec<Rule<S>> = paths.iter().map(|(_, _, rule)| rule.clone()).collect();
+            let selected = selector(&judgement.apply_substitution(substitutions), &rules);
+            let candidates = paths.len();
+            paths = selected
+                .into_iter()
+                .filter_map(|i| paths.get(i).cloned())
+                .collect();
+            if paths.len() < candidates {
+                search.pruned += 1;
+            }
+        }
+
+        let mut goal = Goal {
+            judgement: judgement.clone(),
+            height,
+            normalized,
+            predicate_height,
+            isolated,
+            pruned,
+            cuts: search.cuts,
+            open: if search.annotate {
+                judgement
+                    .apply_substitution(substitutions)
+                    .get_variables()
+                    .into_iter()
+                    .sorted()
+                    .collect()
+            } else {
+                vec![]
+            },
+            paths,
+            path: 0,
+            orders: Cow::Owned(vec![]),
+            order: 0,
+            attempt: Attempt::default(),
+        };
+        self.start_path(&mut goal, search.index, &search.pending, search.enumerate);
+        if let Some(trace) = &mut search.trace {
+            trace.height_reached = trace.height_reached.max(height);
+        }
+        search.goals.push(goal);
+
+        Step::Pending
+    }
+
+    // Works on the innermost goal until it is proved, fails, or needs a premise
+    // to be proved first
+    fn advance(&self, search: &mut Search<S>) -> Step<S> {
+        loop {
+            let parent = search.goals.len() - 1;
+            let goal = &mut search.goals[parent];
+
+            let Some((_, _, rule)) = goal.paths.get(goal.path) else {
+                let goal = search.goals.pop().unwrap();
+                if let Some(trace) = &search.trace {
+                    let deeper = trace
+                        .frontier
+                        .first()
+                        .is_none_or(|failed| failed.height < goal.height);
+                    let partial = deeper.then(|| self.partial_derivation(&search.goals, &goal));
+                    let trace = search.trace.as_mut().unwrap();
+                    trace.record(&goal);
+                    if partial.is_some() {
+                        trace.partial = partial;
+                    }
+                }
+                // Rules left out for their uses on the branch make the failure
+                // depend on the enclosing goals. A failure that met a height
+                // bound only holds where the goal is met as high, which per
+                // predicate bounds do not tell
+                let failure = if search.pruned > goal.pruned {
+                    None
+                } else if search.cuts == goal.cuts {
+                    Some(Failure::Always)
+                } else if self.predicate_heights.is_empty() {
+                    Some(Failure::From(goal.height))
+                } else {
+                    None
+                };
+                self.leave(search, goal.normalized, goal.predicate_height, failure);
+                return Step::Failed;
+            };
+
+            let Some(&position) = goal.orders[goal.order].get(goal.attempt.premise) else {
+                if let Some(trace) = &mut search.trace {
+                    rule.premises
+                        .iter()
+                        .chain([&rule.conclusion])
+                        .for_each(|judgement| trace.record_provenance(judgement));
+                }
+                let rule_label = rule.name.clone();
+                let is_axiom = rule.premises.is_empty();
+                let (unifier, _, _) = &goal.paths[goal.path];
+                let bindings = goal
+                    .open
+                    .iter()
+                    .map(|variable| {
+                        let value = Judgement::Variable(variable.clone(), None)
+                            .apply_substitution(unifier);
+                        (variable.clone(), value)
+                    })
+                    .filter(|(variable, value)| {
+                        !matches!(value, Judgement::Variable(symbol, _) if symbol == variable)
+                    })
+                    .collect();
+                let goal = search.goals.pop().unwrap();
+                if search.enumerate {
+                    let proof = Derivation {
+                        premises: goal.attempt.proofs.clone(),
+                        conclusion: goal.judgement.clone(),
+                        rule_label,
+                        is_axiom,
+                        discharge: None,
+                        bindings,
+                    };
+                    let substitutions = goal.attempt.substitutions.clone();
+                    let normalized = goal.normalized.clone();
+                    self.leave(search, normalized, goal.predicate_height.clone(), None);
+                    search.keep(goal);
+                    return Step::Proved(proof, Some(substitutions));
+                }
+                let proof = Derivation {
+                    premises: goal.attempt.proofs,
+                    conclusion: goal.judgement,
+                    rule_label,
+                    is_axiom,
+                    discharge: None,
+                    bindings,
+                };
+                self.leave(search, goal.normalized, goal.predicate_height, None);
+                if !goal.isolated {
+                    return Step::Proved(proof, Some(goal.attempt.substitutions));
+                }
+                // The conclusion is ground, so only proofs of premises that were
+                // not themselves isolated need resolving
+                return Step::Proved(
+                    if goal.attempt.unresolved {
+                        proof.apply_substitution(&goal.attempt.substitutions)
+                    } else {
+                        proof
+                    },
+                    None,
+                );
+            };
+            let premise = rule.premises[position].clone();
+
+            if let Some((left, right)) = premise.as_disequality() {
+                match self.disequality(left, right, &goal.attempt.substitutions) {
+                    Disequality::Violated => self.fail(search),
+                    Disequality::Suspended => {
+                        search
+                            .pending
+                            .disequalities
+                            .push((left.clone(), right.clone()));
+                        goal.attempt.premise += 1;
+                    }
+                    Disequality::Entailed => goal.attempt.premise += 1,
+                }
+                continue;
+            }
+            if self.is_constraint(&premise) {
+                search.pending.constraints.push(premise);
+                if self.constraints_hold(&search.pending.constraints, &goal.attempt.substitutions) {
+                    goal.attempt.premise += 1;
+                } else {
+                    self.fail(search);
+                }
+                continue;
+            }
+
+            let height = goal.height + 1;
+            if search.ground_fast_path && search.pending.is_empty() {
+                let instance = premise.apply_substitution(&goal.attempt.substitutions);
+                if instance.is_ground() {
+                    return self.enter(search, &UnificationTable::new(), &instance, height, true);
+                }
+            }
+            let substitutions = mem::take(&mut goal.attempt.substitutions);
+            let step = self.enter(search, &substitutions, &premise, height, false);
+            search.goals[parent].attempt.substitutions = substitutions;
+            return step;
+        }
+    }
+
+    // The derivations of the enclosing goals as far as they got, with `failed`
+    // and the premises that were not tried yet left open
+    fn partial_derivation(&self, goals: &[Goal<S>], failed: &Goal<S>) -> PartialDerivation<S> {
+        let open = |conclusion: Judgement<S>| Derivation {
+            premises: vec![],
+            conclusion,
+            rule_label: String::from("open"),
+            is_axiom: false,
+            discharge: None,
+            bindings: vec![],
+        };
+
+        let conclusion = match goals.last() {
+            Some(parent) => failed
+                .judgement
+                .apply_substitution(&parent.attempt.substitutions),
+            None => failed.judgement.clone(),
+        };
+        let mut open_goals = vec![conclusion.clone()];
+        let mut derivation = open(conclusion);
+
+        for goal in goals.iter().rev() {
+            let substitutions = &goal.attempt.substitutions;
+            let (_, _, rule) = &goal.paths[goal.path];
+
+            let mut premises: Vec<Derivation<S>> = goal
+                .attempt
+                .proofs
+                .iter()
+                .map(|proof| proof.apply_substitution(substitutions))
+                .collect();
+            premises.push(derivation);
+            for &position in &goal.orders[goal.order][goal.attempt.premise + 1..] {
+                let premise = &rule.premises[position];
+                if premise.as_disequality().is_none() && !self.is_constraint(premise) {
+                    let premise = premise.apply_substitution(substitutions);
+                    open_goals.push(premise.clone());
+                    premises.push(open(premise));
+                }
+            }
+
+            derivation = Derivation {
+                premises,
+                conclusion: goal.judgement.apply_substitution(substitutions),
+                rule_label: rule.name.clone(),
+                is_axiom: false,
+                discharge: None,
+                bindings: vec![],
+            };
+        }
+
+        PartialDerivation {
+            derivation,
+            open_goals,
+        }
+    }
+
+    // A failed goal comes with where it is known to fail again, if anywhere
+    fn leave(
+        &self,
+        search: &mut Search<S>,
+        normalized: String,
+        predicate_height: Option<(S, u16)>,
+        failure: Option<Failure>,
+    ) {
+        match self.loop_check {
+            _ if search.enumerate => {}
+            // A failure caused by pending disequalities or constraints depends
+            // on the enclosing goals, so it cannot be cached
+            LoopCheck::Global => {
+                if let Some(failure) = failure.filter(|_| search.pending.is_empty()) {
+                    search.bin.insert(normalized, failure);
+                }
+            }
+            LoopCheck::PerBranch => {
+                search.branch.pop();
+            }
+        }
+
+        if let Some((predicate, height)) = predicate_height {
+            search.predicate_heights.insert(predicate, height);
+        }
+    }
+
+    // Moves to the first remaining rule compatible with the pending premises.
+    // Other premise orders only make up for committing to the first derivation
+    // of each premise, so they are left out when enumerating
+    fn start_path<'a>(
+        &self,
+        goal: &mut Goal<'a, S>,
+        index: Option<&'a RuleIndex<S>>,
+        pending: &Pending<S>,
+        enumerate: bool,
+    ) {
+        while let Some((substitutions, position, rule)) = goal.paths.get(goal.path) {
+            if self.pending_hold(pending, substitutions) && self.sorts_hold(rule, substitutions) {
+                goal.orders = match index {
+                    Some(index) => Cow::Borrowed(&index.premise_orders[*position]),
+                    None => Cow::Owned(premise_orders(rule)),
+                };
+                if enumerate {
+                    goal.orders = match mem::take(&mut goal.orders) {
+                        Cow::Borrowed(orders) => Cow::Borrowed(&orders[..1]),
+                        Cow::Owned(mut orders) => {
+                            orders.truncate(1);
+                            Cow::Owned(orders)
+                        }
+                    };
+                }
+                goal.order = 0;
+                goal.attempt = Attempt::new(substitutions, pending);
+                return;
+            }
+            goal.path += 1;
+        }
+    }
+
+    // Abandons the current attempt of the innermost goal. When enumerating, the
+    // premises proved last are given the chance of another derivation first
+    fn fail(&self, search: &mut Search<S>) {
+        loop {
+            let goal = search.goals.last_mut().unwrap();
+            let Some(frame) = goal.attempt.frames.pop() else {
+                self.retry(goal, search.index, &mut search.pending, search.enumerate);
+                return;
+            };
+            goal.attempt.premise = frame.premise;
+            goal.attempt.proofs.truncate(frame.proofs);
+            goal.attempt.substitutions = frame.substitutions;
+            search.pending.disequalities.truncate(frame.disequalities);
+            search.pending.constraints.truncate(frame.constraints);
+            search.reopen(frame.goal);
+        }
+    }
+
+    // Moves on to the next premise order, or the next rule
+    fn retry<'a>(
+        &self,
+        goal: &mut Goal<'a, S>,
+        index: Option<&'a RuleIndex<S>>,
+        pending: &mut Pending<S>,
+        enumerate: bool,
+    ) {
+        pending.disequalities.truncate(goal.attempt.disequalities);
+        pending.constraints.truncate(goal.attempt.constraints);
+
+        goal.order += 1;
+        if goal.order < goal.orders.len() {
+            goal.attempt = Attempt::new(&goal.paths[goal.path].0, pending);
+        } else {
+            goal.path += 1;
+            self.start_path(goal, index, pending, enumerate);
+        }
+    }
+
+    fn reflexivity(
+        &self,
+        judgement: &Judgement<S>,
+        left: &Judgement<S>,
+        right: &Judgement<S>,
+        substitutions: &UnificationTable<S>,
+    ) -> Step<S> {
+        let unifier = if self.commutative.is_empty() {
+            left.would_unify(right, substitutions)
+        } else {
+            left.unify_modulo_with_substitution(right, substitutions, &self.commutative)
+                .into_iter()
+                .next()
+        };
+
+        match unifier {
+            Some(unifier) => Step::Proved(
+                Derivation {
+                    premises: vec![],
+                    conclusion: judgement.clone(),
+                    rule_label: String::from("refl"),
+                    is_axiom: true,
+                    discharge: None,
+                    bindings: vec![],
+                },
+                Some(unifier),
+            ),
+            None => Step::Failed,
+        }
+    }
+
+    fn disequality(
+        &self,
+        left: &Judgement<S>,
+        right: &Judgement<S>,
+        substitutions: &UnificationTable<S>,
+    ) -> Disequality {
+        let left = left.apply_substitution(substitutions);
+        let right = right.apply_substitution(substitutions);
+
+        let unifiable = if self.commutative.is_empty() {
+            left.unify(&right).is_ok()
+        } else {
+            !left.unify_modulo(&right, &self.commutative).is_empty()
+        };
+
+        if left.eq_modulo(&right, &self.commutative) {
+            Disequality::Violated
+        } else if unifiable {
+            Disequality::Suspended
+        } else {
+            Disequality::Entailed
+        }
+    }
+
+    pub(crate) fn sorts_hold(&self, rule: &Rule<S>, substitutions: &UnificationTable<S>) -> bool {
+        let Some(sort_of) = &self.sort_of else {
+            return true;
+        };
+
+        rule.sorts.iter().all(|(variable, sort)| {
+            let Some(binding) = substitutions.get(variable) else {
+                return true;
+            };
+            let term = binding.apply_substitution(substitutions);
+            !matches!(term, Judgement::Operator { .. })
+                || sort_of(&term).is_none_or(|term_sort| term_sort == *sort)
+        })
+    }
+
+    fn pending_hold(&self, pending: &Pending<S>, substitutions: &UnificationTable<S>) -> bool {
+        self.disequalities_hold(&pending.disequalities, substitutions)
+            && self.constraints_hold(&pending.constraints, substitutions)
+    }
+
+    pub(crate) fn is_constraint(&self, judgement: &Judgement<S>) -> bool {
+        matches!(judgement, Judgement::Operator { predicate, .. } if self.constraints.contains(predicate))
+    }
+
+    // Whether the solver finds `constraints`, instantiated with the bindings
+    // made so far, consistent. Without a solver they always are
+    pub(crate) fn constraints_hold(
+        &self,
+        constraints: &[Judgement<S>],
+        substitutions: &UnificationTable<S>,
+    ) -> bool {
+        let Some(solver) = &self.constraint_solver else {
+            return true;
+        };
+        if constraints.is_empty() {
+            return true;
+        }
+        let instances: Vec<Judgement<S>> = constraints
+            .iter()
+            .map(|constraint| constraint.apply_substitution(substitutions))
+            .collect();
+        solver(&instances)
+    }
+
+    pub(crate) fn disequalities_hold(
+        &self,
+        disequalities: &[(Judgement<S>, Judgement<S>)],
+        substitutions: &UnificationTable<S>,
+    ) -> bool {
+        disequalities.iter().all(|(left, right)| {
+            self.disequality(left, right, substitutions) != Disequality::Violated
+        })
+    }
+
+    // Every derivation of `judgement` within the height bounds, each with the
+    // bindings it makes, found by resuming the search after each one. Unlike
+    // `search`, premises are not committed to their first derivation and no
+    // goal is pruned as a loop
+    pub(crate) fn solutions(&self, judgement: &Judgement<S>) -> (Vec<Solution<S>>, Completeness) {
+        let mut search = Search::new(None);
+        search.enumerate = true;
+        search.ground_fast_path = false;
+
+        let mut solutions: Vec<Solution<S>> = vec![];
+        let mut proof = self.search_bounded(&mut search, judgement);
+        while let Some(derivation) = proof {
+            solutions.push((derivation, mem::take(&mut search.bindings)));
+            proof = self.resume(&mut search);
+        }
+        let completeness = if search.cut_off {
+            Completeness::Truncated
+        } else {
+            Completeness::Exhaustive
+        };
+        (solutions, completeness)
+    }
+}
+
+fn preserves_groundness<S: Symbol>(rules: &[Rule<S>]) -> bool {
+    rules.iter().all(|rule| {
+        let bound = rule.conclusion.get_variables();
+        rule.premises
+            .iter()
+            .all(|premise| premise.get_variables().is_subset(&bound))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Failure, FailureCache, SearchStrategy};
+    use crate::fixtures::{empty, nat, nat_rules, node, succ, zero};
+    use crate::{atom, op, var, Completeness, FormalSystem, Judgement, Rule, UnificationTable};
+
+    fn goals() -> Vec<Judgement> {
+        vec![
+            op!("nat", succ(zero())),
+            op!("sum", zero(), zero(), zero()),
+            op!(
+                "sum",
+                succ(zero()),
+                succ(succ(zero())),
+                succ(succ(succ(zero())))
+            ),
+            op!("sum", succ(zero()), var("x"), succ(succ(succ(zero())))),
+            op!("max", succ(zero()), succ(succ(zero())), succ(succ(zero()))),
+            op!(
+                "max",
+                succ(succ(succ(zero()))),
+                succ(succ(zero())),
+                succ(succ(succ(zero())))
+            ),
+            op!("hgt", node(empty(), empty()), succ(zero())),
+            op!(
+                "hgt",
+                node(empty(), node(empty(), empty())),
+                succ(succ(zero()))
+            ),
+            op!("hgt", node(empty(), node(empty(), empty())), var("x")),
+            op!("hgt", var("x"), succ(succ(zero()))),
+            op!("sum", succ(zero()), succ(zero()), zero()),
+            op!("hgt", node(empty(), empty()), succ(succ(zero()))),
+        ]
+    }
+
+    // A reference search without any of the optimizations of `enter`: goals
+    // are proved left to right, trying every rule in order and backtracking
+    // over every way each premise can be proved. Reserved predicates and the
+    // other features of the system are not supported
+    fn naive_provable(
+        system: &FormalSystem,
+        goals: &[(Judgement, u16)],
+        substitutions: &UnificationTable,
+        fresh: &mut usize,
+    ) -> bool {
+        let Some(((goal, height), rest)) = goals.split_first() else {
+            return true;
+        };
+        if *height > system.max_derivation_height {
+            return false;
+        }
+        system.axioms.iter().any(|rule| {
+            *fresh += 1;
+            let suffix = format!("#{}", fresh);
+            let rule = rule.rename_variables(&mut (), &|_, symbol| symbol + &suffix);
+            let Some(substitutions) = rule.conclusion.would_unify(goal, substitutions) else {
+                return false;
+            };
+            let goals: Vec<(Judgement, u16)> = rule
+                .premises
+                .iter()
+                .map(|premise| (premise.clone(), height + 1))
+                .chain(rest.iter().cloned())
+                .collect();
+            naive_provable(system, &goals, &substitutions, fresh)
+        })
+    }
+
+    // Fails on the first goal `verify` and the naive search disagree on
+    // whether it is provable within the height bound of `system`
+    fn assert_search_complete(system: &FormalSystem, goals: &[Judgement]) {
+        for goal in goals {
+            let expected = naive_provable(
+                system,
+                &[(goal.clone(), 0)],
+                &UnificationTable::new(),
+                &mut 0,
+            );
+            assert_eq!(
+                system.verify(goal).is_some(),
+                expected,
+                "verify and the naive search disagree on {}",
+                goal
+            );
+        }
+    }
+
+    #[test]
+    fn search_completeness() {
+        let nat = nat();
+        let mut goals = goals();
+        goals.extend((0..6).map(|n| op!("nat", (0..n).fold(zero(), |n, _| succ(n)))));
+        goals.extend([
+            op!("nat", var("x")),
+            op!("nat", succ(empty())),
+            op!("tree", node(var("x"), node(empty(), var("y")))),
+            op!("sum", var("x"), var("y"), succ(succ(zero()))),
+            op!("max", var("x"), succ(zero()), zero()),
+            op!("hgt", var("x"), var("y")),
+        ]);
+        assert_search_complete(&nat, &goals);
+        assert_search_complete(&nat.clone().with_max_derivation_height(2), &goals);
+    }
+
+    #[test]
+    fn every_derivation() {
+        let nat = nat();
+        let goal = op!("max", zero(), zero(), zero());
+        let (proofs, _) = nat.verify_all(&goal);
+        assert_eq!(
+            proofs
+                .iter()
+                .map(|proof| proof.rule_label.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["max1", "max2"]
+        );
+        // Reaching the same goal again in another derivation does not hide it
+        let goal = op!("hgt", node(empty(), empty()), succ(zero()));
+        let (proofs, _) = nat.verify_all(&goal);
+        assert_eq!(proofs.len(), 2);
+        assert_ne!(
+            proofs[0].premises[2].rule_label,
+            proofs[1].premises[2].rule_label
+        );
+
+        // A repeated rule proves nothing new
+        let repeated = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", zero())),
+                Rule::taut("zero", op!("nat", zero())),
+            ],
+            4,
+        );
+        assert_eq!(repeated.verify_all(&op!("nat", zero())).0.len(), 1);
+    }
+
+    #[test]
+    fn derivation_measures() {
+        let nat = nat();
+        let proof = nat.verify(&op!("nat", zero())).unwrap();
+        assert_eq!((proof.height(), proof.rule_count()), (1, 1));
+
+        // Each `node` takes `h2` with the heights of both subtrees and their
+        // maximum, so the deeper subtree sets the height
+        let proof = nat
+            .verify(&op!(
+                "hgt",
+                node(empty(), node(empty(), empty())),
+                succ(succ(zero()))
+            ))
+            .unwrap();
+        assert_eq!(proof.height(), 3);
+        assert_eq!(proof.rule_count(), 7);
+        // Derivations found within a bound never exceed it, the search
+        // counting the conclusion as 0
+        assert!(nat
+            .with_max_derivation_height(proof.height() as u16 - 1)
+            .verify(&proof.conclusion)
+            .is_some());
+    }
+
+    #[test]
+    fn iterative_deepening() {
+        let system = FormalSystem::new(
+            vec![
+                Rule::new("far", vec![op!("step", zero())], atom("goal")),
+                Rule::taut("near", atom("goal")),
+                Rule::new(
+                    "next",
+                    vec![op!("step", succ(var("n")))],
+                    op!("step", var("n")),
+                ),
+                Rule::taut("stop", op!("step", succ(succ(succ(zero()))))),
+            ],
+            8,
+        );
+        let proof = system.verify(&atom("goal")).unwrap();
+        assert_eq!((proof.rule_label.as_str(), proof.height()), ("far", 5));
+
+        let deepening = system.with_strategy(SearchStrategy::IterativeDeepening);
+        let proof = deepening.verify(&atom("goal")).unwrap();
+        assert_eq!((proof.rule_label.as_str(), proof.height()), ("near", 1));
+        let proof = deepening.verify(&op!("step", succ(zero()))).unwrap();
+        assert_eq!(proof.height(), 3);
+
+        let nat = nat();
+        let deepening = nat
+            .clone()
+            .with_strategy(SearchStrategy::IterativeDeepening);
+        for goal in goals() {
+            let proof = nat.verify(&goal);
+            assert_eq!(
+                deepening.verify(&goal).is_some(),
+                proof.is_some(),
+                "{}",
+                goal
+            );
+        }
+    }
+
+    #[test]
+    fn deep_derivation() {
+        let nat = FormalSystem::new(nat_rules(), 1000);
+        let goal = (0..600).fold(zero(), |n, _| succ(n));
+
+        let proof = nat.verify(&op!("nat", goal.clone())).unwrap();
+        assert_eq!(proof.pretty_print().len(), 2 * 601);
+        assert_eq!((proof.height(), proof.rule_count()), (601, 601));
+
+        // Enumerating derivations goes as deep
+        let (proofs, completeness) = nat.verify_all(&op!("nat", goal));
+        assert!(proofs == vec![proof]);
+        assert_eq!(completeness, Completeness::Exhaustive);
+    }
+
+    // The expected trees were produced by the former recursive search
+    #[test]
+    fn matches_recursive_search() {
+        let nat = nat();
+        let trees: String = goals()
+            .iter()
+            .map(|goal| match nat.verify(goal) {
+                Some(proof) => proof.to_string_tree(),
+                None => format!("\nNo derivation of {}\n", goal),
+            })
+            .collect();
+
+        assert_eq!(trees, include_str!("../tests/main_goals.txt"));
+    }
+
+    #[test]
+    fn ground_fast_path() {
+        let nat = nat().with_max_derivation_height(16);
+        let numeral = |n: usize| (0..n).fold(zero(), |m, _| succ(m));
+        let mut goals = goals();
+        goals.extend([
+            op!("sum", numeral(4), numeral(3), numeral(7)),
+            op!("max", numeral(5), numeral(2), numeral(5)),
+            op!(
+                "hgt",
+                node(empty(), node(empty(), node(empty(), empty()))),
+                numeral(3)
+            ),
+            op!("sum", numeral(4), numeral(3), numeral(6)),
+        ]);
+
+        let general = nat.clone().with_ground_fast_path(false);
+        for goal in &goals {
+            assert_eq!(
+                nat.verify(goal).map(|proof| proof.to_string_tree()),
+                general.verify(goal).map(|proof| proof.to_string_tree())
+            );
+        }
+    }
+
+    #[test]
+    fn least_recently_used_failures() {
+        let mut cache = FailureCache {
+            capacity: Some(2),
+            ..Default::default()
+        };
+        cache.insert(String::from("a"), Failure::Always);
+        cache.insert(String::from("b"), Failure::Always);
+        assert!(cache.find("a", 0).is_some());
+        cache.insert(String::from("c"), Failure::Always);
+
+        assert!(cache.find("a", 0).is_some());
+        assert!(cache.find("b", 0).is_none());
+        assert!(cache.find("c", 0).is_some());
+        assert_eq!(cache.entries.len(), 2);
+
+        // A failure at a height bound only holds as high
+        cache.insert(String::from("d"), Failure::From(3));
+        assert!(cache.find("d", 2).is_none());
+        assert_eq!(cache.find("d", 3), Some(Failure::From(3)));
+    }
+
+    #[test]
+    fn bounded_failure_cache() {
+        let unbounded = nat();
+        for capacity in [0, 1, 4] {
+            let bounded = nat().with_failure_cache_capacity(capacity);
+            for goal in goals() {
+                assert_eq!(
+                    bounded.verify(&goal).map(|proof| proof.to_string_tree()),
+                    unbounded.verify(&goal).map(|proof| proof.to_string_tree())
+                );
+            }
+        }
+
+        // `s` first fails for running into the height bound through `a`, and is
+        // then met lower down through `ts`
+        let shortcut = FormalSystem::new(
+            vec![
+                Rule::new("ta", vec![op!("a")], op!("top")),
+                Rule::new("ts", vec![op!("s")], op!("top")),
+                Rule::new("ab", vec![op!("b")], op!("a")),
+                Rule::new("bs", vec![op!("s")], op!("b")),
+                Rule::new("st", vec![op!("t")], op!("s")),
+                Rule::taut("t", op!("t")),
+            ],
+            3,
+        );
+        let proof = shortcut.verify(&op!("top")).unwrap();
+        assert_eq!(proof.rule_label, "ts");
+        assert_eq!(
+            shortcut
+                .with_failure_cache_capacity(1)
+                .verify(&op!("top"))
+                .map(|proof| proof.to_string_tree()),
+            Some(proof.to_string_tree())
+        );
+    }
+
+    #[test]
+    fn alternative_premises() {
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("even_zero", op!("even", zero())),
+                Rule::taut("odd_one", op!("odd", succ(zero()))),
+                Rule::new(
+                    "parity",
+                    vec![Judgement::one_of(vec![
+                        op!("even", var("n")),
+                        op!("odd", var("n")),
+                    ])],
+                    op!("parity", var("n")),
+                ),
+            ],
+            4,
+        );
+
+        let proof = system.verify(&op!("parity", succ(zero()))).unwrap();
+        assert_eq!(proof.premises.len(), 1);
+        assert_eq!(proof.premises[0].conclusion, op!("odd", succ(zero())));
+        assert_eq!(proof.premises[0].rule_label, "odd_one");
+        assert!(system.check_derivation(&proof).is_ok());
+        assert!(system.verify(&op!("parity", succ(succ(zero())))).is_none());
+
+        // An operator that merely shares the symbol has to be derived as a
+        // whole, by rules of its own
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("odd_one", op!("odd", succ(zero()))),
+                Rule::new(
+                    "parity",
+                    vec![op!("∨", op!("even", var("n")), op!("odd", var("n")))],
+                    op!("parity", var("n")),
+                ),
+            ],
+            4,
+        );
+        assert!(system.verify(&op!("parity", succ(zero()))).is_none());
+    }
+
+    // Bounds each variable by the numerals it is compared with, rejecting
+    // comparisons of numerals that do not hold and variables left without any
+    // value between their bounds
+    fn less_than(constraints: &[Judgement]) -> bool {
+        let mut bounds: HashMap<String, (Option<usize>, Option<usize>)> = HashMap::new();
+        for constraint in constraints {
+            let Judgement::Operator { subjects, .. } = constraint else {
+                continue;
+            };
+            let [left, right] = subjects.as_slice() else {
+                continue;
+            };
+            match (left.try_to_nat(), right.try_to_nat(), &**left, &**right) {
+                (Ok(left), Ok(right), _, _) if left >= right => return false,
+                (Ok(left), Err(_), _, Judgement::Variable(variable, _)) => {
+                    let lower = &mut bounds.entry(variable.clone()).or_default().0;
+                    *lower = Some(lower.map_or(left, |lower| lower.max(left)));
+                }
+                (Err(_), Ok(right), Judgement::Variable(variable, _), _) => {
+                    let upper = &mut bounds.entry(variable.clone()).or_default().1;
+                    *upper = Some(upper.map_or(right, |upper| upper.min(right)));
+                }
+                _ => {}
+            }
+        }
+        bounds.values().all(|bounds| match bounds {
+            (Some(lower), Some(upper)) => lower + 1 < *upper,
+            (None, Some(upper)) => *upper > 0,
+            _ => true,
+        })
+    }
+
+    #[test]
+    fn constraint_solver() {
+        let one = succ(zero());
+        let two = succ(one.clone());
+        let rules = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", zero())),
+                Rule::new(
+                    "succ",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", succ(var("n"))),
+                ),
+                Rule::new(
+                    "mid",
+                    vec![
+                        op!("lt", zero(), var("x")),
+                        op!("lt", var("x"), two.clone()),
+                    ],
+                    op!("mid", var("x")),
+                ),
+                Rule::new(
+                    "gap",
+                    vec![
+                        op!("lt", one.clone(), var("x")),
+                        op!("lt", var("x"), two.clone()),
+                        op!("nat", var("x")),
+                    ],
+                    op!("gap", var("x")),
+                ),
+                Rule::new(
+                    "a",
+                    vec![op!("lt", zero(), var("x")), op!("b", var("x"))],
+                    op!("a", var("x")),
+                ),
+                Rule::new(
+                    "b",
+                    vec![op!("lt", var("y"), one.clone())],
+                    op!("b", var("y")),
+                ),
+            ],
+            8,
+        )
+        .with_constraints(&["lt"]);
+        let system = rules.clone().with_constraint_solver(less_than);
+
+        // Constraints are left out of the derivation
+        let proof = system.verify(&op!("mid", one.clone())).unwrap();
+        assert!(proof.premises.is_empty());
+        assert!(system.verify(&op!("mid", zero())).is_none());
+        assert!(system.verify(&op!("mid", two.clone())).is_none());
+        assert!(system.verify(&op!("mid", var("x"))).is_some());
+
+        // Without a solver every constraint is taken to hold
+        assert!(rules.verify(&op!("gap", var("x"))).is_some());
+        assert!(system.verify(&op!("gap", var("x"))).is_none());
+        assert_eq!(system.distinct_answers(&op!("mid", var("x"))).len(), 1);
+        assert!(system.distinct_answers(&op!("gap", var("x"))).is_empty());
+        // Fair queries drop the branch before looking for numerals
+        assert_eq!(system.query_fair(&op!("gap", var("x"))).next(), None);
+
+        // Constraints met in different rules are solved together
+        let split = op!("a", var("x"));
+        assert!(rules.verify(&split).is_some());
+        assert!(system.verify(&split).is_none());
+        assert!(system.query(&split).0.is_empty());
+        assert!(system.verify_all(&split).0.is_empty());
+        assert_eq!(system.query_fair(&split).next(), None);
+    }
+
+    #[test]
+    fn selected_rules() {
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+                Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
+            ],
+            4,
+        );
+        let goal = op!("max", zero(), zero(), var("x"));
+        assert_eq!(system.verify(&goal).unwrap().rule_label, "max1");
+
+        let mut goals: Vec<Judgement> = vec![];
+        let proof = system
+            .verify_with_selector(&goal, |goal, rules| {
+                goals.push(goal.clone());
+                (0..rules.len())
+                    .filter(|&i| rules[i].name == "max2")
+                    .collect()
+            })
+            .unwrap();
+        assert_eq!(proof.rule_label, "max2");
+        assert_eq!(goals, vec![goal.clone()]);
+
+        // Leaving every rule out fails the goal
+        assert!(system.verify_with_selector(&goal, |_, _| vec![]).is_none());
+
+        // ..for that time only: the same goal met again is searched again
+        let system = FormalSystem::new(
+            vec![
+                Rule::new("first", vec![atom("p"), atom("q")], atom("top")),
+                Rule::new("second", vec![atom("p")], atom("top")),
+                Rule::taut("p", atom("p")),
+            ],
+            4,
+        );
+        let mut seen = 0;
+        let proof = system
+            .verify_with_selector(&atom("top"), |goal, rules| {
+                if *goal == atom("p") {
+                    seen += 1;
+                    if seen == 1 {
+                        return vec![];
+                    }
+                }
+                (0..rules.len()).collect()
+            })
+            .unwrap();
+        assert_eq!(proof.rule_label, "second");
+        assert_eq!(seen, 2);
+    }
+}
@@ -0,0 +1,890 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+
+use itertools::Itertools;
+
+use super::parser::write_symbol;
+use super::rename_apart;
+use super::{atom, op, FormalSystem, Judgement, Rule, UnificationTable, SEQUENCE_PREDICATE};
+
+// Maximum depth of the subjects generated by `sample_goals`
+const SAMPLE_MAX_DEPTH: usize = 6;
+
+// A small seeded generator (SplitMix64), so that samples can be reproduced
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+pub fn numeral(n: usize) -> Judgement {
+    (0..n).fold(atom("zero"), |result, _| op!("succ", result))
+}
+
+// A list of `items` built from `nil()` and `cons(head, tail)`
+pub fn list(items: Vec<Judgement>) -> Judgement {
+    items
+        .into_iter()
+        .rev()
+        .fold(atom("nil"), |tail, head| op!("cons", head, tail))
+}
+
+// The first subterm that breaks an encoding, reached from the decoded term by
+// following `path` as in `Judgement::with_subject_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingError {
+    pub path: Vec<usize>,
+    pub expected: &'static str,
+    pub found: Judgement,
+}
+
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, found `{}`", self.expected, self.found)?;
+        if !self.path.is_empty() {
+            write!(f, " at subject {}", self.path.iter().join("."))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+pub struct Collapsed<'a>(&'a Judgement);
+
+impl Display for Collapsed<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Ok(n) = self.0.try_to_nat() {
+            return write!(f, "{}", n);
+        }
+        if let Ok(items) = self.0.try_to_vec() {
+            return write!(f, "[{}]", items.iter().map(Judgement::collapsed).join(", "));
+        }
+        match self.0 {
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => {
+                write_symbol(f, predicate)?;
+                write!(
+                    f,
+                    "({})",
+                    subjects
+                        .iter()
+                        .map(|subject| subject.collapsed())
+                        .join(", ")
+                )
+            }
+            judgement => Display::fmt(judgement, f),
+        }
+    }
+}
+
+impl Judgement {
+    // The judgement written with numerals as decimal numbers and lists in
+    // brackets, which the parser reads back. Only terms built from the
+    // reserved `succ`/`zero` and `cons`/`nil` are collapsed
+    pub fn collapsed(&self) -> Collapsed<'_> {
+        Collapsed(self)
+    }
+
+    // The number a term built by `numeral` stands for
+    pub fn try_to_nat(&self) -> Result<usize, EncodingError> {
+        let mut n = 0;
+        let mut term = self;
+        loop {
+            if let Judgement::Operator {
+                predicate,
+                subjects,
+            } = term
+            {
+                match (predicate.as_str(), subjects.as_slice()) {
+                    ("zero", []) => return Ok(n),
+                    ("succ", [previous]) => {
+                        n += 1;
+                        term = previous;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            return Err(EncodingError {
+                path: vec![0; n],
+                expected: "`zero()` or `succ(...)`",
+                found: term.clone(),
+            });
+        }
+    }
+
+    // The items of a term built by `list`
+    pub fn try_to_vec(&self) -> Result<Vec<Judgement>, EncodingError> {
+        let mut items: Vec<Judgement> = vec![];
+        let mut term = self;
+        loop {
+            if let Judgement::Operator {
+                predicate,
+                subjects,
+            } = term
+            {
+                match (predicate.as_str(), subjects.as_slice()) {
+                    ("nil", []) => return Ok(items),
+                    ("cons", [head, tail]) => {
+                        items.push(head.as_ref().clone());
+                        term = tail;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            return Err(EncodingError {
+                path: vec![1; items.len()],
+                expected: "`nil()` or `cons(..., ...)`",
+                found: term.clone(),
+            });
+        }
+    }
+}
+
+// Every ground term of exactly `size` nodes built from the given
+// `(name, arity)` constructors
+pub fn ground_terms(constructors: &[(String, usize)], size: usize) -> Vec<Judgement> {
+    let mut cache: HashMap<usize, Vec<Judgement>> = HashMap::new();
+    ground_terms_cached(constructors, size, &mut cache)
+}
+
+fn ground_terms_cached(
+    constructors: &[(String, usize)],
+    size: usize,
+    cache: &mut HashMap<usize, Vec<Judgement>>,
+) -> Vec<Judgement> {
+    if let Some(terms) = cache.get(&size) {
+        return terms.clone();
+    }
+
+    let mut terms: Vec<Judgement> = vec![];
+    if size > 0 {
+        for (name, arity) in constructors {
+            let mut subjects = |size| ground_terms_cached(constructors, size, cache);
+            for subjects in subject_tuples(*arity, size - 1, &mut subjects) {
+                terms.push(Judgement::operator(name, subjects));
+            }
+        }
+    }
+
+    cache.insert(size, terms.clone());
+    terms
+}
+
+// Every tuple of `arity` terms whose sizes add up to `size`, drawing the terms
+// of each size from `terms`
+fn subject_tuples(
+    arity: usize,
+    size: usize,
+    terms: &mut dyn FnMut(usize) -> Vec<Judgement>,
+) -> Vec<Vec<Judgement>> {
+    if arity == 0 {
+        return if size == 0 { vec![vec![]] } else { vec![] };
+    }
+
+    let mut tuples: Vec<Vec<Judgement>> = vec![];
+    for first_size in 1..=size.saturating_sub(arity - 1) {
+        let firsts = terms(first_size);
+        if firsts.is_empty() {
+            continue;
+        }
+        for rest in subject_tuples(arity - 1, size - first_size, terms) {
+            for first in &firsts {
+                let mut tuple = vec![first.clone()];
+                tuple.extend(rest.iter().cloned());
+                tuples.push(tuple);
+            }
+        }
+    }
+    tuples
+}
+
+// Produces the candidate subjects of the judgements to enumerate, suited to
+// the way a system encodes its data
+pub trait TermGenerator {
+    // Every term of exactly `size` nodes
+    fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>>;
+}
+
+// Every term built from the given `(name, arity)` constructors, as found by
+// `FormalSystem::constructors`
+pub struct Constructors(pub Vec<(String, usize)>);
+
+impl TermGenerator for Constructors {
+    fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>> {
+        Box::new(ground_terms(&self.0, size).into_iter())
+    }
+}
+
+// `zero()`, `succ(zero())`, ...
+pub struct Numerals;
+
+impl TermGenerator for Numerals {
+    fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>> {
+        Box::new(size.checked_sub(1).map(numeral).into_iter())
+    }
+}
+
+// Binary trees with constant leaves
+pub struct BinaryTrees {
+    pub leaf: String,
+    pub node: String,
+}
+
+impl TermGenerator for BinaryTrees {
+    fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>> {
+        Constructors(vec![(self.leaf.clone(), 0), (self.node.clone(), 2)]).generate(size)
+    }
+}
+
+// Lists of the given elements, as `cons(e, cons(f, nil()))`
+pub struct Lists {
+    pub nil: String,
+    pub cons: String,
+    pub elements: Vec<Judgement>,
+}
+
+impl TermGenerator for Lists {
+    fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>> {
+        let mut lists: Vec<Vec<Judgement>> = vec![vec![], vec![atom(&self.nil)]];
+        for size in 2..=size {
+            let mut sized = vec![];
+            for element in &self.elements {
+                let Some(rest) = size.checked_sub(1 + element.size()) else {
+                    continue;
+                };
+                for tail in &lists[rest] {
+                    sized.push(op!(&self.cons, element.clone(), tail.clone()));
+                }
+            }
+            lists.push(sized);
+        }
+        Box::new(lists.swap_remove(size.min(lists.len() - 1)).into_iter())
+    }
+}
+
+// A random ground term at most `depth` operators deep, if there is any constant
+pub fn random_term(
+    constructors: &[(String, usize)],
+    depth: usize,
+    rng: &mut Rng,
+) -> Option<Judgement> {
+    let choices: Vec<&(String, usize)> = constructors
+        .iter()
+        .filter(|(_, arity)| depth > 1 || *arity == 0)
+        .collect();
+    if choices.is_empty() {
+        return None;
+    }
+
+    let (name, arity) = choices[rng.below(choices.len())];
+    let subjects = (0..*arity)
+        .map(|_| random_term(constructors, depth - 1, rng))
+        .collect::<Option<Vec<Judgement>>>()?;
+    Some(Judgement::operator(name, subjects))
+}
+
+// A branch of the fair search: the goals left to prove, the next one last,
+// under the bindings made so far
+struct Branch {
+    goals: Vec<Judgement>,
+    substitutions: UnificationTable,
+    // Disequality premises met along the branch, and rules with sorted
+    // variables, checked once every goal is proved
+    disequalities: Vec<(Judgement, Judgement)>,
+    sorted: Vec<Rule>,
+    // Premises with a constraint predicate, checked as they are met
+    constraints: Vec<Judgement>,
+}
+
+// The answers to a query, found breadth first: every alternative rule for a
+// goal is expanded one step at a time, in turn with all the other branches,
+// so that no branch is starved by an infinite one. There is no height bound,
+// so asking for an answer past the last one may not return
+pub struct FairQuery<'a> {
+    system: &'a FormalSystem,
+    // The choices of alternative premises of each axiom
+    alternatives: Vec<Vec<Rule>>,
+    variables: Vec<String>,
+    queue: VecDeque<Branch>,
+    answers: Vec<UnificationTable>,
+}
+
+impl FairQuery<'_> {
+    fn expand(&mut self, mut branch: Branch, goal: Judgement) {
+        if let Some((left, right)) = goal.as_disequality() {
+            branch.disequalities.push((left.clone(), right.clone()));
+            self.queue.push_back(branch);
+            return;
+        }
+        if self.system.is_constraint(&goal) {
+            branch.constraints.push(goal);
+            if self
+                .system
+                .constraints_hold(&branch.constraints, &branch.substitutions)
+            {
+                self.queue.push_back(branch);
+            }
+            return;
+        }
+        if let Judgement::Operator {
+            predicate,
+            subjects,
+        } = goal.resolve(&branch.substitutions)
+        {
+            if let [left, right] = subjects.as_slice() {
+                if self.system.equality.contains(predicate) {
+                    for substitutions in self.unifiers(left, right, &branch.substitutions) {
+                        self.queue.push_back(Branch {
+                            goals: branch.goals.clone(),
+                            substitutions,
+                            disequalities: branch.disequalities.clone(),
+                            sorted: branch.sorted.clone(),
+                            constraints: branch.constraints.clone(),
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+
+        let mut taken: HashSet<String> = goal.get_variables();
+        for judgement in &branch.goals {
+            taken.extend(judgement.get_variables());
+        }
+        for (variable, value) in &branch.substitutions {
+            taken.insert(variable.clone());
+            taken.extend(value.get_variables());
+        }
+
+        for (axiom, alternatives) in self.system.axioms.iter().zip(&self.alternatives) {
+            let mut applied = false;
+            for rule in alternatives {
+                let rule = rename_apart(rule, &taken);
+                let unifiers = self.unifiers(&goal, &rule.conclusion, &branch.substitutions);
+                applied |= !unifiers.is_empty();
+                for substitutions in unifiers {
+                    let mut goals = branch.goals.clone();
+                    goals.extend(rule.premises.iter().rev().cloned());
+                    let mut sorted = branch.sorted.clone();
+                    if !rule.sorts.is_empty() {
+                        sorted.push(rule.clone());
+                    }
+                    self.queue.push_back(Branch {
+                        goals,
+                        substitutions,
+                        disequalities: branch.disequalities.clone(),
+                        sorted,
+                        constraints: branch.constraints.clone(),
+                    });
+                }
+            }
+            // Every alternative of a committed rule is still tried
+            if applied && axiom.committed {
+                break;
+            }
+        }
+    }
+
+    fn unifiers(
+        &self,
+        left: &Judgement,
+        right: &Judgement,
+        substitutions: &UnificationTable,
+    ) -> Vec<UnificationTable> {
+        if self.system.commutative.is_empty() {
+            left.would_unify(right, substitutions).into_iter().collect()
+        } else {
+            left.unify_modulo_with_substitution(right, substitutions, &self.system.commutative)
+        }
+    }
+}
+
+impl Iterator for FairQuery<'_> {
+    type Item = UnificationTable;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(mut branch) = self.queue.pop_front() {
+            if let Some(goal) = branch.goals.pop() {
+                self.expand(branch, goal);
+                continue;
+            }
+
+            let system = self.system;
+            if !system.disequalities_hold(&branch.disequalities, &branch.substitutions)
+                || !system.constraints_hold(&branch.constraints, &branch.substitutions)
+                || !branch
+                    .sorted
+                    .iter()
+                    .all(|rule| system.sorts_hold(rule, &branch.substitutions))
+            {
+                continue;
+            }
+            let answer: UnificationTable = self
+                .variables
+                .iter()
+                .map(|variable| {
+                    (
+                        variable.clone(),
+                        Judgement::variable(variable).apply_substitution(&branch.substitutions),
+                    )
+                })
+                .collect();
+            if !self.answers.contains(&answer) {
+                self.answers.push(answer.clone());
+                return Some(answer);
+            }
+        }
+        None
+    }
+}
+
+impl FormalSystem {
+    // The operators appearing as subjects in the rules, with their arities,
+    // in order of first appearance
+    pub fn constructors(&self) -> Vec<(String, usize)> {
+        fn collect(judgement: &Judgement, constructors: &mut Vec<(String, usize)>) {
+            if let Judgement::Operator { subjects, .. } = judgement {
+                for subject in subjects {
+                    if let Judgement::Operator {
+                        predicate,
+                        subjects: inner,
+                    } = subject.as_ref()
+                    {
+                        let constructor = (predicate.clone(), inner.len());
+                        if predicate != SEQUENCE_PREDICATE && !constructors.contains(&constructor) {
+                            constructors.push(constructor);
+                        }
+                    }
+                    collect(subject, constructors);
+                }
+            }
+        }
+
+        let mut constructors: Vec<(String, usize)> = vec![];
+        for axiom in &self.axioms {
+            for judgement in axiom.premises.iter().chain([&axiom.conclusion]) {
+                collect(judgement, &mut constructors);
+            }
+        }
+        constructors
+    }
+
+    // Judgements `predicate(t1, ..., tn)` of at most `max_size` nodes with
+    // subjects taken from `generator`, smallest first
+    fn ground_judgements(
+        &self,
+        predicate: &str,
+        arity: usize,
+        max_size: usize,
+        generator: &dyn TermGenerator,
+    ) -> Vec<Judgement> {
+        let mut cache: HashMap<usize, Vec<Judgement>> = HashMap::new();
+        let mut terms = |size: usize| {
+            cache
+                .entry(size)
+                .or_insert_with(|| generator.generate(size).collect())
+                .clone()
+        };
+        (arity + 1..=max_size)
+            .flat_map(|size| subject_tuples(arity, size - 1, &mut terms))
+            .map(|subjects| Judgement::operator(predicate, subjects))
+            .collect()
+    }
+
+    pub fn enumerate(&self, predicate: &str, arity: usize, max_size: usize) -> Vec<Judgement> {
+        self.enumerate_with(
+            predicate,
+            arity,
+            max_size,
+            &Constructors(self.constructors()),
+        )
+    }
+
+    pub fn enumerate_with(
+        &self,
+        predicate: &str,
+        arity: usize,
+        max_size: usize,
+        generator: &dyn TermGenerator,
+    ) -> Vec<Judgement> {
+        self.ground_judgements(predicate, arity, max_size, generator)
+            .into_iter()
+            .filter(|judgement| self.verify(judgement).is_some())
+            .collect()
+    }
+
+    // `n` random ground judgements `predicate(t1, ..., tn)`, each labelled with
+    // whether it can be proved
+    pub fn sample_goals(
+        &self,
+        predicate: &str,
+        arity: usize,
+        n: usize,
+        rng: &mut Rng,
+    ) -> Vec<(Judgement, bool)> {
+        let constructors = self.constructors();
+        (0..n)
+            .map_while(|_| {
+                let subjects = (0..arity)
+                    .map(|_| random_term(&constructors, SAMPLE_MAX_DEPTH, rng))
+                    .collect::<Option<Vec<Judgement>>>()?;
+                let goal = Judgement::operator(predicate, subjects);
+                let provable = self.verify(&goal).is_some();
+                Some((goal, provable))
+            })
+            .collect()
+    }
+
+    pub fn smallest_unprovable(
+        &self,
+        predicate: &str,
+        arity: usize,
+        max_size: usize,
+    ) -> Option<Judgement> {
+        self.smallest_unprovable_with(
+            predicate,
+            arity,
+            max_size,
+            &Constructors(self.constructors()),
+        )
+    }
+
+    pub fn smallest_unprovable_with(
+        &self,
+        predicate: &str,
+        arity: usize,
+        max_size: usize,
+        generator: &dyn TermGenerator,
+    ) -> Option<Judgement> {
+        self.ground_judgements(predicate, arity, max_size, generator)
+            .into_iter()
+            .find(|judgement| self.verify(judgement).is_none())
+    }
+
+    // `n` judgements drawn at random among those `enumerate_with` would try,
+    // each labelled with whether it can be proved
+    pub fn sample_goals_with(
+        &self,
+        predicate: &str,
+        arity: usize,
+        max_size: usize,
+        n: usize,
+        generator: &dyn TermGenerator,
+        rng: &mut Rng,
+    ) -> Vec<(Judgement, bool)> {
+        let candidates = self.ground_judgements(predicate, arity, max_size, generator);
+        if candidates.is_empty() {
+            return vec![];
+        }
+        (0..n)
+            .map(|_| {
+                let goal = candidates[rng.below(candidates.len())].clone();
+                let provable = self.verify(&goal).is_some();
+                (goal, provable)
+            })
+            .collect()
+    }
+
+    // Like `query`, but unbounded and lazy: answers come in order of how many
+    // rule applications they take, whatever the order of the rules
+    pub fn query_fair(&self, judgement: &Judgement) -> FairQuery<'_> {
+        FairQuery {
+            system: self,
+            alternatives: self.axioms.iter().map(Rule::alternatives).collect(),
+            variables: judgement.get_variables().into_iter().collect(),
+            queue: VecDeque::from([Branch {
+                goals: vec![judgement.clone()],
+                substitutions: UnificationTable::new(),
+                disequalities: vec![],
+                sorted: vec![],
+                constraints: vec![],
+            }]),
+            answers: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{var, Rule};
+
+    fn parity(complete: bool) -> FormalSystem {
+        let mut axioms = vec![
+            Rule::taut("zero", op!("even", atom("zero"))),
+            Rule::new(
+                "odd",
+                vec![op!("even", var("n"))],
+                op!("odd", op!("succ", var("n"))),
+            ),
+            Rule::new("p1", vec![op!("even", var("n"))], op!("parity", var("n"))),
+            Rule::new("p2", vec![op!("odd", var("n"))], op!("parity", var("n"))),
+        ];
+        if complete {
+            axioms.push(Rule::new(
+                "even",
+                vec![op!("odd", var("n"))],
+                op!("even", op!("succ", var("n"))),
+            ));
+        }
+        FormalSystem::new(axioms, 12)
+    }
+
+    #[test]
+    fn decoded_encodings() {
+        assert_eq!(numeral(3).try_to_nat(), Ok(3));
+        assert_eq!(
+            list(vec![numeral(0), numeral(2)]).try_to_vec(),
+            Ok(vec![numeral(0), numeral(2)])
+        );
+
+        let error = op!("succ", op!("succ", atom("empty")))
+            .try_to_nat()
+            .unwrap_err();
+        assert_eq!(error.path, vec![0, 0]);
+        assert_eq!(error.found, atom("empty"));
+        assert_eq!(
+            error.to_string(),
+            "expected `zero()` or `succ(...)`, found `empty()` at subject 0.0"
+        );
+        assert_eq!(
+            op!("succ", atom("empty")).try_to_nat().unwrap_err().path,
+            vec![0]
+        );
+
+        let error = op!("cons", atom("a"), var("xs")).try_to_vec().unwrap_err();
+        assert_eq!(error.path, vec![1]);
+        assert_eq!(error.found, var("xs"));
+    }
+
+    #[test]
+    fn collapsed_encodings() {
+        let judgement = op!(
+            "sum",
+            numeral(2),
+            op!("f", numeral(0), var("n")),
+            list(vec![numeral(1), atom("a"), list(vec![])])
+        );
+        let collapsed = judgement.collapsed().to_string();
+        assert_eq!(collapsed, "sum(2, f(0, n), [1, a(), []])");
+        assert_eq!(collapsed.parse::<Judgement>(), Ok(judgement));
+        assert_eq!(op!("succ", var("n")).collapsed().to_string(), "succ(n)");
+        // The literals are spelled with the reserved names
+        assert_eq!(
+            "[1]".parse::<Judgement>(),
+            Ok(op!("cons", op!("succ", atom("zero")), atom("nil")))
+        );
+    }
+
+    #[test]
+    fn terms_by_size() {
+        let constructors = vec![(String::from("zero"), 0), (String::from("succ"), 1)];
+        assert_eq!(ground_terms(&constructors, 3), vec![numeral(2)]);
+
+        let constructors = vec![(String::from("leaf"), 0), (String::from("node"), 2)];
+        assert_eq!(ground_terms(&constructors, 2), vec![]);
+        assert_eq!(ground_terms(&constructors, 5).len(), 2);
+    }
+
+    #[test]
+    fn fair_queries() {
+        let zero = Rule::taut("zero", op!("nat", atom("zero")));
+        let succ = Rule::new(
+            "succ",
+            vec![op!("nat", var("n"))],
+            op!("nat", op!("succ", var("n"))),
+        );
+        let numerals: Vec<UnificationTable> = (0..5)
+            .map(|n| HashMap::from([(String::from("n"), numeral(n))]))
+            .collect();
+
+        // The recursive rule first sends a depth-first search down forever
+        for rules in [
+            vec![zero.clone(), succ.clone()],
+            vec![succ.clone(), zero.clone()],
+        ] {
+            let nat = FormalSystem::new(rules, 1);
+            let answers: Vec<UnificationTable> =
+                nat.query_fair(&op!("nat", var("n"))).take(5).collect();
+            assert_eq!(answers, numerals);
+        }
+
+        // Both predicates get their turn
+        let answers: Vec<UnificationTable> = parity(true)
+            .query_fair(&op!("parity", var("n")))
+            .take(4)
+            .collect();
+        assert_eq!(
+            answers,
+            (0..4)
+                .map(|n| HashMap::from([(String::from("n"), numeral(n))]))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            parity(true).query_fair(&op!("odd", atom("zero"))).next(),
+            None
+        );
+    }
+
+    #[test]
+    fn custom_generators() {
+        // Trees of the shape `t(t(e(), e()), e())`, not built from the
+        // constructors the rules happen to mention
+        struct Trees;
+
+        impl TermGenerator for Trees {
+            fn generate(&self, size: usize) -> Box<dyn Iterator<Item = Judgement>> {
+                let mut terms: Vec<Judgement> = vec![];
+                if size == 1 {
+                    terms.push(atom("e"));
+                }
+                for left in 1..size.saturating_sub(1) {
+                    for l in self.generate(left) {
+                        for r in self.generate(size - 1 - left) {
+                            terms.push(op!("t", l.clone(), r));
+                        }
+                    }
+                }
+                Box::new(terms.into_iter())
+            }
+        }
+
+        let trees = FormalSystem::new(
+            vec![
+                Rule::taut("leaf", op!("tree", atom("e"))),
+                Rule::new(
+                    "node",
+                    vec![op!("tree", var("l")), op!("tree", var("r"))],
+                    op!("tree", op!("t", var("l"), var("r"))),
+                ),
+                Rule::new(
+                    "left",
+                    vec![op!("tree", var("l"))],
+                    op!("leftmost", op!("t", var("l"), atom("e"))),
+                ),
+            ],
+            8,
+        );
+        let e = atom("e");
+        let t = |l: Judgement, r: Judgement| op!("t", l, r);
+        assert_eq!(
+            trees.enumerate_with("tree", 1, 6, &Trees),
+            vec![
+                op!("tree", e.clone()),
+                op!("tree", t(e.clone(), e.clone())),
+                op!("tree", t(e.clone(), t(e.clone(), e.clone()))),
+                op!("tree", t(t(e.clone(), e.clone()), e.clone())),
+            ]
+        );
+        assert_eq!(
+            trees.smallest_unprovable_with("leftmost", 1, 6, &Trees),
+            Some(op!("leftmost", e.clone()))
+        );
+
+        let samples = trees.sample_goals_with("leftmost", 1, 6, 10, &Trees, &mut Rng::seeded(3));
+        assert_eq!(samples.len(), 10);
+        for (goal, provable) in samples {
+            assert_eq!(
+                provable,
+                goal != op!("leftmost", e.clone())
+                    && goal != op!("leftmost", t(e.clone(), t(e.clone(), e.clone())))
+            );
+        }
+    }
+
+    #[test]
+    fn built_in_generators() {
+        assert_eq!(Numerals.generate(0).count(), 0);
+        assert_eq!(Numerals.generate(3).collect::<Vec<_>>(), vec![numeral(2)]);
+
+        let trees = BinaryTrees {
+            leaf: String::from("leaf"),
+            node: String::from("node"),
+        };
+        // Catalan numbers, for the trees with 0, 1, 2 and 3 nodes
+        assert_eq!(
+            [1, 3, 5, 7].map(|size| trees.generate(size).count()),
+            [1, 1, 2, 5]
+        );
+
+        let lists = Lists {
+            nil: String::from("nil"),
+            cons: String::from("cons"),
+            elements: vec![atom("a"), op!("f", atom("a"))],
+        };
+        assert_eq!(lists.generate(1).collect::<Vec<_>>(), vec![atom("nil")]);
+        assert_eq!(lists.generate(2).count(), 0);
+        assert_eq!(
+            lists.generate(4).collect::<Vec<_>>(),
+            vec![op!("cons", op!("f", atom("a")), atom("nil"))]
+        );
+        assert_eq!(lists.generate(5).count(), 1);
+        assert_eq!(lists.generate(6).count(), 2);
+    }
+
+    #[test]
+    fn smallest_unprovable_judgement() {
+        assert_eq!(
+            parity(false).constructors(),
+            vec![(String::from("zero"), 0), (String::from("succ"), 1)]
+        );
+        assert_eq!(
+            parity(false).smallest_unprovable("parity", 1, 10),
+            Some(op!("parity", numeral(2)))
+        );
+        assert_eq!(parity(true).smallest_unprovable("parity", 1, 10), None);
+        assert_eq!(
+            parity(true).enumerate("even", 1, 6),
+            vec![
+                op!("even", numeral(0)),
+                op!("even", numeral(2)),
+                op!("even", numeral(4))
+            ]
+        );
+    }
+
+    #[test]
+    fn sampled_goals() {
+        let system = parity(true);
+        let samples = system.sample_goals("even", 1, 20, &mut Rng::seeded(7));
+
+        assert_eq!(samples.len(), 20);
+        assert_eq!(
+            samples,
+            system.sample_goals("even", 1, 20, &mut Rng::seeded(7))
+        );
+        // `numeral(n)` has size `n + 1`
+        for (goal, provable) in &samples {
+            assert_eq!(*provable, goal.size() % 2 == 0);
+        }
+        assert!(samples.iter().any(|(_, provable)| *provable));
+        assert!(samples.iter().any(|(_, provable)| !*provable));
+
+        let empty = FormalSystem::new(vec![], 4);
+        assert!(empty
+            .sample_goals("even", 1, 5, &mut Rng::seeded(7))
+            .is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::{Derivation, Judgement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofAssistant {
+    Lean,
+    Coq,
+}
+
+impl ProofAssistant {
+    fn comment(&self, text: &str) -> String {
+        match self {
+            ProofAssistant::Lean => format!("-- {}", text),
+            ProofAssistant::Coq => format!("(* {} *)", text),
+        }
+    }
+
+    fn apply(&self, name: &str) -> String {
+        match self {
+            ProofAssistant::Lean => format!("apply {}", name),
+            ProofAssistant::Coq => format!("apply {}.", name),
+        }
+    }
+}
+
+// Premises sit in a row above the bar, conclusions are centered below it
+const INTERACTIVE_STYLE: &str = "
+body { font-family: monospace; display: flex; justify-content: center; padding: 2em; }
+.rule { display: inline-flex; flex-direction: column; align-items: center; margin: 0 1em; }
+.premises { display: flex; align-items: flex-end; }
+.bar { position: relative; align-self: stretch; border-top: 1px solid black; }
+.label { position: absolute; right: 100%; top: -0.7em; padding-right: 0.3em; font-size: 0.8em; }
+.conclusion { padding: 0.2em 0.5em; }
+.collapsible > .conclusion { cursor: pointer; }
+.collapsible > .conclusion:hover { background: #eef; }
+.collapsed > .premises { display: none; }
+.collapsed > .bar::before { content: '\\22EE'; position: absolute; left: 50%; bottom: 0.2em; }
+";
+
+// Clicking the conclusion of a rule with premises hides or shows them
+const INTERACTIVE_SCRIPT: &str = "
+document.querySelectorAll('.collapsible > .conclusion').forEach(function (conclusion) {
+  conclusion.addEventListener('click', function () {
+    conclusion.parentElement.classList.toggle('collapsed');
+  });
+});
+";
+
+// The Curry-Howard reading of a derivation: every rule is a constructor taking
+// the proofs of its premises, and the term has the judgement it proves as type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofTerm {
+    pub constructor: String,
+    pub arguments: Vec<ProofTerm>,
+    // Instantiated as in the derivation, variables included
+    pub judgement: Judgement,
+}
+
+impl ProofTerm {
+    // The term followed by its type, as in `succ(zero) : nat(succ(zero()))`
+    pub fn to_typed_string(&self) -> String {
+        format!("{} : {}", self, self.judgement)
+    }
+}
+
+impl Display for ProofTerm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.constructor)?;
+        if !self.arguments.is_empty() {
+            f.write_str("(")?;
+            for (i, argument) in self.arguments.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", argument)?;
+            }
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => String::from("&amp;"),
+            '<' => String::from("&lt;"),
+            '>' => String::from("&gt;"),
+            '"' => String::from("&quot;"),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Judgements are set in math mode, where these characters are commands
+fn escape_latex(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' => String::from("\\backslash{}"),
+            '~' => String::from("\\sim{}"),
+            '^' => String::from("\\hat{}"),
+            '{' | '}' | '_' | '#' | '%' | '&' | '$' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+impl Derivation {
+    // The derivation as nested `\inferrule` of the mathpartir package, each
+    // named on its right after its rule
+    pub fn to_latex(&self) -> String {
+        let premises = self
+            .premises
+            .iter()
+            .map(Derivation::to_latex)
+            .collect::<Vec<String>>()
+            .join(" \\and ");
+        let conclusion = escape_latex(&self.conclusion.to_string());
+        let premises = if premises.is_empty() {
+            String::from(" ")
+        } else {
+            premises
+        };
+        if self.rule_label.is_empty() {
+            return format!("\\inferrule{{{}}}{{{}}}", premises, conclusion);
+        }
+        // Options are split at commas and equal signs unless braced
+        let label = escape_latex(&self.rule_label);
+        let label = if label.contains([',', '=', ']']) {
+            format!("{{{}}}", label)
+        } else {
+            label
+        };
+        format!(
+            "\\inferrule*[right={}]{{{}}}{{{}}}",
+            label, premises, conclusion
+        )
+    }
+
+    // A standalone page showing the derivation as a tree, where the premises of
+    // a rule can be collapsed and expanded by clicking its conclusion
+    pub fn to_interactive_html(&self) -> String {
+        let mut body = String::new();
+        self.html_rule(&mut body);
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+<style>{}</style>
+</head>
+<body>
+{}
+<script>{}</script>
+</body>
+</html>
+",
+            escape_html(&self.conclusion.to_string()),
+            INTERACTIVE_STYLE,
+            body,
+            INTERACTIVE_SCRIPT
+        )
+    }
+
+    fn html_rule(&self, html: &mut String) {
+        if self.premises.is_empty() {
+            html.push_str("<div class=\"rule\">");
+        } else {
+            html.push_str("<div class=\"rule collapsible\">");
+            html.push_str("<div class=\"premises\">");
+            for premise in &self.premises {
+                premise.html_rule(html);
+            }
+            html.push_str("</div>");
+        }
+        html.push_str(&format!(
+            "<div class=\"bar\"><span class=\"label\">{}</span>",
+            escape_html(&self.rule_label)
+        ));
+        if !self.bindings.is_empty() {
+            let bindings = self
+                .bindings
+                .iter()
+                .map(|(variable, value)| format!("{} := {}", variable, value))
+                .collect::<Vec<String>>()
+                .join(", ");
+            html.push_str(&format!(
+                "<span class=\"bindings\">with {}</span>",
+                escape_html(&bindings)
+            ));
+        }
+        html.push_str("</div>");
+        html.push_str(&format!(
+            "<div class=\"conclusion\">{}</div></div>",
+            escape_html(&self.conclusion.to_string())
+        ));
+    }
+
+    pub fn to_proof_term(&self) -> ProofTerm {
+        ProofTerm {
+            constructor: self.rule_label.clone(),
+            arguments: self
+                .premises
+                .iter()
+                .map(Derivation::to_proof_term)
+                .collect(),
+            judgement: self.conclusion.clone(),
+        }
+    }
+
+    // Emits an apply-style tactic script following the order in which rules were
+    // applied. Rule labels are translated through `names`, falling back to the
+    // label itself; each goal is recorded as a comment above its tactic.
+    pub fn to_tactic_script(
+        &self,
+        assistant: ProofAssistant,
+        names: &HashMap<String, String>,
+    ) -> String {
+        let mut lines: Vec<String> = vec![];
+        self.tactic_lines(assistant, names, 0, &mut lines);
+        lines.join("\n") + "\n"
+    }
+
+    // Narrates the derivation for write-ups: each step reduces its goal to the
+    // premises of a rule, and a step with several premises continues with one
+    // indented paragraph per premise
+    pub fn to_prose(&self) -> String {
+        let mut paragraphs: Vec<String> = vec![];
+        self.prose_paragraphs(
+            format!("We prove `{}`.", self.conclusion),
+            0,
+            &mut paragraphs,
+        );
+        paragraphs.join("\n") + "\n"
+    }
+
+    fn prose_paragraphs(
+        &self,
+        mut paragraph: String,
+        indentation: usize,
+        paragraphs: &mut Vec<String>,
+    ) {
+        match self.premises.as_slice() {
+            [] => {
+                paragraph.push_str(&format!(" By rule {} this holds.", self.rule_label));
+                paragraphs.push(paragraph);
+            }
+            [premise] => {
+                paragraph.push_str(&format!(
+                    " By rule {} it suffices to show `{}`.",
+                    self.rule_label, premise.conclusion
+                ));
+                premise.prose_paragraphs(paragraph, indentation, paragraphs);
+            }
+            [premises @ .., last] => {
+                let shown = premises
+                    .iter()
+                    .map(|premise| format!("`{}`", premise.conclusion))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                paragraph.push_str(&format!(
+                    " By rule {} it suffices to show {} and `{}`.",
+                    self.rule_label, shown, last.conclusion
+                ));
+                paragraphs.push(paragraph);
+                let indent = "  ".repeat(indentation + 1);
+                for premise in &self.premises {
+                    premise.prose_paragraphs(
+                        format!("{}We prove `{}`.", indent, premise.conclusion),
+                        indentation + 1,
+                        paragraphs,
+                    );
+                }
+            }
+        }
+    }
+
+    fn tactic_lines(
+        &self,
+        assistant: ProofAssistant,
+        names: &HashMap<String, String>,
+        indentation: usize,
+        lines: &mut Vec<String>,
+    ) {
+        let indent = "  ".repeat(indentation);
+        let name = names.get(&self.rule_label).unwrap_or(&self.rule_label);
+
+        lines.push(format!(
+            "{}{}",
+            indent,
+            assistant.comment(&self.conclusion.to_string())
+        ));
+        lines.push(format!("{}{}", indent, assistant.apply(name)));
+
+        if let [premise] = self.premises.as_slice() {
+            premise.tactic_lines(assistant, names, indentation, lines);
+            return;
+        }
+
+        // Each of several subgoals gets its own focused block
+        for premise in &self.premises {
+            let mut block: Vec<String> = vec![];
+            premise.tactic_lines(assistant, names, indentation + 1, &mut block);
+            match assistant {
+                ProofAssistant::Lean => {
+                    if let Some(first) = block.first_mut() {
+                        first.replace_range(indent.len()..indent.len() + 2, "· ");
+                    }
+                    lines.extend(block);
+                }
+                ProofAssistant::Coq => {
+                    lines.push(format!("{}{{", indent));
+                    lines.extend(block);
+                    lines.push(format!("{}}}", indent));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{nat_rules, succ, sum_rules, zero};
+    use crate::{atom, op, var, FormalSystem, Rule};
+
+    fn system() -> FormalSystem {
+        FormalSystem::new(
+            [
+                nat_rules(),
+                vec![Rule::taut("empty", op!("tree", atom("empty")))],
+                vec![Rule::new(
+                    "node",
+                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+                    op!("tree", op!("node", var("a1"), var("a2"))),
+                )],
+            ]
+            .concat(),
+            8,
+        )
+    }
+
+    #[test]
+    fn proof_terms() {
+        let term = system()
+            .verify(&op!("nat", succ(zero())))
+            .unwrap()
+            .to_proof_term();
+        assert_eq!(term.to_string(), "succ(zero)");
+        assert_eq!(term.arguments[0].judgement, op!("nat", zero()));
+        assert_eq!(term.to_typed_string(), "succ(zero) : nat(succ(zero()))");
+
+        let term = system()
+            .verify(&op!("tree", op!("node", atom("empty"), var("t"))))
+            .unwrap()
+            .to_proof_term();
+        assert_eq!(term.to_string(), "node(empty, empty)");
+        assert_eq!(
+            term.judgement,
+            op!("tree", op!("node", atom("empty"), atom("empty")))
+        );
+    }
+
+    #[test]
+    fn lean_script() {
+        let proof = system().verify(&op!("nat", succ(zero()))).unwrap();
+        let names = HashMap::from([
+            (String::from("zero"), String::from("Nat'.zero")),
+            (String::from("succ"), String::from("Nat'.succ")),
+        ]);
+
+        assert_eq!(
+            proof.to_tactic_script(ProofAssistant::Lean, &names),
+            "-- nat(succ(zero()))\napply Nat'.succ\n-- nat(zero())\napply Nat'.zero\n"
+        );
+    }
+
+    #[test]
+    fn interactive_html() {
+        let proof = system()
+            .verify(&op!("tree", op!("node", atom("empty"), atom("empty"))))
+            .unwrap();
+        let html = proof.to_interactive_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>tree(node(empty(), empty()))</title>"));
+        assert!(html.contains(concat!(
+            "<div class=\"rule collapsible\"><div class=\"premises\">",
+            "<div class=\"rule\"><div class=\"bar\"><span class=\"label\">empty</span></div>",
+            "<div class=\"conclusion\">tree(empty())</div></div>",
+            "<div class=\"rule\"><div class=\"bar\"><span class=\"label\">empty</span></div>",
+            "<div class=\"conclusion\">tree(empty())</div></div>",
+            "</div><div class=\"bar\"><span class=\"label\">node</span></div>",
+            "<div class=\"conclusion\">tree(node(empty(), empty()))</div></div>"
+        )));
+        // Everything the page needs is inline
+        assert!(!html.contains("src="));
+        assert!(!html.contains("href="));
+
+        let proof = FormalSystem::new(vec![Rule::taut("lt", op!("<", zero(), succ(zero())))], 8)
+            .verify(&op!("<", zero(), succ(zero())))
+            .unwrap();
+        assert!(proof
+            .to_interactive_html()
+            .contains("<div class=\"conclusion\">&lt;(zero(), succ(zero()))</div>"));
+    }
+
+    #[test]
+    fn branching_scripts() {
+        let proof = system()
+            .verify(&op!("tree", op!("node", atom("empty"), atom("empty"))))
+            .unwrap();
+
+        assert_eq!(
+            proof.to_tactic_script(ProofAssistant::Lean, &HashMap::new()),
+            "-- tree(node(empty(), empty()))
+apply node
+· -- tree(empty())
+  apply empty
+· -- tree(empty())
+  apply empty
+"
+        );
+        assert_eq!(
+            proof.to_tactic_script(ProofAssistant::Coq, &HashMap::new()),
+            "(* tree(node(empty(), empty())) *)
+apply node.
+{
+  (* tree(empty()) *)
+  apply empty.
+}
+{
+  (* tree(empty()) *)
+  apply empty.
+}
+"
+        );
+    }
+
+    #[test]
+    fn latex_trees() {
+        let proof = system().verify(&op!("nat", succ(zero()))).unwrap();
+        assert_eq!(
+            proof.to_latex(),
+            "\\inferrule*[right=succ]{\\inferrule*[right=zero]{ }{nat(zero())}}{nat(succ(zero()))}"
+        );
+
+        let proof = system()
+            .verify(&op!("tree", op!("node", atom("empty"), atom("empty"))))
+            .unwrap();
+        assert_eq!(
+            proof.to_latex(),
+            "\\inferrule*[right=node]{\\inferrule*[right=empty]{ }{tree(empty())} \\and \\inferrule*[right=empty]{ }{tree(empty())}}{tree(node(empty(), empty()))}"
+        );
+
+        // Labels are escaped, and braced where they would split the options
+        let proof = FormalSystem::new(vec![Rule::taut("x_1,2", op!("nat", zero()))], 4)
+            .verify(&op!("nat", zero()))
+            .unwrap();
+        assert_eq!(
+            proof.to_latex(),
+            "\\inferrule*[right={x\\_1,2}]{ }{nat(zero())}"
+        );
+    }
+
+    #[test]
+    fn prose() {
+        fn number(n: usize) -> Judgement {
+            (0..n).fold(zero(), |n, _| succ(n))
+        }
+        fn sum(n: usize, m: usize, p: usize) -> Judgement {
+            op!("sum", number(n), number(m), number(p))
+        }
+        let addition = FormalSystem::new(sum_rules(), 8);
+
+        let proof = addition.verify(&sum(1, 2, 3)).unwrap();
+        assert_eq!(
+            proof.to_prose(),
+            format!(
+                "We prove `{}`. By rule s2 it suffices to show `{}`. \
+                 By rule s2 it suffices to show `{}`. By rule s1 this holds.\n",
+                sum(1, 2, 3),
+                sum(1, 1, 2),
+                sum(1, 0, 1)
+            )
+        );
+
+        let proof = system()
+            .verify(&op!(
+                "tree",
+                op!(
+                    "node",
+                    atom("empty"),
+                    op!("node", atom("empty"), atom("empty"))
+                )
+            ))
+            .unwrap();
+        assert_eq!(
+            proof.to_prose(),
+            "We prove `tree(node(empty(), node(empty(), empty())))`. \
+By rule node it suffices to show `tree(empty())` and `tree(node(empty(), empty()))`.
+  We prove `tree(empty())`. By rule empty this holds.
+  We prove `tree(node(empty(), empty()))`. \
+By rule node it suffices to show `tree(empty())` and `tree(empty())`.
+    We prove `tree(empty())`. By rule empty this holds.
+    We prove `tree(empty())`. By rule empty this holds.
+"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+// The rules of the demo in `main.rs`, shared by the tests of every module and,
+// through the `test-support` feature, by the benches. Each group can be taken
+// on its own, `nat` puts them all together
+use crate::{atom, op, var, FormalSystem, Judgement, Rule};
+
+pub fn zero() -> Judgement {
+    atom("zero")
+}
+pub fn succ(n: Judgement) -> Judgement {
+    op!("succ", n)
+}
+pub fn empty() -> Judgement {
+    atom("empty")
+}
+pub fn node(t1: Judgement, t2: Judgement) -> Judgement {
+    op!("node", t1, t2)
+}
+
+pub fn nat_rules() -> Vec<Rule> {
+    vec![
+        Rule::taut("zero", op!("nat", zero())),
+        Rule::new(
+            "succ",
+            vec![op!("nat", var("n"))],
+            op!("nat", succ(var("n"))),
+        ),
+    ]
+}
+
+pub fn tree_rules() -> Vec<Rule> {
+    vec![
+        Rule::new(
+            "tree",
+            vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+            op!("tree", node(var("a1"), var("a2"))),
+        ),
+        Rule::taut("empty", op!("tree", empty())),
+    ]
+}
+
+pub fn sum_rules() -> Vec<Rule> {
+    vec![
+        Rule::taut("s1", op!("sum", var("n"), zero(), var("n"))),
+        Rule::new(
+            "s2",
+            vec![op!("sum", var("n"), var("m"), var("p"))],
+            op!("sum", var("n"), succ(var("m")), succ(var("p"))),
+        ),
+    ]
+}
+
+pub fn max_rules() -> Vec<Rule> {
+    vec![
+        Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+        Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
+        Rule::new(
+            "max3",
+            vec![op!("max", var("n"), var("m"), var("p"))],
+            op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
+        ),
+    ]
+}
+
+// The height of a tree, which needs `max_rules`
+pub fn hgt_rules() -> Vec<Rule> {
+    vec![
+        Rule::taut("h1", op!("hgt", empty(), zero())),
+        Rule::new(
+            "h2",
+            vec![
+                op!("hgt", var("t1"), var("n1")),
+                op!("hgt", var("t2"), var("n2")),
+                op!("max", var("n1"), var("n2"), var("n")),
+            ],
+            op!("hgt", node(var("t1"), var("t2")), succ(var("n"))),
+        ),
+    ]
+}
+
+pub fn rules(groups: &[fn() -> Vec<Rule>]) -> Vec<Rule> {
+    groups.iter().flat_map(|group| group()).collect()
+}
+
+pub fn nat() -> FormalSystem {
+    FormalSystem::new(
+        rules(&[nat_rules, tree_rules, sum_rules, max_rules, hgt_rules]),
+        8,
+    )
+}
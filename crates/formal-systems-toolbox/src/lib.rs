@@ -1,10 +1,38 @@
 //mod pratt;
 //mod ast;
+pub mod analysis;
+#[cfg(feature = "async")]
+pub mod async_verify;
+pub mod cache;
+pub mod compiled;
+#[cfg(feature = "config")]
+pub mod config;
+mod engine;
+pub mod enumerate;
+pub mod export;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fixtures;
+pub mod operators;
+pub mod parser;
+pub mod repl;
+pub mod report;
+#[cfg(feature = "serde")]
+pub mod schema;
+pub mod validate;
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::hash_map::HashMap;
 use std::collections::HashSet;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::io;
 use std::iter::zip;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
+use compiled::RuleIndex;
+use engine::{Pending, Search};
 use itertools::Itertools;
 
 #[macro_export]
@@ -12,146 +40,176 @@ macro_rules! op {
     ($name:expr,$($generic:expr),*) => {
         Judgement::Operator {
             predicate: $name.to_string(),
-            subjects: vec![$($generic),*],
+            subjects: vec![$(::std::sync::Arc::new($generic)),*],
         }
     };
     ($name:expr) => { op!($name,) };
 }
 
-pub type UnificationTable = HashMap<String, Judgement>;
+pub type UnificationTable<S = String> = HashMap<S, Judgement<S>>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Judgement {
-    Operator {
-        predicate: String,
-        subjects: Vec<Judgement>,
-    },
-    Variable(String),
-}
+// What predicates and variables are named with: `String`, unless a system is
+// built over interned symbols. Renaming rules apart makes up new names, so a
+// symbol has to be buildable from a string
+pub trait Symbol: AsRef<str> + Clone + Eq + Hash + Ord + Debug + for<'s> From<&'s str> {}
 
-impl Judgement {
-    pub fn operator(predicate: &str, subjects: Vec<Judgement>) -> Self {
-        Self::Operator {
-            predicate: String::from(predicate),
-            subjects,
-        }
-    }
+impl<S> Symbol for S where S: AsRef<str> + Clone + Eq + Hash + Ord + Debug + for<'s> From<&'s str> {}
 
-    pub fn variable(name: &str) -> Self {
-        Self::Variable(String::from(name))
-    }
+pub const DEFAULT_MAX_DERIVATION_HEIGHT: u16 = 16;
 
-    pub fn get_variables(&self) -> HashSet<String> {
-        use Judgement::*;
-        match self {
-            Variable(symbol) => HashSet::from([symbol.clone()]),
-            Operator {
-                predicate: _,
-                subjects,
-            } => subjects.iter().fold(HashSet::new(), |mut result, subject| {
-                result.extend(subject.get_variables());
-                result
-            }),
-        }
-    }
+// Number of recursive calls between two checks of the cancellation flag
+const CANCELLATION_CHECK_INTERVAL: usize = 64;
 
-    pub fn rename_variables<S>(
-        &self,
-        state: &mut S,
-        operation: &impl Fn(&mut S, String) -> String,
-    ) -> Self {
-        use Judgement::*;
-        match self {
-            Variable(symbol) => Variable(operation(state, symbol.clone())),
-            Operator {
-                predicate,
-                subjects,
-            } => Operator {
-                predicate: predicate.clone(),
-                subjects: subjects
-                    .iter()
-                    .map(|subject| subject.rename_variables(state, operation))
-                    .collect(),
-            },
-        }
-    }
+// The label of the leaves that `verify_under` closes with an assumption
+const ASSUMPTION_LABEL: &str = "hyp";
 
-    pub fn apply_substitution(&self, substitutions: &UnificationTable) -> Judgement {
-        use Judgement::*;
-        match self.clone() {
-            Variable(symbol) => {
-                if let Some(substitution) = substitutions.get(&symbol) {
-                    substitution.apply_substitution(substitutions)
-                } else {
-                    self.clone()
-                }
-            }
-            Operator {
-                predicate,
-                subjects,
-            } => Operator {
-                predicate,
-                subjects: subjects
-                    .iter()
-                    .map(|subject| subject.apply_substitution(substitutions))
-                    .collect(),
-            },
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Proof search cancelled")
     }
+}
 
-    pub fn variable_occurs_with_substitution(
-        &self,
+impl std::error::Error for Cancelled {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnifyError {
+    // Two operators without subjects, e.g. `zero()` and `empty()`
+    DistinctConstants {
+        left: String,
+        right: String,
+    },
+    DifferentPredicates {
+        left: String,
+        right: String,
+    },
+    // The same predicate applied to a different number of subjects
+    DifferentArities {
+        predicate: String,
+        left: usize,
+        right: usize,
+    },
+    // The variable would have to contain itself
+    Recursive {
         variable: String,
-        substitutions: &UnificationTable,
-    ) -> bool {
-        use Judgement::*;
+    },
+    // The variable would have to be bound, on a side that `Binding` keeps fixed
+    Fixed {
+        variable: String,
+    },
+}
+
+// Which sides of a unification may have their variables bound. A variable met
+// on a fixed side only unifies with itself, as a constant would
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    // Either side, the right one first when both are variables
+    Both,
+    // Only the left side, whose variables are distinct from those on the right
+    // even when they share a name, as in `matches`
+    Left,
+    // Neither side, which leaves syntactic equality
+    Neither,
+}
+
+impl Display for UnifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Variable(occurrence) => {
-                if let Some(substitution) = substitutions.get(occurrence.as_str()) {
-                    substitution.variable_occurs_with_substitution(variable, substitutions)
-                } else {
-                    occurrence.clone() == variable
-                }
+            UnifyError::DistinctConstants { left, right } => {
+                write!(f, "Distinct constants: {}() != {}()", left, right)
             }
-            Operator {
-                predicate: _,
-                subjects,
-            } => {
-                for subject in subjects {
-                    if subject.variable_occurs_with_substitution(variable.clone(), substitutions) {
-                        return true;
-                    }
-                }
-                false
+            UnifyError::DifferentPredicates { left, right } => {
+                write!(f, "Different predicates: {} != {}", left, right)
+            }
+            UnifyError::DifferentArities {
+                predicate,
+                left,
+                right,
+            } => write!(
+                f,
+                "Predicate {} applied to {} and {} subjects",
+                predicate, left, right
+            ),
+            UnifyError::Recursive { variable } => {
+                write!(f, "Recursive unification of {}", variable)
+            }
+            UnifyError::Fixed { variable } => {
+                write!(f, "Variable {} cannot be bound", variable)
             }
         }
     }
+}
 
-    pub fn unify(&self, other: &Judgement) -> Result<UnificationTable, String> {
-        let mut substitutions: UnificationTable = HashMap::new();
-        self.unify_with_substitution(other, &mut substitutions)?;
-        Ok(substitutions)
+impl std::error::Error for UnifyError {}
+
+// The rule, by name and position in its formal system, whose instantiation
+// introduced a variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Provenance {
+    pub rule: String,
+    pub position: usize,
+}
+
+// Where a rule starts in the file it was parsed from, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+// Subjects are reference counted, so cloning a judgement or rebuilding one of
+// its subjects shares the rest of the term. The count is atomic (`Arc` rather
+// than `Rc`) so judgements stay `Send` and `Sync`: `verify_async` and
+// `verify_cancellable` run the search on another thread
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Judgement<S = String> {
+    Operator {
+        predicate: S,
+        subjects: Vec<Arc<Judgement<S>>>,
+    },
+    // Carries, for variables introduced by instantiating a rule during search,
+    // the rule they come from. It is ignored when comparing judgements
+    Variable(S, Option<Arc<Provenance>>),
+    // Matches zero or more trailing subjects of an operator
+    Rest(S),
+    // A premise the search decides itself instead of deriving it by rules
+    Premise(Premise, Vec<Arc<Judgement<S>>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Premise {
+    // Holds as long as its two subjects cannot be made equal; it is checked
+    // again whenever the search binds more variables
+    Disequality,
+    // Holds when any of its subjects can be derived; derivations show the
+    // alternative that was proved in its place
+    OneOf,
+}
+
+impl Premise {
+    // How the premise is written, in place of a predicate
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Premise::Disequality => "!=",
+            Premise::OneOf => "∨",
+        }
     }
+}
 
-    fn unify_with_substitution(
-        &self,
-        other: &Judgement,
-        substitutions: &mut UnificationTable,
-    ) -> Result<(), String> {
+impl<S: PartialEq> PartialEq for Judgement<S> {
+    fn eq(&self, other: &Self) -> bool {
         use Judgement::*;
-        //println!("Unifying {} with {}", left, other);
         match (self, other) {
-            (Variable(symbol_left), Variable(symbol_right)) if symbol_left == symbol_right => {}
-            (judgement, Variable(symbol)) | (Variable(symbol), judgement) => {
-                if let Some(substitution) = substitutions.get(&symbol.clone()) {
-                    judgement.unify_with_substitution(&substitution.clone(), substitutions)?;
-                }
-
-                if judgement.variable_occurs_with_substitution(symbol.clone(), substitutions) {
-                    return Err("Recursive unification!".into());
-                }
-                substitutions.insert(symbol.clone(), judgement.clone());
-            }
             (
                 Operator {
                     predicate: predicate_left,
@@ -161,541 +219,4297 @@ impl Judgement {
                     predicate: predicate_right,
                     subjects: subjects_right,
                 },
-            ) => {
-                if predicate_left != predicate_right {
-                    return Err(format!(
-                        "Different predicates: {} != {}",
-                        predicate_left, predicate_right,
-                    ));
-                } else if subjects_left.len() != subjects_right.len() {
-                    return Err(format!(
-                        "Predicates with different arieties: {} and {}",
-                        subjects_left.len(),
-                        subjects_right.len()
-                    ));
-                }
-
-                for (left, right) in zip(subjects_left, subjects_right) {
-                    left.unify_with_substitution(right, substitutions)?;
-                }
+            ) => predicate_left == predicate_right && subjects_left == subjects_right,
+            (Premise(left, subjects_left), Premise(right, subjects_right)) => {
+                left == right && subjects_left == subjects_right
             }
+            (Variable(left, _), Variable(right, _)) | (Rest(left), Rest(right)) => left == right,
+            _ => false,
         }
-
-        Ok(())
     }
 }
 
-impl Display for Judgement {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<S: Eq> Eq for Judgement<S> {}
+
+// Variables come first, then rest variables, each by name, then operators by
+// predicate, by arity and by their subjects in turn, then premises the same
+// way. Provenance is ignored, as it is by `Eq`
+impl<S: Ord> Ord for Judgement<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
         use Judgement::*;
-        match self {
-            Variable(symbol) => f.write_str(symbol)?,
-            Operator {
-                predicate,
-                subjects,
-            } => {
-                f.write_str(&predicate)?;
-                f.write_str("(")?;
-                for (i, subject) in subjects.iter().enumerate() {
-                    f.write_str(format!("{}", subject).as_str())?;
-                    if i != subjects.len() - 1 {
-                        f.write_str(", ")?;
-                    }
-                }
-                f.write_str(")")?;
-            }
+        match (self, other) {
+            (Variable(left, _), Variable(right, _)) | (Rest(left), Rest(right)) => left.cmp(right),
+            (
+                Operator {
+                    predicate: predicate_left,
+                    subjects: subjects_left,
+                },
+                Operator {
+                    predicate: predicate_right,
+                    subjects: subjects_right,
+                },
+            ) => predicate_left
+                .cmp(predicate_right)
+                .then(subjects_left.len().cmp(&subjects_right.len()))
+                .then_with(|| subjects_left.cmp(subjects_right)),
+            (Premise(left, subjects_left), Premise(right, subjects_right)) => left
+                .cmp(right)
+                .then(subjects_left.len().cmp(&subjects_right.len()))
+                .then_with(|| subjects_left.cmp(subjects_right)),
+            (Variable(..), _)
+            | (Rest(_), Operator { .. } | Premise(..))
+            | (Operator { .. }, Premise(..)) => Ordering::Less,
+            _ => Ordering::Greater,
         }
-
-        Ok(())
     }
 }
 
-#[derive(Clone)]
-pub struct Derivation {
-    premises: Vec<Derivation>,
-    conclusion: Judgement,
-    rule_label: String,
+impl<S: Ord> PartialOrd for Judgement<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-impl Derivation {
-    pub fn apply_substitution(&self, substitutions: &UnificationTable) -> Self {
-        Self {
-            premises: self
-                .premises
-                .iter()
-                .map(|premise| premise.apply_substitution(substitutions))
-                .collect(),
-            conclusion: self.conclusion.apply_substitution(substitutions),
-            rule_label: self.rule_label.clone(),
+// Rest variables are bound to a nameless operator holding the matched subjects
+pub const SEQUENCE_PREDICATE: &str = "";
+
+// A premise `¬(j)` is meant to hold when `j` cannot be derived. The search does
+// not decide such premises yet, but `FormalSystem::is_stratified` accounts for
+// them
+pub const NEGATION_PREDICATE: &str = "¬";
+
+// For `FormalSystem::verify_schematic`, universal variables are replaced by
+// constants named with this prefix, which rules files cannot mention
+const UNIVERSAL_PREFIX: &str = "∀";
+
+impl<S: Symbol> Judgement<S> {
+    pub fn operator(predicate: &str, subjects: Vec<Judgement<S>>) -> Self {
+        Self::Operator {
+            predicate: S::from(predicate),
+            subjects: subjects.into_iter().map(Arc::new).collect(),
         }
     }
 
-    pub fn pretty_print(&self) -> Vec<String> {
-        let mut lines: Vec<String> = vec![];
-
-        let mut premises_results: Vec<Vec<String>> = vec![];
-        let mut premises_width: usize = 0;
-        let mut max_premise_height: usize = 0;
+    pub fn variable(name: &str) -> Self {
+        Self::Variable(S::from(name), None)
+    }
 
-        let conclusion_string = self.conclusion.to_string();
+    pub fn rest(name: &str) -> Self {
+        Self::Rest(S::from(name))
+    }
 
-        let rule_label = self.rule_label.clone();
-        let conclusion_width: usize = conclusion_string.len();
-        let padded_width = conclusion_width + rule_label.len();
+    pub fn sequence(subjects: Vec<Judgement<S>>) -> Self {
+        Self::operator(SEQUENCE_PREDICATE, subjects)
+    }
 
-        for (premise, last) in self
-            .premises
-            .iter()
-            .enumerate()
-            .map(|(i, el)| (el, i == self.premises.len() - 1))
-        {
-            let premise_tree = if !last {
-                premise
-                    .pretty_print()
-                    .into_iter()
-                    .map(|line| line + "  ")
-                    .collect()
-            } else {
-                premise.pretty_print()
-            };
+    pub fn diseq(left: Judgement<S>, right: Judgement<S>) -> Self {
+        Self::Premise(Premise::Disequality, vec![Arc::new(left), Arc::new(right)])
+    }
 
-            if premise_tree.len() > max_premise_height {
-                max_premise_height = premise_tree.len();
+    pub fn as_disequality(&self) -> Option<(&Judgement<S>, &Judgement<S>)> {
+        match self {
+            Self::Premise(Premise::Disequality, subjects) if subjects.len() == 2 => {
+                Some((&subjects[0], &subjects[1]))
             }
-
-            premises_width += premise_tree.get(0).map(|s| s.len()).unwrap_or(0);
-            premises_results.push(premise_tree);
+            _ => None,
         }
+    }
 
-        let max_width = std::cmp::max(premises_width, padded_width);
-        let bar_width = std::cmp::max(max_width, conclusion_width + 2);
-        let max_width = std::cmp::max(max_width, bar_width + rule_label.len());
-
-        lines.push(format!(
-            "{}{: ^width$}",
-            " ".repeat(rule_label.len()),
-            conclusion_string,
-            width = max_width - rule_label.len()
-        ));
-        lines.push(format!(
-            "{}{: ^width$}",
-            rule_label,
-            ("-".repeat(bar_width)).as_str(),
-            width = max_width - rule_label.len()
-        ));
-
-        // Merge
-        for i in 0..max_premise_height {
-            let mut line = String::new();
+    pub fn negation(judgement: Judgement<S>) -> Self {
+        Self::operator(NEGATION_PREDICATE, vec![judgement])
+    }
 
-            for premise_tree in &premises_results {
-                if let Some(premise_line) = premise_tree.get(i) {
-                    line.push_str(premise_line.as_str());
-                } else {
-                    line.push_str(" ".repeat(premise_tree.get(0).unwrap().len()).as_str());
-                }
+    pub fn as_negation(&self) -> Option<&Judgement<S>> {
+        match self {
+            Self::Operator {
+                predicate,
+                subjects,
+            } if predicate.as_ref() == NEGATION_PREDICATE && subjects.len() == 1 => {
+                Some(&subjects[0])
             }
-
-            lines.push(format!("{: ^width$}", line, width = max_width));
+            _ => None,
         }
+    }
 
-        lines
+    pub fn one_of(alternatives: Vec<Judgement<S>>) -> Self {
+        Self::Premise(
+            Premise::OneOf,
+            alternatives.into_iter().map(Arc::new).collect(),
+        )
     }
 
-    pub fn to_string_tree(&self) -> String {
-        let mut lines = self.pretty_print();
-        let mut result = String::from("\n");
+    pub fn as_one_of(&self) -> Option<&[Arc<Judgement<S>>]> {
+        match self {
+            Self::Premise(Premise::OneOf, subjects) => Some(subjects),
+            _ => None,
+        }
+    }
 
-        lines.reverse();
-        for line in &lines {
-            result += line;
-            result.push_str("\n");
+    pub fn as_sequence(&self) -> Option<&[Arc<Judgement<S>>]> {
+        match self {
+            Self::Operator {
+                predicate,
+                subjects,
+            } if predicate.as_ref() == SEQUENCE_PREDICATE => Some(subjects),
+            _ => None,
         }
+    }
 
-        result
+    // Bottom-up traversal: variables (rest ones included) are mapped by
+    // `on_var`, operators by `on_op` from their predicate and folded subjects.
+    // Premises are given to `on_op` under their symbol
+    pub fn fold<T>(&self, on_var: &impl Fn(&str) -> T, on_op: &impl Fn(&str, Vec<T>) -> T) -> T {
+        use Judgement::*;
+        match self {
+            Variable(symbol, _) | Rest(symbol) => on_var(symbol.as_ref()),
+            Operator {
+                predicate,
+                subjects,
+            } => on_op(
+                predicate.as_ref(),
+                subjects
+                    .iter()
+                    .map(|subject| subject.fold(on_var, on_op))
+                    .collect(),
+            ),
+            Premise(premise, subjects) => on_op(
+                premise.symbol(),
+                subjects
+                    .iter()
+                    .map(|subject| subject.fold(on_var, on_op))
+                    .collect(),
+            ),
+        }
     }
-}
 
-#[derive(Clone)]
-pub struct Rule {
-    name: String,
-    premises: Vec<Judgement>,
-    conclusion: Judgement,
-}
+    pub fn get_variables(&self) -> HashSet<S> {
+        self.fold(
+            &|symbol| HashSet::from([S::from(symbol)]),
+            &|_, subjects| subjects.into_iter().flatten().collect(),
+        )
+    }
 
-impl Rule {
-    pub fn new(name: &str, premises: Vec<Judgement>, conclusion: Judgement) -> Self {
-        Self {
-            name: String::from(name),
-            premises,
-            conclusion,
+    pub fn is_ground(&self) -> bool {
+        match self {
+            Judgement::Operator { subjects, .. } | Judgement::Premise(_, subjects) => {
+                subjects.iter().all(|subject| subject.is_ground())
+            }
+            _ => false,
         }
     }
 
-    pub fn taut(name: &str, judgement: Judgement) -> Self {
-        Self::new(name, vec![], judgement)
+    pub fn size(&self) -> usize {
+        self.fold(&|_| 1, &|_, subjects| 1 + subjects.iter().sum::<usize>())
     }
 
-    pub fn rename_variables<S>(
-        &self,
-        state: &mut S,
-        operation: &impl Fn(&mut S, String) -> String,
-    ) -> Self {
-        Self {
-            name: self.name.clone(),
-            premises: self
-                .premises
+    // How far apart two judgements are: nothing where they agree, otherwise
+    // the sizes of the differing subterms below their common operators
+    fn distance(&self, other: &Judgement<S>) -> usize {
+        match (self, other) {
+            _ if self == other => 0,
+            (
+                Judgement::Operator {
+                    predicate,
+                    subjects,
+                },
+                Judgement::Operator {
+                    predicate: other_predicate,
+                    subjects: other_subjects,
+                },
+            ) if predicate == other_predicate && subjects.len() == other_subjects.len() => subjects
                 .iter()
-                .map(|premise| premise.rename_variables(state, operation))
-                .collect(),
-            conclusion: self.conclusion.rename_variables(state, operation),
+                .zip(other_subjects)
+                .map(|(subject, other)| subject.distance(other))
+                .sum(),
+            _ => self.size() + other.size(),
         }
     }
-}
 
-impl Display for Rule {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("(")?;
-        for (i, premise) in self.premises.iter().enumerate() {
-            f.write_str(format!("{}", premise).as_str())?;
-            if i != self.premises.len() - 1 {
-                f.write_str(", ")?;
+    // Replaces the subject at `index`, sharing all the other subjects.
+    // Panics if the judgement is not an operator or the index is out of bounds
+    pub fn with_subject(&self, index: usize, subject: Judgement<S>) -> Judgement<S> {
+        match self {
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => {
+                let mut subjects = subjects.clone();
+                subjects[index] = Arc::new(subject);
+                Judgement::Operator {
+                    predicate: predicate.clone(),
+                    subjects,
+                }
             }
+            _ => panic!("{} has no subjects", self),
         }
-        f.write_str(")->")?;
-        f.write_str(format!("{}", self.conclusion).as_str())?;
-
-        Ok(())
     }
-}
 
-pub struct FormalSystem {
-    axioms: Vec<Rule>,
-    max_derivation_height: u16,
-}
+    // Replaces the subject reached by following `path`, rebuilding only the
+    // operators along the way
+    pub fn with_subject_at(&self, path: &[usize], subject: Judgement<S>) -> Judgement<S> {
+        match path {
+            [] => subject,
+            [index, rest @ ..] => match self {
+                Judgement::Operator { subjects, .. } => {
+                    let inner = subjects[*index].with_subject_at(rest, subject);
+                    self.with_subject(*index, inner)
+                }
+                _ => panic!("{} has no subjects", self),
+            },
+        }
+    }
 
-impl FormalSystem {
-    pub fn new(axioms: Vec<Rule>, max_derivation_height: u16) -> Self {
-        Self {
-            axioms,
-            max_derivation_height,
+    // The paths, as taken by `with_subject_at`, to the outermost subterms
+    // where `self` and `other` differ, in order
+    pub fn differences(&self, other: &Judgement<S>) -> Vec<Vec<usize>> {
+        match (self, other) {
+            (
+                Judgement::Operator {
+                    predicate,
+                    subjects,
+                },
+                Judgement::Operator {
+                    predicate: other_predicate,
+                    subjects: other_subjects,
+                },
+            ) if predicate == other_predicate && subjects.len() == other_subjects.len() => {
+                zip(subjects, other_subjects)
+                    .enumerate()
+                    .flat_map(|(i, (subject, other_subject))| {
+                        subject
+                            .differences(other_subject)
+                            .into_iter()
+                            .map(move |mut path| {
+                                path.insert(0, i);
+                                path
+                            })
+                    })
+                    .collect()
+            }
+            _ if self == other => vec![],
+            _ => vec![vec![]],
         }
     }
 
-    pub fn verify(&self, judgement: &Judgement) -> Option<Derivation> {
-        let (proof, substitutions) = self.verify_recursion(
-            &mut HashSet::new(),
-            &UnificationTable::new(),
-            judgement,
-            0,
-        )?;
-        Some(proof.apply_substitution(&substitutions))
+    pub fn rename_variables<T>(&self, state: &mut T, operation: &impl Fn(&mut T, S) -> S) -> Self {
+        use Judgement::*;
+        match self {
+            Variable(symbol, provenance) => {
+                Variable(operation(state, symbol.clone()), provenance.clone())
+            }
+            Rest(symbol) => Rest(operation(state, symbol.clone())),
+            Operator {
+                predicate,
+                subjects,
+            } => Operator {
+                predicate: predicate.clone(),
+                subjects: subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.rename_variables(state, operation)))
+                    .collect(),
+            },
+            Premise(premise, subjects) => Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.rename_variables(state, operation)))
+                    .collect(),
+            ),
+        }
     }
 
-    fn get_possible_derivation_paths(
-        &self,
-        substitutions: &UnificationTable,
-        judgement: &Judgement,
-    ) -> Vec<(UnificationTable, Rule)> {
-        let mut result: Vec<(UnificationTable, Rule)> = vec![];
+    // Renames the variables shared with `other` to fresh names, used by
+    // neither judgement, so that the two can be unified independently
+    pub fn rename_apart(&self, other: &Judgement<S>) -> Judgement<S> {
+        let clashing = other.get_variables();
+        let mut taken = self.get_variables();
+        taken.extend(clashing.iter().cloned());
+        self.rename_variables(&mut HashMap::<S, S>::new(), &|renamed, symbol| {
+            fresh_name(renamed, symbol, &clashing, &taken)
+        })
+    }
 
-        let mut variables = judgement.get_variables();
-        for (key, value) in substitutions.iter() {
-            variables.insert(key.clone());
-            variables.extend(value.get_variables());
+    pub fn provenance(&self) -> Option<&Provenance> {
+        match self {
+            Judgement::Variable(_, provenance) => provenance.as_deref(),
+            _ => None,
+        }
+    }
+
+    // Tags every variable with `provenance`, replacing any previous tag
+    pub fn with_provenance(&self, provenance: &Arc<Provenance>) -> Self {
+        use Judgement::*;
+        match self {
+            Variable(symbol, _) => Variable(symbol.clone(), Some(provenance.clone())),
+            Rest(_) => self.clone(),
+            Operator {
+                predicate,
+                subjects,
+            } => Operator {
+                predicate: predicate.clone(),
+                subjects: subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.with_provenance(provenance)))
+                    .collect(),
+            },
+            Premise(premise, subjects) => Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.with_provenance(provenance)))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Follows the bindings of a variable standing for the whole judgement
+    pub fn resolve<'a>(&'a self, substitutions: &'a UnificationTable<S>) -> &'a Judgement<S> {
+        let mut judgement = self;
+        while let Judgement::Variable(symbol, _) | Judgement::Rest(symbol) = judgement {
+            match substitutions.get(symbol) {
+                Some(substitution) => judgement = substitution,
+                None => break,
+            }
+        }
+        judgement
+    }
+
+    pub fn map_predicates(&self, operation: &impl Fn(&str) -> String) -> Self {
+        use Judgement::*;
+        match self {
+            Operator {
+                predicate,
+                subjects,
+            } => Operator {
+                predicate: if [SEQUENCE_PREDICATE, NEGATION_PREDICATE].contains(&predicate.as_ref())
+                {
+                    predicate.clone()
+                } else {
+                    S::from(&operation(predicate.as_ref()))
+                },
+                subjects: subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.map_predicates(operation)))
+                    .collect(),
+            },
+            Premise(premise, subjects) => Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.map_predicates(operation)))
+                    .collect(),
+            ),
+            _ => self.clone(),
         }
+    }
 
-        for axiom in &self.axioms {
-            //println!(">  {}", axiom);
-            let axiom = axiom.rename_variables(&mut (), &|_, symbol| {
-                let mut new_symbol = symbol.clone();
-                while variables.contains(new_symbol.as_str()) {
-                    new_symbol = next_name(new_symbol.as_str());
+    // Collapses nested applications of an associative predicate into a single
+    // one, so that `+(+(a, b), c)` becomes `+(a, b, c)`
+    pub fn flatten_associative(&self, associative: &HashSet<S>) -> Self {
+        use Judgement::*;
+        match self {
+            Operator {
+                predicate,
+                subjects,
+            } => {
+                let mut flattened: Vec<Arc<Judgement<S>>> = vec![];
+                for subject in subjects {
+                    match subject.flatten_associative(associative) {
+                        Operator {
+                            predicate: inner,
+                            subjects,
+                        } if inner == *predicate && associative.contains(predicate) => {
+                            flattened.extend(subjects)
+                        }
+                        subject => flattened.push(Arc::new(subject)),
+                    }
                 }
-                new_symbol
-            });
-            //println!(">> {}", axiom);
-            let mut unification_substitutions = substitutions.clone();
+                Operator {
+                    predicate: predicate.clone(),
+                    subjects: flattened,
+                }
+            }
+            Premise(premise, subjects) => Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.flatten_associative(associative)))
+                    .collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
 
-            match judgement
-                .unify_with_substitution(&axiom.conclusion, &mut unification_substitutions)
-            {
-                Ok(_) => {
-                    result.push((unification_substitutions.clone(), axiom.clone()));
+    pub fn apply_substitution(&self, substitutions: &UnificationTable<S>) -> Judgement<S> {
+        use Judgement::*;
+        match self.clone() {
+            Variable(symbol, _) => {
+                if let Some(substitution) = substitutions.get(&symbol) {
+                    substitution.apply_substitution(substitutions)
+                } else {
+                    self.clone()
+                }
+            }
+            Rest(symbol) => {
+                if let Some(substitution) = substitutions.get(&symbol) {
+                    substitution.apply_substitution(substitutions)
+                } else {
+                    self.clone()
+                }
+            }
+            Operator {
+                predicate,
+                subjects,
+            } => {
+                let mut result = vec![];
+                for subject in &subjects {
+                    let substituted = subject.apply_substitution(substitutions);
+                    match (subject.as_ref(), substituted.as_sequence()) {
+                        (Rest(_), Some(sequence)) => result.extend_from_slice(sequence),
+                        _ => result.push(Arc::new(substituted)),
+                    }
+                }
+                Operator {
+                    predicate,
+                    subjects: result,
+                }
+            }
+            Premise(premise, subjects) => Premise(
+                premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.apply_substitution(substitutions)))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Replaces the listed variables all at once, leaving the others intact.
+    // Unlike `apply_substitution`, the replacements are not themselves rewritten,
+    // so `n` may be bound to a term mentioning `n`
+    pub fn instantiate(&self, bindings: &[(&str, Judgement<S>)]) -> Judgement<S> {
+        use Judgement::*;
+        match self {
+            Variable(symbol, _) | Rest(symbol) => bindings
+                .iter()
+                .find(|(variable, _)| *variable == symbol.as_ref())
+                .map_or_else(|| self.clone(), |(_, value)| value.clone()),
+            Operator {
+                predicate,
+                subjects,
+            } => {
+                let mut result = vec![];
+                for subject in subjects {
+                    let instance = subject.instantiate(bindings);
+                    match (subject.as_ref(), instance.as_sequence()) {
+                        (Rest(_), Some(sequence)) => result.extend_from_slice(sequence),
+                        _ => result.push(Arc::new(instance)),
+                    }
+                }
+                Operator {
+                    predicate: predicate.clone(),
+                    subjects: result,
+                }
+            }
+            Premise(premise, subjects) => Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.instantiate(bindings)))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Replaces the `universal` variables by constants, which unification can
+    // neither bind nor make equal to anything but themselves
+    fn fix_universal(&self, universal: &[&str]) -> Judgement<S> {
+        match self {
+            Judgement::Variable(symbol, _) if universal.contains(&symbol.as_ref()) => {
+                Judgement::operator(&format!("{}{}", UNIVERSAL_PREFIX, symbol.as_ref()), vec![])
+            }
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => Judgement::Operator {
+                predicate: predicate.clone(),
+                subjects: subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.fix_universal(universal)))
+                    .collect(),
+            },
+            Judgement::Premise(premise, subjects) => Judgement::Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.fix_universal(universal)))
+                    .collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    fn release_universal(&self) -> Judgement<S> {
+        match self {
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => match predicate.as_ref().strip_prefix(UNIVERSAL_PREFIX) {
+                Some(symbol) if subjects.is_empty() => Judgement::variable(symbol),
+                _ => Judgement::Operator {
+                    predicate: predicate.clone(),
+                    subjects: subjects
+                        .iter()
+                        .map(|subject| Arc::new(subject.release_universal()))
+                        .collect(),
+                },
+            },
+            Judgement::Premise(premise, subjects) => Judgement::Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(subject.release_universal()))
+                    .collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn variable_occurs_with_substitution(
+        &self,
+        variable: S,
+        substitutions: &UnificationTable<S>,
+    ) -> bool {
+        use Judgement::*;
+        match self {
+            Variable(occurrence, _) | Rest(occurrence) => {
+                if let Some(substitution) = substitutions.get(occurrence) {
+                    substitution.variable_occurs_with_substitution(variable, substitutions)
+                } else {
+                    occurrence.clone() == variable
+                }
+            }
+            Operator {
+                predicate: _,
+                subjects,
+            }
+            | Premise(_, subjects) => {
+                for subject in subjects {
+                    if subject.variable_occurs_with_substitution(variable.clone(), substitutions) {
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    pub fn unify(&self, other: &Judgement<S>) -> Result<UnificationTable<S>, UnifyError> {
+        self.unify_with_binding(other, Binding::Both)
+    }
+
+    pub fn unify_with_binding(
+        &self,
+        other: &Judgement<S>,
+        binding: Binding,
+    ) -> Result<UnificationTable<S>, UnifyError> {
+        let mut substitutions: UnificationTable<S> = HashMap::new();
+        self.unify_with_substitution(other, binding, &mut substitutions)?;
+        Ok(substitutions)
+    }
+
+    // Unifies under the bindings of `context`, returning them extended with the
+    // new ones and leaving `context` as it was
+    pub fn would_unify(
+        &self,
+        other: &Judgement<S>,
+        context: &UnificationTable<S>,
+    ) -> Option<UnificationTable<S>> {
+        let mut substitutions = context.clone();
+        self.unify_with_substitution(other, Binding::Both, &mut substitutions)
+            .ok()
+            .map(|_| substitutions)
+    }
+
+    // The substitution, binding only variables of `self`, that turns it into
+    // `instance`. The variables of `instance` are left untouched
+    pub fn matches(&self, instance: &Judgement<S>) -> Option<UnificationTable<S>> {
+        let mut substitutions = self.unify_with_binding(instance, Binding::Left).ok()?;
+        // A variable shared by both sides may be bound to itself, which would
+        // send `apply_substitution` into a loop
+        substitutions.retain(|variable, value| {
+            !matches!(value, Judgement::Variable(symbol, _) | Judgement::Rest(symbol) if symbol == variable)
+        });
+        Some(substitutions)
+    }
+
+    pub fn is_instance_of(&self, pattern: &Judgement<S>) -> bool {
+        pattern.matches(self).is_some()
+    }
+
+    // Whether the two judgements are equal up to a renaming of their variables
+    pub fn is_variant_of(&self, other: &Judgement<S>) -> bool {
+        self.is_instance_of(other) && other.is_instance_of(self)
+    }
+
+    fn unify_with_substitution(
+        &self,
+        other: &Judgement<S>,
+        binding: Binding,
+        substitutions: &mut UnificationTable<S>,
+    ) -> Result<(), UnifyError> {
+        use Judgement::*;
+        //println!("Unifying {} with {}", left, other);
+        match (self, other) {
+            (Variable(symbol_left, _), Variable(symbol_right, _))
+            | (Rest(symbol_left), Rest(symbol_right))
+                if symbol_left == symbol_right && binding != Binding::Left => {}
+            (judgement, Variable(symbol, _) | Rest(symbol)) if binding == Binding::Both => {
+                judgement.bind(symbol, substitutions)?
+            }
+            (Variable(symbol, _) | Rest(symbol), judgement) if binding == Binding::Both => {
+                judgement.bind(symbol, substitutions)?
+            }
+            (Variable(symbol, _) | Rest(symbol), judgement) if binding == Binding::Left => {
+                // Bindings hold terms of the right side, compared as they are
+                match substitutions.get(symbol) {
+                    Some(substitution) => substitution.clone().unify_with_substitution(
+                        judgement,
+                        Binding::Neither,
+                        substitutions,
+                    )?,
+                    None => {
+                        substitutions.insert(symbol.clone(), judgement.clone());
+                    }
+                }
+            }
+            (Variable(symbol, _) | Rest(symbol), _) | (_, Variable(symbol, _) | Rest(symbol)) => {
+                return Err(UnifyError::Fixed {
+                    variable: symbol.as_ref().to_string(),
+                })
+            }
+            (
+                Operator {
+                    predicate: predicate_left,
+                    subjects: subjects_left,
+                },
+                Operator {
+                    predicate: predicate_right,
+                    subjects: subjects_right,
+                },
+            ) => {
+                if predicate_left != predicate_right {
+                    return Err(if subjects_left.is_empty() && subjects_right.is_empty() {
+                        UnifyError::DistinctConstants {
+                            left: predicate_left.as_ref().to_string(),
+                            right: predicate_right.as_ref().to_string(),
+                        }
+                    } else {
+                        UnifyError::DifferentPredicates {
+                            left: predicate_left.as_ref().to_string(),
+                            right: predicate_right.as_ref().to_string(),
+                        }
+                    });
+                }
+
+                let Some(pairs) = align_subjects(subjects_left, subjects_right) else {
+                    return Err(UnifyError::DifferentArities {
+                        predicate: predicate_left.as_ref().to_string(),
+                        left: subjects_left.len(),
+                        right: subjects_right.len(),
+                    });
+                };
+
+                for (left, right) in &pairs {
+                    left.unify_with_substitution(right, binding, substitutions)?;
+                }
+            }
+            (Premise(premise_left, subjects_left), Premise(premise_right, subjects_right)) => {
+                if premise_left != premise_right {
+                    return Err(UnifyError::DifferentPredicates {
+                        left: String::from(premise_left.symbol()),
+                        right: String::from(premise_right.symbol()),
+                    });
+                }
+                if subjects_left.len() != subjects_right.len() {
+                    return Err(UnifyError::DifferentArities {
+                        predicate: String::from(premise_left.symbol()),
+                        left: subjects_left.len(),
+                        right: subjects_right.len(),
+                    });
+                }
+                for (left, right) in zip(subjects_left, subjects_right) {
+                    left.unify_with_substitution(right, binding, substitutions)?;
                 }
-                Err(_e) => {}
             }
+            (Operator { predicate, .. }, Premise(premise, _)) => {
+                return Err(UnifyError::DifferentPredicates {
+                    left: predicate.as_ref().to_string(),
+                    right: String::from(premise.symbol()),
+                })
+            }
+            (Premise(premise, _), Operator { predicate, .. }) => {
+                return Err(UnifyError::DifferentPredicates {
+                    left: String::from(premise.symbol()),
+                    right: predicate.as_ref().to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    // Binds `symbol` to `self`, consistently with the binding it may already have
+    fn bind(&self, symbol: &S, substitutions: &mut UnificationTable<S>) -> Result<(), UnifyError> {
+        if let Some(substitution) = substitutions.get(symbol) {
+            self.unify_with_substitution(&substitution.clone(), Binding::Both, substitutions)?;
+        }
+
+        if self.variable_occurs_with_substitution(symbol.clone(), substitutions) {
+            return Err(UnifyError::Recursive {
+                variable: symbol.as_ref().to_string(),
+            });
         }
+        substitutions.insert(symbol.clone(), self.clone());
+        Ok(())
+    }
+
+    // The first two subjects of a commutative predicate may be exchanged
+    pub fn eq_modulo(&self, other: &Judgement<S>, commutative: &HashSet<S>) -> bool {
+        use Judgement::*;
+        match (self, other) {
+            (Variable(left, _), Variable(right, _)) | (Rest(left), Rest(right)) => left == right,
+            (
+                Operator {
+                    predicate: predicate_left,
+                    subjects: subjects_left,
+                },
+                Operator {
+                    predicate: predicate_right,
+                    subjects: subjects_right,
+                },
+            ) => {
+                if predicate_left != predicate_right || subjects_left.len() != subjects_right.len()
+                {
+                    return false;
+                }
+
+                commuted_subjects(predicate_right, subjects_right, commutative)
+                    .iter()
+                    .any(|subjects_right| {
+                        zip(subjects_left, subjects_right)
+                            .all(|(left, right)| left.eq_modulo(right, commutative))
+                    })
+            }
+            (Premise(premise_left, subjects_left), Premise(premise_right, subjects_right)) => {
+                premise_left == premise_right
+                    && subjects_left.len() == subjects_right.len()
+                    && zip(subjects_left, subjects_right)
+                        .all(|(left, right)| left.eq_modulo(right, commutative))
+            }
+            _ => false,
+        }
+    }
+
+    pub fn unify_modulo(
+        &self,
+        other: &Judgement<S>,
+        commutative: &HashSet<S>,
+    ) -> Vec<UnificationTable<S>> {
+        self.unify_modulo_with_substitution(other, &UnificationTable::new(), commutative)
+    }
+
+    fn unify_modulo_with_substitution(
+        &self,
+        other: &Judgement<S>,
+        substitutions: &UnificationTable<S>,
+        commutative: &HashSet<S>,
+    ) -> Vec<UnificationTable<S>> {
+        use Judgement::*;
+        match (self, other) {
+            (Variable(symbol_left, _), Variable(symbol_right, _))
+            | (Rest(symbol_left), Rest(symbol_right))
+                if symbol_left == symbol_right =>
+            {
+                vec![substitutions.clone()]
+            }
+            (judgement, Variable(symbol, _))
+            | (Variable(symbol, _), judgement)
+            | (judgement, Rest(symbol))
+            | (Rest(symbol), judgement) => {
+                let candidates = if let Some(substitution) = substitutions.get(symbol) {
+                    judgement.unify_modulo_with_substitution(
+                        substitution,
+                        substitutions,
+                        commutative,
+                    )
+                } else {
+                    vec![substitutions.clone()]
+                };
+
+                candidates
+                    .into_iter()
+                    .filter_map(|mut candidate| {
+                        if judgement.variable_occurs_with_substitution(symbol.clone(), &candidate) {
+                            None
+                        } else {
+                            candidate.insert(symbol.clone(), judgement.clone());
+                            Some(candidate)
+                        }
+                    })
+                    .collect()
+            }
+            (
+                Operator {
+                    predicate: predicate_left,
+                    subjects: subjects_left,
+                },
+                Operator {
+                    predicate: predicate_right,
+                    subjects: subjects_right,
+                },
+            ) => {
+                if predicate_left != predicate_right {
+                    return vec![];
+                }
+
+                let mut result: Vec<UnificationTable<S>> = vec![];
+                for subjects_right in
+                    commuted_subjects(predicate_right, subjects_right, commutative)
+                {
+                    let Some(pairs) = align_subjects(subjects_left, &subjects_right) else {
+                        continue;
+                    };
+
+                    let mut tables = vec![substitutions.clone()];
+                    for (left, right) in &pairs {
+                        tables = tables
+                            .iter()
+                            .flat_map(|table| {
+                                left.unify_modulo_with_substitution(right, table, commutative)
+                            })
+                            .collect();
+                    }
+
+                    for table in tables {
+                        if !result.contains(&table) {
+                            result.push(table);
+                        }
+                    }
+                }
+                result
+            }
+            (Premise(premise_left, subjects_left), Premise(premise_right, subjects_right))
+                if premise_left == premise_right && subjects_left.len() == subjects_right.len() =>
+            {
+                let mut tables = vec![substitutions.clone()];
+                for (left, right) in zip(subjects_left, subjects_right) {
+                    tables = tables
+                        .iter()
+                        .flat_map(|table| {
+                            left.unify_modulo_with_substitution(right, table, commutative)
+                        })
+                        .collect();
+                }
+                tables
+            }
+            _ => vec![],
+        }
+    }
+}
+
+// Pairs up the subjects of two operators, matching a trailing `Rest` against
+// the remaining subjects of the other side
+fn align_subjects<S: Symbol>(
+    left: &[Arc<Judgement<S>>],
+    right: &[Arc<Judgement<S>>],
+) -> Option<Vec<(Judgement<S>, Judgement<S>)>> {
+    use Judgement::*;
+
+    let split = |subjects: &[Arc<Judgement<S>>]| match subjects.last().map(Arc::as_ref) {
+        Some(Rest(symbol)) => (subjects.len() - 1, Some(symbol.clone())),
+        _ => (subjects.len(), None),
+    };
+    let (fixed_left, rest_left) = split(left);
+    let (fixed_right, rest_right) = split(right);
+
+    let rest_on_left = match (&rest_left, &rest_right) {
+        (Some(_), None) => true,
+        (Some(_), Some(_)) => fixed_left <= fixed_right,
+        _ => false,
+    };
+    let (fixed, rest, rest_subjects) = match (rest_left, rest_right) {
+        (None, None) => {
+            if fixed_left != fixed_right {
+                return None;
+            }
+            return Some(
+                zip(left, right)
+                    .map(|(l, r)| (l.as_ref().clone(), r.as_ref().clone()))
+                    .collect(),
+            );
+        }
+        (Some(symbol), None) => {
+            if fixed_left > right.len() {
+                return None;
+            }
+            (fixed_left, symbol, &right[fixed_left..])
+        }
+        (None, Some(symbol)) => {
+            if fixed_right > left.len() {
+                return None;
+            }
+            (fixed_right, symbol, &left[fixed_right..])
+        }
+        (Some(symbol), Some(_)) if fixed_left <= fixed_right => {
+            (fixed_left, symbol, &right[fixed_left..])
+        }
+        (Some(_), Some(symbol)) => (fixed_right, symbol, &left[fixed_right..]),
+    };
+
+    let mut pairs: Vec<(Judgement<S>, Judgement<S>)> = zip(&left[..fixed], &right[..fixed])
+        .map(|(l, r)| (l.as_ref().clone(), r.as_ref().clone()))
+        .collect();
+    let sequence = Judgement::Operator {
+        predicate: S::from(SEQUENCE_PREDICATE),
+        subjects: rest_subjects.to_vec(),
+    };
+    // Kept on its own side, for unifications that only bind one of them
+    pairs.push(if rest_on_left {
+        (Variable(rest, None), sequence)
+    } else {
+        (sequence, Variable(rest, None))
+    });
+    Some(pairs)
+}
+
+fn commuted_subjects<S: Symbol>(
+    predicate: &S,
+    subjects: &[Arc<Judgement<S>>],
+    commutative: &HashSet<S>,
+) -> Vec<Vec<Arc<Judgement<S>>>> {
+    let mut orderings = vec![subjects.to_vec()];
+    if subjects.len() >= 2
+        && commutative.contains(predicate)
+        && !matches!(subjects[1].as_ref(), Judgement::Rest(_))
+    {
+        let mut swapped = subjects.to_vec();
+        swapped.swap(0, 1);
+        orderings.push(swapped);
+    }
+    orderings
+}
+
+impl<S: Symbol> Display for Judgement<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Judgement::*;
+        match self {
+            Variable(symbol, _) => parser::write_symbol(f, symbol.as_ref())?,
+            Rest(symbol) => {
+                parser::write_symbol(f, symbol.as_ref())?;
+                f.write_str("...")?;
+            }
+            Operator {
+                predicate,
+                subjects,
+            } => {
+                parser::write_symbol(f, predicate.as_ref())?;
+                f.write_str("(")?;
+                for (i, subject) in subjects.iter().enumerate() {
+                    f.write_str(format!("{}", subject).as_str())?;
+                    if i != subjects.len() - 1 {
+                        f.write_str(", ")?;
+                    }
+                }
+                f.write_str(")")?;
+            }
+            Premise(premise, subjects) => {
+                // Unquoted, unlike an operator that happens to share the symbol
+                f.write_str(premise.symbol())?;
+                f.write_str("(")?;
+                for (i, subject) in subjects.iter().enumerate() {
+                    f.write_str(format!("{}", subject).as_str())?;
+                    if i != subjects.len() - 1 {
+                        f.write_str(", ")?;
+                    }
+                }
+                f.write_str(")")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Derivation<S = String> {
+    premises: Vec<Derivation<S>>,
+    conclusion: Judgement<S>,
+    rule_label: String,
+    // Whether the applied rule has no premises at all
+    is_axiom: bool,
+    // The mark pairing an assumption with the rule that discharges it, set on
+    // both the assumption leaves and the discharging step
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    discharge: Option<usize>,
+    // With `verify_annotated`, the values the rule gave to the variables left
+    // open in the goal, as they were when it was applied
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    bindings: Vec<(S, Judgement<S>)>,
+}
+
+// Dropped one node at a time, as derivations can be deeper than the stack
+impl<S> Drop for Derivation<S> {
+    fn drop(&mut self) {
+        let mut premises = std::mem::take(&mut self.premises);
+        while let Some(mut premise) = premises.pop() {
+            premises.append(&mut premise.premises);
+        }
+    }
+}
+
+impl<S: Symbol> Derivation<S> {
+    // Walks the tree with an explicit stack, as the search builds it
+    pub fn apply_substitution(&self, substitutions: &UnificationTable<S>) -> Self {
+        let mut done: Vec<Derivation<S>> = vec![];
+        let mut todo = vec![(self, false)];
+        while let Some((derivation, expanded)) = todo.pop() {
+            if !expanded {
+                todo.push((derivation, true));
+                todo.extend(
+                    derivation
+                        .premises
+                        .iter()
+                        .rev()
+                        .map(|premise| (premise, false)),
+                );
+                continue;
+            }
+            let premises = done.split_off(done.len() - derivation.premises.len());
+            done.push(Self {
+                premises,
+                conclusion: derivation.conclusion.apply_substitution(substitutions),
+                rule_label: derivation.rule_label.clone(),
+                is_axiom: derivation.is_axiom,
+                discharge: derivation.discharge,
+                bindings: derivation.bindings.clone(),
+            });
+        }
+        done.pop().unwrap()
+    }
+
+    pub fn is_axiom(&self) -> bool {
+        self.is_axiom
+    }
+
+    // The number of steps on the longest path from the conclusion to a leaf,
+    // so a tautology has height 1; one more than the height the search
+    // reaches, which counts the conclusion as 0
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut steps = vec![(self, 1)];
+        while let Some((step, depth)) = steps.pop() {
+            height = std::cmp::max(height, depth);
+            steps.extend(step.premises.iter().map(|premise| (premise, depth + 1)));
+        }
+        height
+    }
+
+    // The number of rule applications in the tree, leaves included
+    pub fn rule_count(&self) -> usize {
+        let mut count = 0;
+        let mut steps = vec![self];
+        while let Some(step) = steps.pop() {
+            count += 1;
+            steps.extend(&step.premises);
+        }
+        count
+    }
+
+    // The derivation of a premise of a premise.., following `path` from the
+    // root as `Judgement::with_subject_at` does for subjects
+    pub fn subproof_at(&self, path: &[usize]) -> Option<&Derivation<S>> {
+        path.iter()
+            .try_fold(self, |proof, &index| proof.premises.get(index))
+    }
+
+    pub fn bindings(&self) -> &[(S, Judgement<S>)] {
+        &self.bindings
+    }
+
+    // Closes the derivation with a rule, such as an implication introduction,
+    // that discharges `assumption`: its open leaves, as left by `verify_under`,
+    // are marked with a fresh number shared with the new step
+    pub fn discharge(
+        self,
+        assumption: &Judgement<S>,
+        rule_label: &str,
+        conclusion: Judgement<S>,
+    ) -> Self {
+        fn last_mark<S>(derivation: &Derivation<S>) -> usize {
+            derivation
+                .premises
+                .iter()
+                .map(last_mark)
+                .fold(derivation.discharge.unwrap_or(0), std::cmp::max)
+        }
+
+        fn mark<S: Symbol>(
+            derivation: &mut Derivation<S>,
+            assumption: &Judgement<S>,
+            number: usize,
+        ) {
+            if derivation.rule_label == ASSUMPTION_LABEL
+                && derivation.discharge.is_none()
+                && derivation.conclusion == *assumption
+            {
+                derivation.discharge = Some(number);
+            }
+            for premise in &mut derivation.premises {
+                mark(premise, assumption, number);
+            }
+        }
+
+        let number = last_mark(&self) + 1;
+        let mut premise = self;
+        mark(&mut premise, assumption, number);
+        Self {
+            premises: vec![premise],
+            conclusion,
+            rule_label: String::from(rule_label),
+            is_axiom: false,
+            discharge: Some(number),
+            bindings: vec![],
+        }
+    }
+
+    fn map_judgements(&self, operation: &impl Fn(&Judgement<S>) -> Judgement<S>) -> Self {
+        Self {
+            premises: self
+                .premises
+                .iter()
+                .map(|premise| premise.map_judgements(operation))
+                .collect(),
+            conclusion: operation(&self.conclusion),
+            rule_label: self.rule_label.clone(),
+            is_axiom: self.is_axiom,
+            discharge: self.discharge,
+            bindings: self
+                .bindings
+                .iter()
+                .map(|(variable, value)| (variable.clone(), operation(value)))
+                .collect(),
+        }
+    }
+}
+
+// Drawn trees may collapse numerals and lists, which are only recognized among
+// `String` symbols
+impl Derivation {
+    pub fn pretty_print(&self) -> Vec<String> {
+        self.pretty_print_with(&PrettyPrintConfig::default())
+    }
+
+    pub fn pretty_print_with(&self, config: &PrettyPrintConfig) -> Vec<String> {
+        let layout = Layout::new(self, config, 0);
+        (0..layout.lines())
+            .map(|row| {
+                let mut line = String::new();
+                layout.write_line(row, &mut line);
+                line
+            })
+            .collect()
+    }
+
+    pub fn write_tree(&self, w: &mut impl io::Write) -> io::Result<()> {
+        self.write_tree_with(w, &PrettyPrintConfig::default())
+    }
+
+    // Writes the tree one line at a time: the layout keeps the rendered
+    // conclusion and label of every node, but never the whole picture
+    pub fn write_tree_with(
+        &self,
+        w: &mut impl io::Write,
+        config: &PrettyPrintConfig,
+    ) -> io::Result<()> {
+        let layout = Layout::within(self, config)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+        writeln!(w)?;
+        let mut line = String::new();
+        for row in (0..layout.lines()).rev() {
+            line.clear();
+            layout.write_line(row, &mut line);
+            writeln!(w, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_string_tree(&self) -> String {
+        Layout::new(self, &PrettyPrintConfig::default(), 0).render()
+    }
+
+    pub fn to_string_tree_with(&self, config: &PrettyPrintConfig) -> Result<String, TooWide> {
+        Ok(Layout::within(self, config)?.render())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PrettyPrintConfig {
+    // How much wider than the conclusion the inference bar is drawn
+    pub bar_overhang: usize,
+    pub show_labels: bool,
+    // Writing a wider tree fails instead of producing it
+    pub max_width: Option<usize>,
+    // Draws assumptions as bare leaves, bracketed with their mark once
+    // discharged, in the style of Gentzen's natural deduction
+    pub gentzen: bool,
+    // Writes numerals and lists as with `Judgement::collapsed`
+    pub collapse_encodings: bool,
+}
+
+impl Default for PrettyPrintConfig {
+    fn default() -> Self {
+        Self {
+            bar_overhang: 2,
+            show_labels: true,
+            max_width: None,
+            gentzen: false,
+            collapse_encodings: false,
+        }
+    }
+}
+
+// The rendered conclusions and labels of a derivation with their widths,
+// computed before any line is written: it grows with the size of the
+// derivation, while each line is built on its own
+struct Layout<'a> {
+    conclusion: String,
+    label: Cow<'a, str>,
+    // Drawn as the conclusion alone, without a bar above it
+    bare: bool,
+    bar_width: usize,
+    premises: Vec<Layout<'a>>,
+    premises_width: usize,
+    // Lines above the bar, as many as in the tallest premise
+    height: usize,
+    // Blank columns separating the block from the next premise
+    padding: usize,
+}
+
+impl<'a> Layout<'a> {
+    // Lays out the premises before their conclusion with an explicit stack
+    fn new(derivation: &'a Derivation, config: &PrettyPrintConfig, padding: usize) -> Self {
+        let mut done: Vec<Layout> = vec![];
+        let mut todo = vec![(derivation, padding, false)];
+        while let Some((derivation, padding, expanded)) = todo.pop() {
+            if !expanded {
+                todo.push((derivation, padding, true));
+                let last = derivation.premises.len().saturating_sub(1);
+                todo.extend(
+                    derivation
+                        .premises
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, premise)| (premise, if i < last { 2 } else { 0 }, false)),
+                );
+                continue;
+            }
+            let premises = done.split_off(done.len() - derivation.premises.len());
+            done.push(Layout::node(derivation, config, padding, premises));
+        }
+        done.pop().unwrap()
+    }
+
+    fn node(
+        derivation: &'a Derivation,
+        config: &PrettyPrintConfig,
+        padding: usize,
+        premises: Vec<Layout<'a>>,
+    ) -> Self {
+        let premises_width = premises.iter().map(Layout::width).sum();
+        let bare = config.gentzen && derivation.rule_label == ASSUMPTION_LABEL;
+        let conclusion = if config.collapse_encodings {
+            derivation.conclusion.collapsed().to_string()
+        } else {
+            derivation.conclusion.to_string()
+        };
+        let conclusion = match derivation.discharge {
+            Some(number) if bare => format!("[{}]{}", conclusion, superscript(number)),
+            _ => conclusion,
+        };
+
+        // The bar underlines both the conclusion and the whole row of premises,
+        // while the label hangs to its left
+        let bar_width = if bare {
+            conclusion.chars().count()
+        } else {
+            std::cmp::max(
+                premises_width,
+                conclusion.chars().count() + config.bar_overhang,
+            )
+        };
+
+        Self {
+            label: match derivation.discharge {
+                _ if bare || !config.show_labels => Cow::Borrowed(""),
+                Some(number) if config.gentzen => {
+                    Cow::Owned(format!("{}{}", derivation.rule_label, superscript(number)))
+                }
+                _ => Cow::Borrowed(&derivation.rule_label),
+            },
+            bare,
+            conclusion,
+            bar_width,
+            height: premises.iter().map(Layout::lines).max().unwrap_or(0),
+            premises,
+            premises_width,
+            padding,
+        }
+    }
+
+    // Fails if the derivation is wider than `config.max_width`
+    fn within(derivation: &'a Derivation, config: &PrettyPrintConfig) -> Result<Self, TooWide> {
+        let layout = Layout::new(derivation, config, 0);
+        match config.max_width {
+            Some(max_width) if layout.width() > max_width => Err(TooWide {
+                width: layout.width(),
+                max_width,
+            }),
+            _ => Ok(layout),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut result = String::from("\n");
+        for row in (0..self.lines()).rev() {
+            self.write_line(row, &mut result);
+            result.push('\n');
+        }
+        result
+    }
+
+    fn width(&self) -> usize {
+        self.label.chars().count() + self.bar_width + self.padding
+    }
+
+    fn lines(&self) -> usize {
+        if self.bare {
+            1
+        } else {
+            2 + self.height
+        }
+    }
+
+    // Appends the line `row`, counting upwards from the conclusion. The
+    // pieces of a row still to be written are kept on a stack, leftmost last
+    fn write_line(&self, row: usize, line: &mut String) {
+        let mut pieces = vec![Piece::Line(self, row)];
+        while let Some(piece) = pieces.pop() {
+            let (layout, row) = match piece {
+                Piece::Spaces(count) => {
+                    push_spaces(line, count);
+                    continue;
+                }
+                Piece::Line(layout, row) => (layout, row),
+            };
+            let indent = layout.label.chars().count();
+            match row {
+                0 => {
+                    let conclusion_width = layout.conclusion.chars().count();
+                    let left = (layout.bar_width - conclusion_width) / 2;
+                    push_spaces(line, indent + left);
+                    line.push_str(&layout.conclusion);
+                    push_spaces(line, layout.bar_width - conclusion_width - left);
+                    push_spaces(line, layout.padding);
+                }
+                1 => {
+                    line.push_str(&layout.label);
+                    line.extend(std::iter::repeat_n('-', layout.bar_width));
+                    push_spaces(line, layout.padding);
+                }
+                _ => {
+                    let left = (layout.bar_width - layout.premises_width) / 2;
+                    push_spaces(line, indent + left);
+                    pieces.push(Piece::Spaces(layout.padding));
+                    pieces.push(Piece::Spaces(
+                        layout.bar_width - layout.premises_width - left,
+                    ));
+                    pieces.extend(layout.premises.iter().rev().map(|premise| {
+                        if row - 2 < premise.lines() {
+                            Piece::Line(premise, row - 2)
+                        } else {
+                            Piece::Spaces(premise.width())
+                        }
+                    }));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Layout<'_> {
+    fn drop(&mut self) {
+        let mut premises = std::mem::take(&mut self.premises);
+        while let Some(mut premise) = premises.pop() {
+            premises.append(&mut premise.premises);
+        }
+    }
+}
+
+enum Piece<'l, 'a> {
+    Line(&'l Layout<'a>, usize),
+    Spaces(usize),
+}
+
+fn push_spaces(line: &mut String, count: usize) {
+    line.extend(std::iter::repeat_n(' ', count));
+}
+
+fn superscript(number: usize) -> String {
+    number
+        .to_string()
+        .chars()
+        .map(|digit| {
+            "⁰¹²³⁴⁵⁶⁷⁸⁹"
+                .chars()
+                .nth(digit as usize - '0' as usize)
+                .unwrap()
+        })
+        .collect()
+}
+
+// Returned when a derivation would be rendered wider than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooWide {
+    pub width: usize,
+    pub max_width: usize,
+}
+
+impl Display for TooWide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Derivation is {} columns wide, more than the limit of {}",
+            self.width, self.max_width
+        )
+    }
+}
+
+impl std::error::Error for TooWide {}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "S: serde::Serialize",
+        deserialize = "S: Symbol + serde::Deserialize<'de>"
+    ))
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Rule<S = String> {
+    name: String,
+    premises: Vec<Judgement<S>>,
+    conclusion: Judgement<S>,
+    // Sorts declared for some of the variables of the rule
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    sorts: HashMap<S, String>,
+    // Names under which some premises, by position, can be cited
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    premise_labels: HashMap<usize, String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    span: Option<Span>,
+    // How many times the rule may be applied along a single branch
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    max_uses: Option<usize>,
+    // Whether the rules following it are skipped for goals it applies to
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    committed: bool,
+    // Metadata for tools organizing rules, such as a category or a citation,
+    // which the search ignores
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    attributes: HashMap<String, String>,
+}
+
+impl<S: Symbol> Rule<S> {
+    pub fn new(name: &str, premises: Vec<Judgement<S>>, conclusion: Judgement<S>) -> Self {
+        Self {
+            name: String::from(name),
+            premises,
+            conclusion,
+            sorts: HashMap::new(),
+            premise_labels: HashMap::new(),
+            span: None,
+            max_uses: None,
+            committed: false,
+            attributes: HashMap::new(),
+        }
+    }
+
+    // Declares that `variable` ranges over terms of `sort`, as computed by the
+    // sort inference of the formal system
+    pub fn with_sort(mut self, variable: &str, sort: &str) -> Self {
+        self.sorts.insert(S::from(variable), String::from(sort));
+        self
+    }
+
+    pub fn with_premise_label(mut self, position: usize, label: &str) -> Self {
+        self.premise_labels.insert(position, String::from(label));
+        self
+    }
+
+    pub fn premise_label(&self, position: usize) -> Option<&str> {
+        self.premise_labels.get(&position).map(String::as_str)
+    }
+
+    pub fn with_attributes(mut self, attributes: HashMap<String, String>) -> Self {
+        self.attributes.extend(attributes);
+        self
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    pub fn attributes(&self) -> &HashMap<String, String> {
+        &self.attributes
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    // Lets the search apply the rule at most `max_uses` times along any branch
+    // of a derivation, to tame rules such as coercions that could otherwise be
+    // chained up to the height bound
+    pub fn with_max_uses(mut self, max_uses: usize) -> Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    // Once the conclusion of the rule unifies with a goal, the search commits to
+    // it like a Prolog cut: the rules declared after it are not tried for that
+    // goal, while its own premises are still searched in full
+    pub fn committed(mut self) -> Self {
+        self.committed = true;
+        self
+    }
+
+    // The rules obtained by choosing one alternative for each premise listing
+    // them, in order; a rule without alternatives is its only choice
+    pub(crate) fn alternatives(&self) -> Vec<Rule<S>> {
+        let mut rules = vec![self.clone()];
+        for (i, premise) in self.premises.iter().enumerate() {
+            let Some(alternatives) = premise.as_one_of() else {
+                continue;
+            };
+            rules = rules
+                .into_iter()
+                .flat_map(|rule| {
+                    alternatives.iter().map(move |alternative| {
+                        let mut rule = rule.clone();
+                        rule.premises[i] = (**alternative).clone();
+                        rule
+                    })
+                })
+                .collect();
+        }
+        rules
+    }
+
+    pub fn taut(name: &str, judgement: Judgement<S>) -> Self {
+        Self::new(name, vec![], judgement)
+    }
+
+    pub fn rename_variables<T>(&self, state: &mut T, operation: &impl Fn(&mut T, S) -> S) -> Self {
+        let premises = self
+            .premises
+            .iter()
+            .map(|premise| premise.rename_variables(state, operation))
+            .collect();
+        let conclusion = self.conclusion.rename_variables(state, operation);
+        Self {
+            name: self.name.clone(),
+            premises,
+            conclusion,
+            sorts: self
+                .sorts
+                .iter()
+                .map(|(variable, sort)| (operation(state, variable.clone()), sort.clone()))
+                .collect(),
+            premise_labels: self.premise_labels.clone(),
+            span: self.span,
+            max_uses: self.max_uses,
+            committed: self.committed,
+            attributes: self.attributes.clone(),
+        }
+    }
+
+    pub fn with_provenance(&self, provenance: &Arc<Provenance>) -> Self {
+        Self {
+            premises: self
+                .premises
+                .iter()
+                .map(|premise| premise.with_provenance(provenance))
+                .collect(),
+            conclusion: self.conclusion.with_provenance(provenance),
+            ..self.clone()
+        }
+    }
+
+    pub fn get_variables(&self) -> HashSet<S> {
+        self.premises
+            .iter()
+            .fold(self.conclusion.get_variables(), |mut result, premise| {
+                result.extend(premise.get_variables());
+                result
+            })
+    }
+
+    pub fn map_predicates(&self, operation: &impl Fn(&str) -> String) -> Self {
+        Self {
+            name: self.name.clone(),
+            premises: self
+                .premises
+                .iter()
+                .map(|premise| premise.map_predicates(operation))
+                .collect(),
+            conclusion: self.conclusion.map_predicates(operation),
+            sorts: self.sorts.clone(),
+            premise_labels: self.premise_labels.clone(),
+            span: self.span,
+            max_uses: self.max_uses,
+            committed: self.committed,
+            attributes: self.attributes.clone(),
+        }
+    }
+}
+
+impl<S: Symbol> Display for Rule<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(")?;
+        for (i, premise) in self.premises.iter().enumerate() {
+            if let Some(label) = self.premise_label(i) {
+                write!(f, "({}): ", label)?;
+            }
+            f.write_str(format!("{}", premise).as_str())?;
+            if i != self.premises.len() - 1 {
+                f.write_str(", ")?;
+            }
+        }
+        f.write_str(")->")?;
+        f.write_str(format!("{}", self.conclusion).as_str())?;
+
+        Ok(())
+    }
+}
+
+impl<S: Symbol> Rule<S> {
+    // The rule as in a rules file: its premises on one line, then a bar long
+    // enough to cover them and the conclusion, named after the rule
+    pub fn to_bar_string(&self) -> String {
+        let premises = self
+            .premises
+            .iter()
+            .enumerate()
+            .map(|(i, premise)| match self.premise_label(i) {
+                Some(label) => format!("({}): {}", label, premise),
+                None => premise.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("    ");
+        let conclusion = self.conclusion.to_string();
+        let bar = "-".repeat(
+            premises
+                .chars()
+                .count()
+                .max(conclusion.chars().count())
+                .max(1),
+        );
+
+        let mut text = String::new();
+        for (key, value) in self.attributes.iter().sorted() {
+            text.push_str(&format!(
+                "@{} \"{}\"\n",
+                key,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+        }
+        if !premises.is_empty() {
+            text.push_str(&premises);
+            text.push('\n');
+        }
+        text.push_str(&bar);
+        if !self.name.is_empty() {
+            text.push(' ');
+            // Writing to a string cannot fail
+            let _ = parser::write_symbol(&mut text, &self.name);
+        }
+        text.push('\n');
+        text.push_str(&conclusion);
+        text
+    }
+}
+
+// How the search avoids exploring the same goal over and over.
+// `Global` remembers every goal that failed anywhere in the search and does not
+// try it again, or only where it is met as high when it failed for lack of
+// height. Such failures are not remembered under per-predicate heights.
+// `PerBranch` only rejects a goal that repeats one of its own ancestors, at the
+// cost of re-exploring failed goals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopCheck {
+    #[default]
+    Global,
+    PerBranch,
+}
+
+// How `verify` and the searches built on it explore the rules.
+// `DepthFirst` follows each branch down to the height bound before trying the
+// next one, so it may return a deep derivation where a shallow one exists.
+// `IterativeDeepening` searches again with bounds from 0 up to the height
+// bound, returning a derivation of least height at the cost of repeating the
+// shallower searches. It also bounds the height of the whole derivation when
+// predicates have heights of their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchStrategy {
+    #[default]
+    DepthFirst,
+    IterativeDeepening,
+}
+
+// Whether an enumeration of derivations found all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completeness {
+    Exhaustive,
+    // Some goal was given up at a height limit while rules still applied to
+    // it, so more derivations may exist beyond the bound
+    Truncated,
+}
+
+// Computes the sort of a term, if it has a known one
+pub type SortInference<S = String> = Arc<dyn Fn(&Judgement<S>) -> Option<String> + Send + Sync>;
+
+// Checks that constraints, instantiated with the bindings made so far, can
+// all hold at once
+pub type ConstraintSolver<S = String> = Arc<dyn Fn(&[Judgement<S>]) -> bool + Send + Sync>;
+
+// A formal system is saved with serde as its rules and height bound alone.
+// Settings holding functions, like sort inference, cannot be, and the others
+// are left to the code loading it to set again
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        from = "SavedSystem<S>",
+        into = "SavedSystem<S>",
+        bound(
+            serialize = "S: Symbol + serde::Serialize",
+            deserialize = "S: Symbol + serde::Deserialize<'de>"
+        )
+    )
+)]
+pub struct FormalSystem<S = String> {
+    axioms: Vec<Rule<S>>,
+    max_derivation_height: u16,
+    predicate_heights: HashMap<S, u16>,
+    commutative: HashSet<S>,
+    equality: HashSet<S>,
+    loop_check: LoopCheck,
+    strategy: SearchStrategy,
+    // Whether ground premises are proved by the streamlined deterministic
+    // check when the rules allow it
+    ground_fast_path: bool,
+    sort_of: Option<SortInference<S>>,
+    failure_cache_capacity: Option<usize>,
+    // Judgements asserted to be false: goals unifying with one fail outright
+    negative_facts: Vec<Judgement<S>>,
+    // Predicates of the premises collected for the constraint solver instead
+    // of being proved
+    constraints: HashSet<S>,
+    constraint_solver: Option<ConstraintSolver<S>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound(
+    serialize = "S: serde::Serialize",
+    deserialize = "S: Symbol + serde::Deserialize<'de>"
+))]
+struct SavedSystem<S = String> {
+    axioms: Vec<Rule<S>>,
+    max_derivation_height: u16,
+}
+
+#[cfg(feature = "serde")]
+impl<S: Symbol> From<SavedSystem<S>> for FormalSystem<S> {
+    fn from(saved: SavedSystem<S>) -> Self {
+        Self::new(saved.axioms, saved.max_derivation_height)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S: Symbol> From<FormalSystem<S>> for SavedSystem<S> {
+    fn from(system: FormalSystem<S>) -> Self {
+        Self {
+            axioms: system.axioms,
+            max_derivation_height: system.max_derivation_height,
+        }
+    }
+}
+
+impl<S: Symbol> FromIterator<Rule<S>> for FormalSystem<S> {
+    fn from_iter<T: IntoIterator<Item = Rule<S>>>(rules: T) -> Self {
+        Self::from_rules(rules)
+    }
+}
+
+impl<S: Symbol> FormalSystem<S> {
+    pub fn new(axioms: Vec<Rule<S>>, max_derivation_height: u16) -> Self {
+        Self {
+            axioms,
+            max_derivation_height,
+            predicate_heights: HashMap::new(),
+            commutative: HashSet::new(),
+            equality: HashSet::new(),
+            loop_check: LoopCheck::default(),
+            strategy: SearchStrategy::default(),
+            ground_fast_path: true,
+            sort_of: None,
+            failure_cache_capacity: None,
+            negative_facts: vec![],
+            constraints: HashSet::new(),
+            constraint_solver: None,
+        }
+    }
+
+    // A system bounded by `DEFAULT_MAX_DERIVATION_HEIGHT`
+    pub fn from_rules(rules: impl IntoIterator<Item = Rule<S>>) -> Self {
+        Self::new(rules.into_iter().collect(), DEFAULT_MAX_DERIVATION_HEIGHT)
+    }
+
+    pub fn with_max_derivation_height(mut self, max_derivation_height: u16) -> Self {
+        self.max_derivation_height = max_derivation_height;
+        self
+    }
+
+    // Once per-predicate limits are set, a goal is also bounded by the number of
+    // enclosing goals with the same predicate, next to `max_derivation_height`
+    // which still bounds the whole derivation
+    pub fn with_predicate_heights(mut self, heights: HashMap<S, u16>) -> Self {
+        self.predicate_heights = heights;
+        self
+    }
+
+    // Variables with a declared sort may only be bound to terms whose sort is
+    // unknown or the same. Without sort inference, declared sorts are ignored
+    pub fn with_sort_inference(
+        mut self,
+        sort_of: impl Fn(&Judgement<S>) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.sort_of = Some(Arc::new(sort_of));
+        self
+    }
+
+    pub fn with_loop_check(mut self, loop_check: LoopCheck) -> Self {
+        self.loop_check = loop_check;
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: SearchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // Turning the ground fast path off leaves every goal to the general
+    // search, which finds the same derivations, as when comparing the two
+    pub fn with_ground_fast_path(mut self, enabled: bool) -> Self {
+        self.ground_fast_path = enabled;
+        self
+    }
+
+    // Bounds how many failed goals a search remembers, forgetting the least
+    // recently used first. Forgotten goals are explored again when met, which
+    // costs time but finds the same derivations
+    pub fn with_failure_cache_capacity(mut self, capacity: usize) -> Self {
+        self.failure_cache_capacity = Some(capacity);
+        self
+    }
+
+    pub fn with_commutative(mut self, predicates: &[&str]) -> Self {
+        self.commutative
+            .extend(predicates.iter().map(|&predicate| S::from(predicate)));
+        self
+    }
+
+    // Goals `predicate(a, b)` are proved by unifying `a` with `b`, without
+    // looking for a rule
+    pub fn with_equality(mut self, predicate: &str) -> Self {
+        self.equality.insert(S::from(predicate));
+        self
+    }
+
+    // Any goal unifying with one of `facts` fails before any rule is tried,
+    // whatever the rules would derive. Unlike negation as failure, the facts
+    // are asserted rather than computed
+    pub fn with_negative_facts(mut self, facts: &[Judgement<S>]) -> Self {
+        self.negative_facts.extend_from_slice(facts);
+        self
+    }
+
+    // Premises with one of `predicates` are not proved by rules. The search
+    // collects them along the branch instead, each time asking the solver
+    // whether those collected so far are consistent and backtracking if not
+    pub fn with_constraints(mut self, predicates: &[&str]) -> Self {
+        self.constraints
+            .extend(predicates.iter().map(|&predicate| S::from(predicate)));
+        self
+    }
+
+    pub fn with_constraint_solver(
+        mut self,
+        solver: impl Fn(&[Judgement<S>]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.constraint_solver = Some(Arc::new(solver));
+        self
+    }
+
+    pub fn verify(&self, judgement: &Judgement<S>) -> Option<Derivation<S>> {
+        self.verify_indexed(None, judgement)
+    }
+
+    // Like `verify`, with each step of the derivation recording the bindings
+    // its rule made, which the final derivation only shows substituted
+    pub fn verify_annotated(&self, judgement: &Judgement<S>) -> Option<Derivation<S>> {
+        let mut search = Search::new(None);
+        search.annotate = true;
+        self.search(&mut search, judgement)
+    }
+
+    // Like `verify`, letting `selector` decide which of the rules applicable
+    // to each goal are tried, and in which order, by returning their indices
+    // in the slice it is given. Rules it leaves out are not tried at all
+    pub fn verify_with_selector(
+        &self,
+        judgement: &Judgement<S>,
+        mut selector: impl FnMut(&Judgement<S>, &[Rule<S>]) -> Vec<usize>,
+    ) -> Option<Derivation<S>> {
+        let mut search = Search::new(None);
+        search.selector = Some(&mut selector);
+        self.search(&mut search, judgement)
+    }
+
+    // Rewrites every predicate `name` into `prefix::name`, so that systems
+    // sharing predicate names can be merged
+    pub fn with_prefix(&self, prefix: &str) -> FormalSystem<S> {
+        let rename = |predicate: &str| format!("{}::{}", prefix, predicate);
+        let mut system = self.clone();
+        system.axioms = self
+            .axioms
+            .iter()
+            .map(|axiom| axiom.map_predicates(&rename))
+            .collect();
+        system.commutative = self
+            .commutative
+            .iter()
+            .map(|predicate| S::from(&rename(predicate.as_ref())))
+            .collect();
+        system.equality = self
+            .equality
+            .iter()
+            .map(|predicate| S::from(&rename(predicate.as_ref())))
+            .collect();
+        system.constraints = self
+            .constraints
+            .iter()
+            .map(|predicate| S::from(&rename(predicate.as_ref())))
+            .collect();
+        system.negative_facts = self
+            .negative_facts
+            .iter()
+            .map(|fact| fact.map_predicates(&rename))
+            .collect();
+        system.predicate_heights = self
+            .predicate_heights
+            .iter()
+            .map(|(predicate, height)| (S::from(&rename(predicate.as_ref())), *height))
+            .collect();
+        system
+    }
+
+    // The rules numbered in order, each drawn with its bar as by
+    // `Rule::to_bar_string`, for people rather than the parser
+    pub fn to_display_string(&self) -> String {
+        self.axioms
+            .iter()
+            .enumerate()
+            .map(|(i, axiom)| format!("{}.\n{}", i + 1, axiom.to_bar_string()))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+
+    pub fn merge(mut self, other: FormalSystem<S>) -> FormalSystem<S> {
+        self.axioms.extend(other.axioms);
+        self.max_derivation_height =
+            std::cmp::max(self.max_derivation_height, other.max_derivation_height);
+        self.commutative.extend(other.commutative);
+        self.equality.extend(other.equality);
+        self.sort_of = self.sort_of.or(other.sort_of);
+        self.failure_cache_capacity = self.failure_cache_capacity.or(other.failure_cache_capacity);
+        self.negative_facts.extend(other.negative_facts);
+        self.constraints.extend(other.constraints);
+        self.constraint_solver = self.constraint_solver.or(other.constraint_solver);
+        for (predicate, height) in other.predicate_heights {
+            let limit = self.predicate_heights.entry(predicate).or_insert(height);
+            *limit = std::cmp::max(*limit, height);
+        }
+        self
+    }
+
+    // Proves `judgement` for any value of the `universal` variables. These are
+    // eigenvariables: no unification binds them, to terms or to each other,
+    // while the other variables of the goal may be solved as usual
+    pub fn verify_schematic(
+        &self,
+        judgement: &Judgement<S>,
+        universal: &[&str],
+    ) -> Option<Derivation<S>> {
+        self.verify(&judgement.fix_universal(universal))
+            .map(|proof| proof.map_judgements(&Judgement::release_universal))
+    }
+
+    // Extra rules are tried after the axioms of the system, for this query only
+    pub fn verify_with_extra_rules(
+        &self,
+        extra: &[Rule<S>],
+        judgement: &Judgement<S>,
+    ) -> Option<Derivation<S>> {
+        let mut system = self.clone();
+        system.axioms.extend_from_slice(extra);
+        system.verify(judgement)
+    }
+
+    // The variables of the assumptions stand for some fixed terms, as in
+    // `verify_schematic`: an assumption `nat(m)` proves `nat(m)` but not
+    // `nat(zero())`. The same variables in `judgement` stand for the same terms
+    pub fn verify_under(
+        &self,
+        assumptions: &[Judgement<S>],
+        judgement: &Judgement<S>,
+    ) -> Option<Derivation<S>> {
+        let fixed: Vec<S> = assumptions
+            .iter()
+            .flat_map(Judgement::get_variables)
+            .unique()
+            .collect();
+        let fixed: Vec<&str> = fixed.iter().map(S::as_ref).collect();
+        let assumptions: Vec<Rule<S>> = assumptions
+            .iter()
+            .map(|assumption| Rule::taut(ASSUMPTION_LABEL, assumption.fix_universal(&fixed)))
+            .collect();
+        self.verify_with_extra_rules(&assumptions, &judgement.fix_universal(&fixed))
+            .map(|proof| proof.map_judgements(&Judgement::release_universal))
+    }
+
+    // For a goal that does not hold, a provable goal agreeing with it everywhere
+    // but at the subjects in `vary_positions`, which are solved for instead:
+    // `hgt(t, succ(zero()))` may come back as `hgt(t, succ(succ(zero())))`.
+    // Of the solutions within the height bounds, the one whose subjects differ
+    // least from the goal's is chosen, the first found on a tie. None when the
+    // goal is not an operator or a position is out of its range
+    pub fn nearest_provable(
+        &self,
+        goal: &Judgement<S>,
+        vary_positions: &[usize],
+    ) -> Option<Judgement<S>> {
+        let Judgement::Operator { subjects, .. } = goal else {
+            return None;
+        };
+        if vary_positions
+            .iter()
+            .any(|&position| position >= subjects.len())
+        {
+            return None;
+        }
+
+        let mut taken = goal.get_variables();
+        let mut open = goal.clone();
+        for &position in vary_positions {
+            let mut name = String::from("x");
+            while taken.contains(&S::from(&name)) {
+                name = next_name(&name);
+            }
+            open = open.with_subject(position, Judgement::variable(&name));
+            taken.insert(S::from(&name));
+        }
+        let (answers, _) = self.query(&open);
+        answers
+            .iter()
+            .map(|answer| open.apply_substitution(answer))
+            .enumerate()
+            .min_by_key(|(order, candidate)| (goal.distance(candidate), *order))
+            .map(|(_, candidate)| candidate)
+    }
+
+    // Like `verify`, also returning the values the derivation gives to the
+    // variables of `judgement`, already resolved. Variables it leaves open are
+    // missing from the table, or bound to another variable of the goal
+    pub fn verify_with_bindings(
+        &self,
+        judgement: &Judgement<S>,
+    ) -> Option<(Derivation<S>, UnificationTable<S>)> {
+        let proof = self.verify(judgement)?;
+        let bindings = judgement.matches(&proof.conclusion)?;
+        Some((proof, bindings))
+    }
+
+    // The value `variable` takes in the first derivation found of `goal`, or
+    // None when `goal` doesn't mention it
+    pub fn exists(&self, variable: &str, goal: &Judgement<S>) -> Option<Judgement<S>> {
+        if !goal.get_variables().contains(&S::from(variable)) {
+            return None;
+        }
+        let (_, bindings) = self.verify_with_bindings(goal)?;
+        Some(Judgement::variable(variable).apply_substitution(&bindings))
+    }
+
+    // The search stops shortly after `cancellation` is raised from another thread
+    pub fn verify_cancellable(
+        &self,
+        judgement: &Judgement<S>,
+        cancellation: &AtomicBool,
+    ) -> Result<Option<Derivation<S>>, Cancelled> {
+        let mut search = Search::new(None);
+        search.cancellation = Some(cancellation);
+        let result = self.search(&mut search, judgement);
+        if search.cancelled {
+            Err(Cancelled)
+        } else {
+            Ok(result)
+        }
+    }
+
+    // Every derivation of `judgement` within the height bounds, following the
+    // order in which rules are declared. Open goals of productive systems have
+    // infinitely many, so the result is only complete when `Exhaustive`.
+    // Failed goals are not remembered as in `verify`, which would hide the
+    // other derivations of a goal met again; equal trees are only kept once
+    pub fn verify_all(&self, judgement: &Judgement<S>) -> (Vec<Derivation<S>>, Completeness) {
+        let (solutions, completeness) = self.solutions(judgement);
+        let mut proofs: Vec<Derivation<S>> = vec![];
+        for (proof, _) in solutions {
+            if !proofs.contains(&proof) {
+                proofs.push(proof);
+            }
+        }
+        (proofs, completeness)
+    }
+
+    // The distinct bindings of the variables of `judgement` for which it is
+    // derivable within the height bounds
+    pub fn query(&self, judgement: &Judgement<S>) -> (Vec<UnificationTable<S>>, Completeness) {
+        let (solutions, completeness) = self.solutions(judgement);
+        let variables = judgement.get_variables();
+        let mut answers: Vec<UnificationTable<S>> = vec![];
+        for (_, substitutions) in solutions {
+            let answer: UnificationTable<S> = variables
+                .iter()
+                .map(|variable| {
+                    (
+                        variable.clone(),
+                        Judgement::Variable(variable.clone(), None)
+                            .apply_substitution(&substitutions),
+                    )
+                })
+                .collect();
+            if !answers.contains(&answer) {
+                answers.push(answer);
+            }
+        }
+        (answers, completeness)
+    }
+
+    // Like `query`, also leaving out answers that only differ from an earlier
+    // one by the names of the variables they leave open
+    pub fn distinct_answers(&self, judgement: &Judgement<S>) -> Vec<UnificationTable<S>> {
+        let (answers, _) = self.query(judgement);
+        let variables: Vec<S> = judgement.get_variables().into_iter().sorted().collect();
+        let mut seen: Vec<Judgement<S>> = vec![];
+        answers
+            .into_iter()
+            .filter(|answer| {
+                let values = Judgement::sequence(
+                    variables
+                        .iter()
+                        .map(|variable| answer[variable].clone())
+                        .collect(),
+                );
+                if seen.iter().any(|other| other.is_variant_of(&values)) {
+                    return false;
+                }
+                seen.push(values);
+                true
+            })
+            .collect()
+    }
+
+    fn verify_indexed(
+        &self,
+        index: Option<&RuleIndex<S>>,
+        judgement: &Judgement<S>,
+    ) -> Option<Derivation<S>> {
+        self.search(&mut Search::new(index), judgement)
+    }
+
+    // With `tag_provenance`, the variables of each instantiated rule carry the
+    // rule they come from. Rules with alternative premises are expanded once
+    // into `alternatives`, by position. Rules are renamed apart from the goal,
+    // the bindings and the premises still `pending` on the branch
+    #[allow(clippy::too_many_arguments)]
+    fn get_possible_derivation_paths(
+        &self,
+        axioms: &[Rule<S>],
+        index: Option<&RuleIndex<S>>,
+        alternatives: &mut HashMap<usize, Vec<Rule<S>>>,
+        substitutions: &UnificationTable<S>,
+        pending: &Pending<S>,
+        judgement: &Judgement<S>,
+        tag_provenance: bool,
+    ) -> Vec<(UnificationTable<S>, usize, Rule<S>)> {
+        let mut result: Vec<(UnificationTable<S>, usize, Rule<S>)> = vec![];
+
+        let mut variables = judgement.get_variables();
+        for (key, value) in substitutions.iter() {
+            variables.insert(key.clone());
+            variables.extend(value.get_variables());
+        }
+        variables.extend(pending.variables());
+
+        let candidates: Cow<[usize]> = match index {
+            Some(index) => Cow::Borrowed(index.candidates(judgement, substitutions)),
+            None => Cow::Owned((0..axioms.len()).collect()),
+        };
+        // A ground goal only unifies with the conclusions that match it, which
+        // the compiled patterns check without renaming the rules
+        let ground = match index {
+            Some(_) if self.commutative.is_empty() => {
+                Some(judgement.apply_substitution(substitutions)).filter(Judgement::is_ground)
+            }
+            _ => None,
+        };
+
+        for &position in candidates.iter() {
+            if let (Some(index), Some(goal)) = (index, &ground) {
+                if index.patterns[position].match_against(goal).is_none() {
+                    continue;
+                }
+            }
+            let rule = &axioms[position];
+            let choices = if rule.premises.iter().any(|p| p.as_one_of().is_some()) {
+                alternatives
+                    .entry(position)
+                    .or_insert_with(|| rule.alternatives())
+                    .as_slice()
+            } else {
+                std::slice::from_ref(rule)
+            };
+            for axiom in choices {
+                //println!(">  {}", axiom);
+                let axiom_variables: Cow<HashSet<S>> = match index {
+                    Some(index) => Cow::Borrowed(&index.variables[position]),
+                    None => Cow::Owned(axiom.get_variables()),
+                };
+                let axiom = if axiom_variables.is_disjoint(&variables) {
+                    axiom.clone()
+                } else {
+                    rename_apart(axiom, &variables)
+                };
+                let axiom = if tag_provenance {
+                    axiom.with_provenance(&Arc::new(Provenance {
+                        rule: axiom.name.clone(),
+                        position,
+                    }))
+                } else {
+                    axiom
+                };
+                //println!(">> {}", axiom);
+                if !self.commutative.is_empty() {
+                    for unification_substitutions in judgement.unify_modulo_with_substitution(
+                        &axiom.conclusion,
+                        substitutions,
+                        &self.commutative,
+                    ) {
+                        result.push((unification_substitutions, position, axiom.clone()));
+                    }
+                    continue;
+                }
+
+                if let Some(unification_substitutions) =
+                    judgement.would_unify(&axiom.conclusion, substitutions)
+                {
+                    result.push((unification_substitutions, position, axiom.clone()));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+// Like `FormalSystem::verify`, searching borrowed rules with the default
+// settings, for one-off queries that would otherwise build a system each time
+pub fn verify_with<S: Symbol>(
+    axioms: &[Rule<S>],
+    max_height: u16,
+    goal: &Judgement<S>,
+) -> Option<Derivation<S>> {
+    let mut search = Search::new(None);
+    search.rules = Some(axioms);
+    FormalSystem::new(vec![], max_height).search(&mut search, goal)
+}
+
+pub fn var(name: &str) -> Judgement {
+    Judgement::variable(name)
+}
+
+pub fn atom(name: &str) -> Judgement {
+    Judgement::operator(name, vec![])
+}
+
+pub fn rest(name: &str) -> Judgement {
+    Judgement::rest(name)
+}
+
+// Writes the bindings as `{ x = succ(zero()), y = zero() }`, sorted by variable
+// and with every value fully substituted
+pub fn format_substitution<S: Symbol>(substitutions: &UnificationTable<S>) -> String {
+    if substitutions.is_empty() {
+        return String::from("{}");
+    }
+    let bindings: Vec<String> = substitutions
+        .keys()
+        .sorted()
+        .map(|variable| {
+            let value = substitutions[variable].apply_substitution(substitutions);
+            format!("{} = {}", variable.as_ref(), value)
+        })
+        .collect();
+    format!("{{ {} }}", bindings.join(", "))
+}
+
+fn premise_orders<S: Symbol>(rule: &Rule<S>) -> Vec<Vec<usize>> {
+    (0..rule.premises.len())
+        .permutations(rule.premises.len())
+        .collect()
+}
+
+// Renames the variables of `rule` found in `taken` to fresh names, used by
+// neither `taken` nor the other variables of the rule
+pub(crate) fn rename_apart<S: Symbol>(rule: &Rule<S>, taken: &HashSet<S>) -> Rule<S> {
+    let mut avoided = rule.get_variables();
+    avoided.extend(taken.iter().cloned());
+    rule.rename_variables(&mut HashMap::<S, S>::new(), &|renamed, symbol| {
+        fresh_name(renamed, symbol, taken, &avoided)
+    })
+}
+
+// The name of `symbol` in a renaming recorded in `renamed`: variables in
+// `clashing` get the first name after theirs that is neither `avoided` nor
+// given to another variable, while the others keep theirs
+fn fresh_name<S: Symbol>(
+    renamed: &mut HashMap<S, S>,
+    symbol: S,
+    clashing: &HashSet<S>,
+    avoided: &HashSet<S>,
+) -> S {
+    if !clashing.contains(&symbol) {
+        return symbol;
+    }
+    if let Some(new_symbol) = renamed.get(&symbol) {
+        return new_symbol.clone();
+    }
+    let mut new_symbol = symbol.clone();
+    while avoided.contains(&new_symbol) || renamed.values().any(|s| *s == new_symbol) {
+        new_symbol = S::from(&next_name(new_symbol.as_ref()));
+    }
+    renamed.insert(symbol, new_symbol.clone());
+    new_symbol
+}
+
+#[allow(
+    clippy::needless_return,
+    clippy::is_digit_ascii_radix,
+    clippy::from_str_radix_10
+)]
+fn next_name(name: &str) -> String {
+    let mut base = String::new();
+    let mut number = String::new();
+    let mut number_found = false;
+
+    let name_plus_one = String::from(name) + "1";
+
+    for c in name.chars() {
+        if number_found {
+            if c.is_digit(10) {
+                number.push(c);
+            } else {
+                return name_plus_one;
+            }
+        } else {
+            if c.is_digit(10) {
+                number_found = true;
+                number.push(c);
+            } else {
+                base.push(c);
+            }
+        }
+    }
+
+    if number_found {
+        if let Ok(number) = u32::from_str_radix(number.as_str(), 10) {
+            return format!("{}{}", base, number + 1);
+        }
+    }
+
+    return name_plus_one;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{
+        empty, hgt_rules, max_rules, nat_rules, node, rules, succ, sum_rules, zero,
+    };
+
+    #[test]
+    fn var_unification() {
+        let x = var("x");
+        let y = var("y");
+
+        let unification = x.unify(&y);
+        assert!(unification.is_ok());
+        let unification = unification.unwrap();
+
+        let unified_left = x.apply_substitution(&unification);
+        let unified_right = y.apply_substitution(&unification);
+
+        assert_eq!(unified_left, unified_right);
+        assert_eq!(unified_left, var("x"));
+        assert_eq!(unified_right, var("x"));
+    }
+
+    #[test]
+    fn operator_unification() {
+        let left = op!("succ", var("n"));
+        let right = op!("succ", op!("succ", var("m")));
+
+        let unification = left.unify(&right);
+        assert!(unification.is_ok());
+        let unification = unification.unwrap();
+
+        let unified_left = left.apply_substitution(&unification);
+        let unified_right = right.apply_substitution(&unification);
+
+        assert_eq!(unified_left, unified_right);
+
+        let n_substitution = unification.get("n");
+        assert!(n_substitution.is_some());
+
+        let n_substitution = n_substitution.unwrap();
+        assert_eq!(n_substitution.clone(), op!("succ", var("m")));
+    }
+
+    #[test]
+    fn infinite_unification() {
+        let left = var("n");
+        let right = op!("succ", var("n"));
+
+        let unification = left.unify(&right);
+        assert!(unification.is_err());
+        assert_eq!(
+            unification.unwrap_err(),
+            UnifyError::Recursive {
+                variable: String::from("n")
+            }
+        );
+    }
+
+    #[test]
+    fn unification_errors() {
+        assert_eq!(
+            atom("zero").unify(&atom("empty")),
+            Err(UnifyError::DistinctConstants {
+                left: String::from("zero"),
+                right: String::from("empty")
+            })
+        );
+        assert_eq!(
+            atom("zero").unify(&atom("empty")).unwrap_err().to_string(),
+            "Distinct constants: zero() != empty()"
+        );
+        assert_eq!(
+            atom("zero").unify(&op!("zero", var("n"))),
+            Err(UnifyError::DifferentArities {
+                predicate: String::from("zero"),
+                left: 0,
+                right: 1
+            })
+        );
+        assert_eq!(
+            op!("succ", var("n")).unify(&op!("nat", var("n"))),
+            Err(UnifyError::DifferentPredicates {
+                left: String::from("succ"),
+                right: String::from("nat")
+            })
+        );
+        assert_eq!(
+            op!("succ", var("n"))
+                .unify(&atom("zero"))
+                .unwrap_err()
+                .to_string(),
+            "Different predicates: succ != zero"
+        );
+        // Errors nested in subjects are reported as they are found
+        assert!(matches!(
+            op!("nat", atom("zero")).unify(&op!("nat", op!("zero", atom("zero")))),
+            Err(UnifyError::DifferentArities { .. })
+        ));
+    }
+
+    #[test]
+    #[allow(clippy::nonminimal_bool)]
+    fn nat_formal_system() {
+        fn zero() -> Judgement {
+            atom("zero")
+        }
+        fn succ(n: Judgement) -> Judgement {
+            op!("succ", n)
+        }
+        fn empty() -> Judgement {
+            atom("empty")
+        }
+        fn node(t1: Judgement, t2: Judgement) -> Judgement {
+            op!("node", t1, t2)
+        }
+
+        let nat = FormalSystem::new(
+            vec![
+                Rule::new(
+                    "succ",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", succ(var("n"))),
+                ),
+                Rule::taut("zero", op!("nat", zero())),
+                Rule::new(
+                    "tree",
+                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+                    op!("tree", op!("node", var("a1"), var("a2"))),
+                ),
+                Rule::taut("empty", op!("tree", atom("empty"))),
+                Rule::taut("s1", op!("sum", var("n"), zero(), var("n"))),
+                Rule::new(
+                    "s2",
+                    vec![op!("sum", var("n"), var("m"), var("p"))],
+                    op!("sum", var("n"), succ(var("m")), succ(var("p"))),
+                ),
+                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+                Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
+                Rule::new(
+                    "max3",
+                    vec![op!("max", var("n"), var("m"), var("p"))],
+                    op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
+                ),
+                Rule::taut("h1", op!("hgt", atom("empty"), zero())),
+                Rule::new(
+                    "h2",
+                    vec![
+                        op!("hgt", var("t1"), var("n1")),
+                        op!("hgt", var("t2"), var("n2")),
+                        op!("max", var("n1"), var("n2"), var("n")),
+                    ],
+                    op!("hgt", op!("node", var("t1"), var("t2")), succ(var("n"))),
+                ),
+            ],
+            8,
+        );
+
+        assert!(nat.verify(&op!("nat", atom("zero"))).is_some());
+        assert!(nat.verify(&op!("sum", zero(), zero(), zero())).is_some());
+        assert!(!nat
+            .verify(&op!("sum", zero(), succ(zero()), zero()))
+            .is_some());
+        assert!(nat
+            .verify(&op!(
+                "max",
+                succ(zero()),
+                succ(succ(zero())),
+                succ(succ(zero()))
+            ))
+            .is_some());
+        assert!(nat
+            .verify(&op!(
+                "max",
+                succ(zero()),
+                succ(succ(zero())),
+                succ(succ(zero()))
+            ))
+            .is_some());
+        assert!(nat
+            .verify(&op!("hgt", node(empty(), empty()), succ(zero())))
+            .is_some());
+        assert!(nat
+            .verify(&op!(
+                "hgt",
+                node(empty(), node(empty(), empty())),
+                succ(succ(zero()))
+            ))
+            .is_some());
+        assert!(!nat
+            .verify(&op!(
+                "hgt",
+                node(empty(), node(empty(), empty())),
+                succ(zero())
+            ))
+            .is_some());
+        assert!(nat
+            .verify(&op!("hgt", node(empty(), node(empty(), empty())), var("x")))
+            .is_some());
+    }
+
+    #[test]
+    fn clashing_rule_variables() {
+        // The subgoals of h2 share names with its own variables, so renaming
+        // one of them apart must not land on another
+        let nat = crate::fixtures::nat();
+        assert!(nat
+            .verify(&op!(
+                "hgt",
+                node(node(empty(), empty()), empty()),
+                succ(succ(zero()))
+            ))
+            .is_some());
+    }
+
+    // Symbols shared by every occurrence, as an interner would hand them out
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    struct Interned(Arc<str>);
+
+    impl AsRef<str> for Interned {
+        fn as_ref(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl From<&str> for Interned {
+        fn from(symbol: &str) -> Self {
+            Self(Arc::from(symbol))
+        }
+    }
+
+    #[test]
+    fn interned_symbols() {
+        let intern = |judgement: &Judgement| -> Judgement<Interned> {
+            judgement.fold(
+                &|symbol| Judgement::variable(symbol),
+                &|predicate, subjects| Judgement::operator(predicate, subjects),
+            )
+        };
+        let nat = crate::fixtures::nat();
+        let interned: FormalSystem<Interned> = nat
+            .axioms
+            .iter()
+            .map(|axiom| {
+                Rule::new(
+                    &axiom.name,
+                    axiom.premises.iter().map(intern).collect(),
+                    intern(&axiom.conclusion),
+                )
+            })
+            .collect::<FormalSystem<Interned>>()
+            .with_max_derivation_height(nat.max_derivation_height);
+
+        for goal in [
+            op!("sum", succ(zero()), succ(zero()), var("x")),
+            op!("sum", zero(), succ(zero()), zero()),
+            op!("hgt", node(node(empty(), empty()), empty()), var("n")),
+            op!("max", var("n"), succ(zero()), succ(zero())),
+        ] {
+            let expected = nat.verify(&goal);
+            let found = interned.verify(&intern(&goal));
+            assert_eq!(
+                expected.map(|proof| proof.conclusion.to_string()),
+                found.map(|proof| proof.conclusion.to_string())
+            );
+            assert_eq!(
+                nat.query(&goal).0.len(),
+                interned.query(&intern(&goal)).0.len()
+            );
+        }
+    }
+
+    #[test]
+    fn commutative_unification() {
+        let commutative = HashSet::from([String::from("max")]);
+        let left = op!("max", atom("zero"), var("n"), var("n"));
+        let right = op!("max", var("m"), atom("zero"), var("m"));
+
+        assert!(left.unify(&right).is_ok());
+        assert_eq!(left.unify_modulo(&right, &commutative).len(), 2);
+        assert!(op!("max", atom("a"), atom("b"), atom("c"))
+            .eq_modulo(&op!("max", atom("b"), atom("a"), atom("c")), &commutative));
+        assert!(!op!("max", atom("a"), atom("b"), atom("c"))
+            .eq_modulo(&op!("max", atom("c"), atom("b"), atom("a")), &commutative));
+    }
+
+    #[test]
+    fn commutative_formal_system() {
+        let max = FormalSystem::new(
+            vec![
+                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+                Rule::new(
+                    "max3",
+                    vec![op!("max", var("n"), var("m"), var("p"))],
+                    op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
+                ),
+            ],
+            8,
+        );
+        assert!(max
+            .verify(&op!("max", succ(zero()), zero(), succ(zero())))
+            .is_some());
+        assert!(max
+            .verify(&op!("max", zero(), succ(zero()), succ(zero())))
+            .is_none());
+
+        let max = max.with_commutative(&["max"]);
+        assert!(max
+            .verify(&op!("max", succ(zero()), zero(), succ(zero())))
+            .is_some());
+        assert!(max
+            .verify(&op!("max", zero(), succ(zero()), succ(zero())))
+            .is_some());
+        assert!(max
+            .verify(&op!(
+                "max",
+                succ(zero()),
+                succ(succ(zero())),
+                succ(succ(zero()))
+            ))
+            .is_some());
+        assert!(max
+            .verify(&op!("max", zero(), succ(zero()), zero()))
+            .is_none());
+    }
+
+    #[test]
+    fn shared_subjects() {
+        let tree = op!(
+            "node",
+            op!("node", atom("empty"), atom("empty")),
+            atom("empty")
+        );
+        let updated = tree.with_subject_at(&[0, 1], atom("leaf"));
+
+        assert_eq!(
+            updated,
+            op!(
+                "node",
+                op!("node", atom("empty"), atom("leaf")),
+                atom("empty")
+            )
+        );
+        assert_eq!(
+            tree.with_subject(1, var("t")),
+            op!("node", op!("node", atom("empty"), atom("empty")), var("t"))
+        );
+
+        let (
+            Judgement::Operator {
+                subjects: before, ..
+            },
+            Judgement::Operator {
+                subjects: after, ..
+            },
+        ) = (&tree, &updated)
+        else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(&before[1], &after[1]));
+        assert!(!Arc::ptr_eq(&before[0], &after[0]));
+
+        // Sharing doesn't keep judgements from crossing threads
+        fn thread_safe<T: Send + Sync>(_: &T) {}
+        thread_safe(&updated);
+    }
+
+    #[test]
+    fn folds() {
+        let judgement = op!(
+            "sum",
+            var("n"),
+            op!("succ", op!("succ", atom("zero"))),
+            op!("succ", var("p"))
+        );
+
+        let size = judgement.fold(&|_| 1, &|_, subjects| 1 + subjects.iter().sum::<usize>());
+        assert_eq!(size, 7);
+        assert_eq!(size, judgement.size());
+
+        let variables = judgement.fold(&|symbol| vec![String::from(symbol)], &|_, subjects| {
+            subjects.concat()
+        });
+        assert_eq!(variables, vec!["n", "p"]);
+        assert_eq!(
+            variables.into_iter().collect::<HashSet<String>>(),
+            judgement.get_variables()
+        );
+
+        let depth = judgement.fold(&|_| 0, &|_, subjects| {
+            1 + subjects.into_iter().max().unwrap_or(0)
+        });
+        assert_eq!(depth, 4);
+    }
+
+    #[test]
+    fn associative_flattening() {
+        let associative = HashSet::from([String::from("+")]);
+
+        assert_eq!(
+            op!("+", op!("+", var("a"), var("b")), var("c")).flatten_associative(&associative),
+            op!("+", var("a"), var("b"), var("c"))
+        );
+        assert_eq!(
+            op!(
+                "eq",
+                op!(
+                    "+",
+                    var("a"),
+                    op!("+", var("b"), op!("+", var("c"), var("d")))
+                ),
+                op!("*", op!("*", var("a"), var("b")), var("c"))
+            )
+            .flatten_associative(&associative),
+            op!(
+                "eq",
+                op!("+", var("a"), var("b"), var("c"), var("d")),
+                op!("*", op!("*", var("a"), var("b")), var("c"))
+            )
+        );
+        // Chains are only collapsed below an application of the same predicate
+        assert_eq!(
+            op!("+", op!("s", op!("+", var("a"), var("b"))), var("c"))
+                .flatten_associative(&associative),
+            op!("+", op!("s", op!("+", var("a"), var("b"))), var("c"))
+        );
+    }
+
+    #[test]
+    fn variable_provenance() {
+        let provenance = Arc::new(Provenance {
+            rule: String::from("s2"),
+            position: 3,
+        });
+        let tagged = op!("sum", var("n"), op!("succ", var("m"))).with_provenance(&provenance);
+
+        // Tags are metadata: they do not distinguish judgements
+        assert_eq!(tagged, op!("sum", var("n"), op!("succ", var("m"))));
+        let unifier = tagged.unify(&op!("sum", var("n"), var("k"))).unwrap();
+        assert_eq!(unifier.get("k"), Some(&op!("succ", var("m"))));
+        let Judgement::Operator { subjects, .. } = &unifier["k"] else {
+            unreachable!()
+        };
+        assert_eq!(subjects[0].provenance(), Some(provenance.as_ref()));
+
+        let renamed = tagged.rename_variables(&mut (), &|_, symbol| symbol + "'");
+        let Judgement::Operator { subjects, .. } = &renamed else {
+            unreachable!()
+        };
+        assert_eq!(subjects[0].as_ref(), &var("n'"));
+        assert_eq!(subjects[0].provenance(), Some(provenance.as_ref()));
+
+        let substituted =
+            tagged.apply_substitution(&HashMap::from([(String::from("m"), atom("zero"))]));
+        let Judgement::Operator { subjects, .. } = &substituted else {
+            unreachable!()
+        };
+        assert_eq!(subjects[0].provenance(), Some(provenance.as_ref()));
+        assert_eq!(var("n").provenance(), None);
+    }
+
+    #[test]
+    fn dry_run_unification() {
+        let context: UnificationTable = HashMap::from([(String::from("n"), atom("zero"))]);
+        let before = context.clone();
+
+        let left = op!("sum", var("n"), var("m"));
+        let unifier = left
+            .would_unify(&op!("sum", atom("zero"), op!("succ", var("p"))), &context)
+            .unwrap();
+        assert_eq!(unifier.get("n"), Some(&atom("zero")));
+        assert_eq!(unifier.get("m"), Some(&op!("succ", var("p"))));
+        assert_eq!(context, before);
+
+        assert_eq!(
+            left.would_unify(&op!("sum", op!("succ", var("p")), var("q")), &context),
+            None
+        );
+        assert_eq!(context, before);
+    }
+
+    #[test]
+    fn rest_unification() {
+        let left = op!("ctx", atom("a"), rest("xs"));
+        let right = op!("ctx", atom("a"), atom("b"), atom("c"));
+
+        let unification = left.unify(&right).unwrap();
+        assert_eq!(
+            unification.get("xs"),
+            Some(&Judgement::sequence(vec![atom("b"), atom("c")]))
+        );
+        assert_eq!(left.apply_substitution(&unification), right);
+
+        let unification = left.unify(&op!("ctx", atom("a"))).unwrap();
+        assert_eq!(unification.get("xs"), Some(&Judgement::sequence(vec![])));
+
+        assert!(left.unify(&op!("ctx")).is_err());
+        assert!(left.unify(&op!("ctx", atom("b"), atom("a"))).is_err());
+
+        let unification = left
+            .unify(&op!("ctx", var("y"), atom("b"), rest("ys")))
+            .unwrap();
+        assert_eq!(
+            left.apply_substitution(&unification),
+            op!("ctx", atom("a"), atom("b"), rest("ys"))
+        );
+    }
+
+    #[test]
+    fn all_derivations() {
+        let nat = FormalSystem::new(rules(&[nat_rules, sum_rules]), 4);
+
+        // Only finitely many pairs add up to two
+        let (answers, completeness) =
+            nat.query(&op!("sum", var("x"), var("y"), succ(succ(zero()))));
+        assert_eq!(completeness, Completeness::Exhaustive);
+        assert_eq!(
+            answers
+                .iter()
+                .map(|answer| (answer["x"].to_string(), answer["y"].to_string()))
+                .collect::<Vec<(String, String)>>(),
+            vec![
+                (String::from("succ(succ(zero()))"), String::from("zero()")),
+                (String::from("succ(zero())"), String::from("succ(zero())")),
+                (String::from("zero()"), String::from("succ(succ(zero()))")),
+            ]
+        );
+        let (proofs, _) = nat.verify_all(&op!("sum", var("x"), var("y"), succ(succ(zero()))));
+        assert_eq!(proofs.len(), 3);
+        assert!(proofs.iter().all(|proof| proof.conclusion.is_ground()));
+
+        // Both `max` rules prove the same answer
+        let max = FormalSystem::new(
+            vec![
+                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+                Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
+                Rule::taut("any", op!("any", op!("pair", var("a"), var("a")))),
+                Rule::taut("any'", op!("any", op!("pair", var("b"), var("b")))),
+            ],
+            4,
+        );
+        let goal = op!("max", zero(), zero(), var("x"));
+        assert_eq!(max.verify_all(&goal).0.len(), 2);
+        assert_eq!(
+            max.distinct_answers(&goal),
+            vec![UnificationTable::from([(String::from("x"), zero())])]
+        );
+        // Open answers are the same up to the names of their variables
+        let goal = op!("any", var("x"));
+        assert_eq!(max.query(&goal).0.len(), 2);
+        assert_eq!(max.distinct_answers(&goal).len(), 1);
+
+        // There are as many numerals as the height bound lets through
+        let (proofs, completeness) = nat.verify_all(&op!("nat", var("x")));
+        assert_eq!(completeness, Completeness::Truncated);
+        assert_eq!(proofs.len(), 5);
+        assert_eq!(
+            proofs.last().unwrap().conclusion,
+            op!("nat", succ(succ(succ(succ(zero())))))
+        );
+
+        let (proofs, completeness) = nat.with_max_derivation_height(1).verify_all(&op!(
+            "sum",
+            var("x"),
+            var("y"),
+            succ(succ(zero()))
+        ));
+        assert_eq!(completeness, Completeness::Truncated);
+        assert_eq!(proofs.len(), 2);
+    }
+
+    #[test]
+    fn universal_variables() {
+        let nat = FormalSystem::new(rules(&[nat_rules, sum_rules]), 8);
+
+        // An existential `n` is solved as zero, a universal one cannot be
+        assert!(nat.verify(&op!("nat", var("n"))).is_some());
+        assert!(nat
+            .verify_schematic(&op!("nat", var("n")), &["n"])
+            .is_none());
+        assert!(nat
+            .verify_schematic(&op!("sum", zero(), var("n"), var("n")), &["n"])
+            .is_none());
+
+        // Rule variables may still stand for a universal one
+        let proof = nat
+            .verify_schematic(&op!("sum", var("n"), succ(zero()), var("p")), &["n"])
+            .unwrap();
+        assert_eq!(
+            proof.conclusion,
+            op!("sum", var("n"), succ(zero()), succ(var("n")))
+        );
+        assert_eq!(
+            proof.premises[0].conclusion,
+            op!("sum", var("n"), zero(), var("n"))
+        );
+
+        // Distinct universals are never made equal
+        assert!(nat
+            .verify_schematic(&op!("sum", var("n"), zero(), var("m")), &["n", "m"])
+            .is_none());
+        assert!(nat
+            .verify_schematic(&op!("sum", var("n"), zero(), var("m")), &["n"])
+            .is_some());
+    }
+
+    #[test]
+    fn rule_uses() {
+        let types = |max_uses: usize| {
+            FormalSystem::new(
+                vec![
+                    Rule::taut("one", op!("typed", atom("one"), atom("int"))),
+                    Rule::taut("int", op!("sub", atom("int"), atom("rat"))),
+                    Rule::taut("rat", op!("sub", atom("rat"), atom("real"))),
+                    Rule::taut("refl", op!("sub", var("t"), var("t"))),
+                    Rule::new(
+                        "coerce",
+                        vec![
+                            op!("typed", var("e"), var("a")),
+                            op!("sub", var("a"), var("b")),
+                        ],
+                        op!("typed", var("e"), var("b")),
+                    )
+                    .with_max_uses(max_uses),
+                ],
+                8,
+            )
+        };
+
+        // Reflexive subtyping lets coercions pile up to the height bound
+        let goal = op!("typed", atom("one"), atom("int"));
+        let (_, completeness) = types(usize::MAX).verify_all(&goal);
+        assert_eq!(completeness, Completeness::Truncated);
+        let (proofs, completeness) = types(1).verify_all(&goal);
+        assert_eq!(completeness, Completeness::Exhaustive);
+        assert_eq!(
+            proofs
+                .iter()
+                .map(|proof| proof.rule_label.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["one", "coerce"]
+        );
+
+        let proof = types(1)
+            .verify(&op!("typed", atom("one"), atom("rat")))
+            .unwrap();
+        assert_eq!(proof.rule_label, "coerce");
+        assert_eq!(proof.premises[0].rule_label, "one");
+
+        // Reaching `real` takes two coercions
+        let goal = op!("typed", atom("one"), atom("real"));
+        assert!(types(1).verify(&goal).is_none());
+        assert!(types(2).verify(&goal).is_some());
+        assert!(types(1).verify_all(&goal).0.is_empty());
+    }
+
+    #[test]
+    fn binding_directions() {
+        let zero = atom("zero");
+        let table = |bindings: &[(&str, Judgement)]| -> UnificationTable {
+            bindings
+                .iter()
+                .map(|(variable, value)| (String::from(*variable), value.clone()))
+                .collect()
+        };
+
+        // Between two variables, the right one is bound
+        assert_eq!(
+            var("x").unify_with_binding(&var("y"), Binding::Both),
+            Ok(table(&[("y", var("x"))]))
+        );
+        assert_eq!(
+            zero.unify_with_binding(&var("y"), Binding::Both),
+            Ok(table(&[("y", zero.clone())]))
+        );
+        assert_eq!(
+            var("x").unify_with_binding(&var("x"), Binding::Both),
+            Ok(table(&[]))
+        );
+
+        assert_eq!(
+            var("x").unify_with_binding(&var("y"), Binding::Left),
+            Ok(table(&[("x", var("y"))]))
+        );
+        assert_eq!(
+            zero.unify_with_binding(&var("y"), Binding::Left),
+            Err(UnifyError::Fixed {
+                variable: String::from("y")
+            })
+        );
+        // The two sides do not share variables, even under the same name
+        assert_eq!(
+            var("x").unify_with_binding(&var("x"), Binding::Left),
+            Ok(table(&[("x", var("x"))]))
+        );
+        assert_eq!(
+            op!("pair", var("x"), var("x"))
+                .unify_with_binding(&op!("pair", var("x"), zero.clone()), Binding::Left),
+            Err(UnifyError::Fixed {
+                variable: String::from("x")
+            })
+        );
+        assert_eq!(
+            op!("ctx", rest("xs"))
+                .unify_with_binding(&op!("ctx", zero.clone(), rest("ys")), Binding::Left),
+            Ok(table(&[(
+                "xs",
+                Judgement::sequence(vec![zero.clone(), rest("ys")])
+            )]))
+        );
+        assert!(op!("ctx", zero.clone(), rest("ys"))
+            .unify_with_binding(&op!("ctx", rest("xs")), Binding::Left)
+            .is_err());
+
+        assert_eq!(
+            var("x").unify_with_binding(&var("x"), Binding::Neither),
+            Ok(table(&[]))
+        );
+        assert_eq!(
+            var("x").unify_with_binding(&var("y"), Binding::Neither),
+            Err(UnifyError::Fixed {
+                variable: String::from("x")
+            })
+        );
+    }
+
+    #[test]
+    fn substitution_format() {
+        let substitutions = HashMap::from([
+            (String::from("y"), atom("zero")),
+            (String::from("x"), op!("succ", var("y"))),
+        ]);
+        assert_eq!(
+            format_substitution(&substitutions),
+            "{ x = succ(zero()), y = zero() }"
+        );
+        assert_eq!(format_substitution::<String>(&HashMap::new()), "{}");
+    }
+
+    #[test]
+    fn judgement_order() {
+        let mut judgements = vec![
+            op!("succ", atom("zero")),
+            atom("zero"),
+            var("x"),
+            op!("pair", atom("zero"), var("y")),
+            rest("xs"),
+            op!("pair", atom("zero"), atom("zero")),
+            op!("succ", var("n")),
+            op!("pair", atom("zero")),
+            var("a"),
+        ];
+        judgements.sort();
+        assert_eq!(
+            judgements,
+            vec![
+                var("a"),
+                var("x"),
+                rest("xs"),
+                op!("pair", atom("zero")),
+                op!("pair", atom("zero"), var("y")),
+                op!("pair", atom("zero"), atom("zero")),
+                op!("succ", var("n")),
+                op!("succ", atom("zero")),
+                atom("zero"),
+            ]
+        );
+
+        let provenance = Arc::new(Provenance {
+            rule: String::from("s2"),
+            position: 1,
+        });
+        assert_eq!(
+            Judgement::Variable(String::from("n"), Some(provenance)).cmp(&var("n")),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn instances() {
+        let zero = atom("zero");
+
+        assert!(succ(zero.clone()).is_instance_of(&succ(var("x"))));
+        assert!(!succ(var("x")).is_instance_of(&succ(zero.clone())));
+        assert_eq!(
+            succ(var("x")).matches(&succ(zero.clone())),
+            Some(HashMap::from([(String::from("x"), zero.clone())]))
+        );
+
+        // Variables of the instance are constants that the pattern may bind
+        let pattern = op!("pair", var("x"), var("y"));
+        assert_eq!(
+            pattern.matches(&op!("pair", var("y"), zero.clone())),
+            Some(HashMap::from([
+                (String::from("x"), var("y")),
+                (String::from("y"), zero.clone()),
+            ]))
+        );
+        assert!(!op!("pair", var("y"), var("y")).is_instance_of(&op!(
+            "pair",
+            zero.clone(),
+            var("x")
+        )));
+        assert!(op!("pair", var("x"), var("x")).is_instance_of(&pattern));
+        assert!(!pattern.is_instance_of(&op!("pair", var("x"), var("x"))));
+
+        assert!(pattern.is_variant_of(&op!("pair", var("a"), var("b"))));
+        assert!(pattern.is_variant_of(&op!("pair", var("y"), var("x"))));
+        assert!(!pattern.is_variant_of(&op!("pair", var("a"), var("a"))));
+        assert!(!succ(var("x")).is_variant_of(&succ(zero)));
+    }
+
+    #[test]
+    fn annotated_derivations() {
+        let sum = FormalSystem::new(sum_rules(), 8);
+        let goal = op!("sum", succ(zero()), var("x"), succ(succ(zero())));
+        let proof = sum.verify_annotated(&goal).unwrap();
+        assert_eq!(
+            proof.conclusion,
+            op!("sum", succ(zero()), succ(zero()), succ(succ(zero())))
+        );
+        assert_eq!(
+            proof.bindings(),
+            [(String::from("x"), succ(var("m")))].as_slice()
+        );
+        assert_eq!(
+            proof.premises[0].bindings(),
+            [(String::from("m"), zero())].as_slice()
+        );
+        assert!(proof
+            .to_interactive_html()
+            .contains("<span class=\"bindings\">with x := succ(m)</span>"));
+
+        // Plain verification records none
+        assert!(sum.verify(&goal).unwrap().bindings().is_empty());
+    }
+
+    #[test]
+    fn committed_rules() {
+        let sum = |committed: bool| {
+            let s1 = Rule::taut("s1", op!("sum", var("n"), zero(), var("n")));
+            let s2 = Rule::new(
+                "s2",
+                vec![op!("sum", var("n"), var("m"), var("p"))],
+                op!("sum", var("n"), succ(var("m")), succ(var("p"))),
+            );
+            FormalSystem::new(
+                if committed {
+                    vec![s1.committed(), s2.committed()]
+                } else {
+                    vec![s1, s2]
+                },
+                4,
+            )
+        };
+
+        // `s2` is still tried where `s1` does not apply
+        let goal = op!("sum", succ(zero()), succ(zero()), succ(succ(zero())));
+        for committed in [false, true] {
+            let proof = sum(committed).verify(&goal).unwrap();
+            assert_eq!(proof.rule_label, "s2");
+            assert_eq!(proof.premises[0].rule_label, "s1");
+        }
+
+        // With the second summand open, every application of `s1` would be
+        // followed by attempts through `s2`
+        let open = op!("sum", zero(), var("m"), var("p"));
+        let (solutions, completeness) = sum(false).query(&open);
+        assert_eq!(completeness, Completeness::Truncated);
+        assert_eq!(solutions.len(), 5);
+        let (solutions, completeness) = sum(true).query(&open);
+        assert_eq!(completeness, Completeness::Exhaustive);
+        assert_eq!(
+            solutions,
+            vec![HashMap::from([
+                (String::from("m"), zero()),
+                (String::from("p"), zero()),
+            ])]
+        );
+
+        // Every alternative premise of a committed rule is still tried
+        let parity = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("even", zero())),
+                Rule::new(
+                    "odd",
+                    vec![op!("even", var("n"))],
+                    op!("odd", succ(var("n"))),
+                ),
+                Rule::new(
+                    "even",
+                    vec![op!("odd", var("n"))],
+                    op!("even", succ(var("n"))),
+                ),
+                Rule::new(
+                    "parity",
+                    vec![Judgement::one_of(vec![
+                        op!("even", var("n")),
+                        op!("odd", var("n")),
+                    ])],
+                    op!("parity", var("n")),
+                )
+                .committed(),
+            ],
+            4,
+        );
+        let goal = op!("parity", succ(zero()));
+        assert_eq!(parity.verify(&goal).unwrap().premises[0].rule_label, "odd");
+        assert_eq!(parity.verify_all(&goal).0.len(), 1);
+        assert_eq!(parity.query_fair(&goal).count(), 1);
+    }
+
+    #[test]
+    fn instantiation() {
+        let template = op!("sum", var("n"), succ(var("m")), succ(var("p")));
+
+        assert_eq!(
+            template.instantiate(&[("n", succ(zero())), ("m", zero())]),
+            op!("sum", succ(zero()), succ(zero()), succ(var("p")))
+        );
+        assert_eq!(template.instantiate(&[]), template);
+
+        // Bindings are simultaneous
+        assert_eq!(
+            template.instantiate(&[("n", var("m")), ("m", succ(var("n")))]),
+            op!("sum", var("m"), succ(succ(var("n"))), succ(var("p")))
+        );
+
+        assert_eq!(
+            op!("ctx", atom("a"), rest("xs"))
+                .instantiate(&[("xs", Judgement::sequence(vec![atom("b"), atom("c")]))]),
+            op!("ctx", atom("a"), atom("b"), atom("c"))
+        );
+    }
+
+    #[test]
+    fn rest_formal_system() {
+        let context = FormalSystem::new(
+            vec![
+                Rule::taut(
+                    "here",
+                    op!("in", var("x"), op!("ctx", var("x"), rest("ys"))),
+                ),
+                Rule::new(
+                    "there",
+                    vec![op!("in", var("x"), op!("ctx", rest("ys")))],
+                    op!("in", var("x"), op!("ctx", var("y"), rest("ys"))),
+                ),
+            ],
+            8,
+        );
+
+        let ctx = op!("ctx", atom("a"), atom("b"), atom("c"));
+        assert!(context.verify(&op!("in", atom("c"), ctx.clone())).is_some());
+        assert!(context.verify(&op!("in", atom("d"), ctx)).is_none());
+    }
+
+    #[test]
+    fn extra_rules() {
+        let nat = FormalSystem::new(nat_rules(), 3);
+        let goal = op!("nat", succ(succ(succ(succ(zero())))));
+        let lemma = Rule::new(
+            "plus2",
+            vec![op!("nat", var("n"))],
+            op!("nat", succ(succ(var("n")))),
+        );
+
+        assert!(nat.verify(&goal).is_none());
+        let proof = nat.verify_with_extra_rules(std::slice::from_ref(&lemma), &goal);
+        assert!(proof.is_some());
+        assert!(proof.unwrap().to_string_tree().contains("plus2"));
+        assert!(nat.verify(&goal).is_none());
+
+        let open_goal = op!("nat", succ(succ(succ(var("n")))));
+        let proof = nat.verify_with_extra_rules(&[lemma], &open_goal).unwrap();
+        assert_eq!(proof.conclusion, op!("nat", succ(succ(succ(zero())))));
+
+        assert!(nat
+            .verify_under(&[op!("nat", var("m"))], &op!("nat", succ(var("m"))))
+            .is_some());
+
+        // An assumption about `m` says nothing about other terms
+        let successors = FormalSystem::new(
+            vec![Rule::new(
+                "succ",
+                vec![op!("nat", var("n"))],
+                op!("nat", succ(var("n"))),
+            )],
+            3,
+        );
+        let assumptions = [op!("nat", var("m"))];
+        assert!(successors
+            .verify_under(&assumptions, &op!("nat", zero()))
+            .is_none());
+        assert!(successors
+            .verify_under(&assumptions, &op!("nat", succ(zero())))
+            .is_none());
+        let proof = successors
+            .verify_under(&assumptions, &op!("nat", succ(var("m"))))
+            .unwrap();
+        assert_eq!(proof.conclusion, op!("nat", succ(var("m"))));
+        assert_eq!(proof.premises[0].conclusion, op!("nat", var("m")));
+    }
+
+    #[test]
+    fn default_height() {
+        let rules = [
+            Rule::taut("zero", op!("nat", atom("zero"))),
+            Rule::new(
+                "succ",
+                vec![op!("nat", var("n"))],
+                op!("nat", op!("succ", var("n"))),
+            ),
+        ];
+        let nat: FormalSystem = rules.iter().cloned().collect();
+        assert_eq!(nat.max_derivation_height, DEFAULT_MAX_DERIVATION_HEIGHT);
+
+        let deep = (0..DEFAULT_MAX_DERIVATION_HEIGHT).fold(atom("zero"), |n, _| op!("succ", n));
+        assert!(nat.verify(&op!("nat", deep.clone())).is_some());
+        assert!(FormalSystem::from_rules(rules)
+            .verify(&op!("nat", op!("succ", deep)))
+            .is_none());
+    }
+
+    #[test]
+    fn pretty_print_long_label() {
+        let proof = Derivation {
+            premises: vec![Derivation {
+                premises: vec![],
+                conclusion: op!("nat", atom("zero")),
+                rule_label: String::from("zero"),
+                is_axiom: true,
+                discharge: None,
+                bindings: vec![],
+            }],
+            conclusion: op!("nat", op!("succ", atom("zero"))),
+            rule_label: String::from("successor_of_a_natural"),
+            is_axiom: false,
+            discharge: None,
+            bindings: vec![],
+        };
+
+        assert_eq!(
+            proof.to_string_tree(),
+            concat!(
+                "\n",
+                "                       zero------------- \n",
+                "                            nat(zero())  \n",
+                "successor_of_a_natural-------------------\n",
+                "                       nat(succ(zero())) \n",
+            )
+        );
+
+        let config = PrettyPrintConfig {
+            bar_overhang: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config).unwrap(),
+            concat!(
+                "\n",
+                "                       zero----------------- \n",
+                "                              nat(zero())    \n",
+                "successor_of_a_natural-----------------------\n",
+                "                         nat(succ(zero()))   \n",
+            )
+        );
+    }
+
+    #[test]
+    fn deeper_than_the_stack() {
+        let leaf = Derivation {
+            premises: vec![],
+            conclusion: var("x"),
+            rule_label: String::from("leaf"),
+            is_axiom: true,
+            discharge: None,
+            bindings: vec![],
+        };
+        let proof = (0..100_000).fold(leaf, |premise, _| Derivation {
+            premises: vec![premise],
+            conclusion: atom("a"),
+            rule_label: String::from("step"),
+            is_axiom: false,
+            discharge: None,
+            bindings: vec![],
+        });
+
+        let mut substitutions = UnificationTable::new();
+        substitutions.insert(String::from("x"), atom("b"));
+        let proof = proof.apply_substitution(&substitutions);
+        let leaf = proof.subproof_at(&vec![0; 100_000]).unwrap();
+        assert_eq!(leaf.conclusion, atom("b"));
+        assert_eq!(proof.height(), 100_001);
+        assert_eq!(proof.rule_count(), 100_001);
+
+        // Rendering lays the whole tree out before writing the first line
+        let config = PrettyPrintConfig {
+            max_width: Some(0),
+            ..Default::default()
+        };
+        assert!(proof.to_string_tree_with(&config).is_err());
+    }
+
+    #[test]
+    fn streamed_trees() {
+        let tree = FormalSystem::new(
+            vec![
+                Rule::taut("empty", op!("tree", atom("empty"))),
+                Rule::new(
+                    "node",
+                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+                    op!("tree", op!("node", var("a1"), var("a2"))),
+                ),
+            ],
+            8,
+        );
+        let proof = tree
+            .verify(&op!(
+                "tree",
+                op!(
+                    "node",
+                    atom("empty"),
+                    op!("node", atom("empty"), atom("empty"))
+                )
+            ))
+            .unwrap();
+
+        let mut written: Vec<u8> = vec![];
+        proof.write_tree(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), proof.to_string_tree());
+        assert_eq!(
+            proof.to_string_tree(),
+            "\n".to_string() + &proof.pretty_print().into_iter().rev().join("\n") + "\n"
+        );
+
+        let width = proof.pretty_print()[0].len();
+        let config = PrettyPrintConfig {
+            max_width: Some(width - 1),
+            ..Default::default()
+        };
+        let error = proof.write_tree_with(&mut vec![], &config).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.into_inner().unwrap().downcast_ref::<TooWide>(),
+            Some(&TooWide {
+                width,
+                max_width: width - 1
+            })
+        );
+
+        let config = PrettyPrintConfig {
+            max_width: Some(width),
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config),
+            Ok(proof.to_string_tree())
+        );
+
+        // A narrow width is an error rather than a panic
+        let config = PrettyPrintConfig {
+            max_width: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config),
+            Err(TooWide {
+                width,
+                max_width: 1
+            })
+        );
+    }
+
+    #[test]
+    fn pretty_print_without_labels() {
+        let sum = FormalSystem::new(sum_rules(), 8);
+        let proof = sum
+            .verify(&op!(
+                "sum",
+                atom("zero"),
+                op!("succ", atom("zero")),
+                op!("succ", atom("zero"))
+            ))
+            .unwrap();
+
+        assert_eq!(
+            proof.to_string_tree(),
+            concat!(
+                "\n",
+                "       s1-----------------------------     \n",
+                "          sum(zero(), zero(), zero())      \n",
+                "s2-----------------------------------------\n",
+                "   sum(zero(), succ(zero()), succ(zero())) \n",
+            )
+        );
+
+        let config = PrettyPrintConfig {
+            collapse_encodings: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config).unwrap(),
+            concat!(
+                "\n",
+                "  s1--------------\n",
+                "     sum(0, 0, 0) \n",
+                "s2----------------\n",
+                "    sum(0, 1, 1)  \n",
+            )
+        );
+
+        let config = PrettyPrintConfig {
+            show_labels: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config).unwrap(),
+            concat!(
+                "\n",
+                "      -----------------------------      \n",
+                "       sum(zero(), zero(), zero())       \n",
+                "-----------------------------------------\n",
+                " sum(zero(), succ(zero()), succ(zero())) \n",
+            )
+        );
+    }
+
+    #[test]
+    fn gentzen_discharge() {
+        fn and(a: Judgement, b: Judgement) -> Judgement {
+            op!("∧", a, b)
+        }
+
+        let system = FormalSystem::new(
+            vec![Rule::new(
+                "∧I",
+                vec![op!("true", var("a")), op!("true", var("b"))],
+                op!("true", and(var("a"), var("b"))),
+            )],
+            4,
+        );
+        let assumption = op!("true", atom("p"));
+        let proof = system
+            .verify_under(
+                std::slice::from_ref(&assumption),
+                &op!("true", and(atom("p"), atom("p"))),
+            )
+            .unwrap()
+            .discharge(
+                &assumption,
+                "→I",
+                op!("true", op!("→", atom("p"), and(atom("p"), atom("p")))),
+            );
+
+        let config = PrettyPrintConfig {
+            gentzen: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            proof.to_string_tree_with(&config).unwrap(),
+            concat!(
+                "\n",
+                "     [true(p())]¹  [true(p())]¹\n",
+                "   ∧I--------------------------\n",
+                "         true(∧(p(), p()))     \n",
+                "→I¹----------------------------\n",
+                "    true(→(p(), ∧(p(), p())))  \n",
+            )
+        );
+    }
+
+    #[test]
+    fn axiom_leaves() {
+        let nat = FormalSystem::new(nat_rules(), 8);
+        let proof = nat.verify(&op!("nat", op!("succ", atom("zero")))).unwrap();
+
+        assert!(!proof.is_axiom());
+        assert_eq!(proof.premises[0].rule_label, "zero");
+        assert!(proof.premises[0].is_axiom());
+    }
+
+    #[test]
+    fn disequality_premises() {
+        let colors = FormalSystem::new(
+            vec![
+                Rule::taut("red", op!("color", atom("red"))),
+                Rule::taut("green", op!("color", atom("green"))),
+                Rule::new(
+                    "pair",
+                    vec![
+                        Judgement::diseq(var("x"), var("y")),
+                        op!("color", var("x")),
+                        op!("color", var("y")),
+                    ],
+                    op!("pair", var("x"), var("y")),
+                ),
+            ],
+            8,
+        );
+
+        assert!(colors
+            .verify(&op!("pair", atom("red"), atom("red")))
+            .is_none());
+        assert!(colors
+            .verify(&op!("pair", atom("red"), atom("green")))
+            .is_some());
+
+        // The constraint is suspended until `y` is bound, then steers the
+        // search away from `red`
+        let proof = colors.verify(&op!("pair", atom("red"), var("y"))).unwrap();
+        assert_eq!(proof.conclusion, op!("pair", atom("red"), atom("green")));
+        let proof = colors.verify(&op!("pair", var("x"), var("y"))).unwrap();
+        assert_eq!(proof.conclusion, op!("pair", atom("red"), atom("green")));
+
+        // A later rule reusing the name of a suspended variable is renamed
+        // apart from it, so binding its own `y` leaves the constraint alone
+        let unrelated = FormalSystem::new(
+            vec![
+                Rule::new(
+                    "top",
+                    vec![Judgement::diseq(var("y"), atom("a")), atom("other")],
+                    atom("top"),
+                ),
+                Rule::new("other", vec![op!("eq", var("y"), atom("a"))], atom("other")),
+                Rule::taut("refl", op!("eq", var("z"), var("z"))),
+            ],
+            8,
+        );
+        assert!(unrelated.verify(&atom("top")).is_some());
+
+        // An operator that merely shares the symbol is an ordinary premise,
+        // derived by the rules like any other
+        let named = FormalSystem::new(
+            vec![
+                Rule::new(
+                    "pair",
+                    vec![op!("!=", var("x"), var("y"))],
+                    op!("pair", var("x"), var("y")),
+                ),
+                Rule::taut("same", op!("!=", var("z"), var("z"))),
+            ],
+            8,
+        );
+        assert!(named
+            .verify(&op!("pair", atom("red"), atom("red")))
+            .is_some());
+        assert!(named
+            .verify(&op!("pair", atom("red"), atom("green")))
+            .is_none());
+        assert_ne!(
+            op!("!=", var("x"), var("y")),
+            Judgement::diseq(var("x"), var("y"))
+        );
+    }
+
+    #[test]
+    fn native_equality() {
+        let system = FormalSystem::new(
+            vec![Rule::new(
+                "pred",
+                vec![op!("eq", var("n"), succ(var("m")))],
+                op!("pred", var("n"), var("m")),
+            )],
+            8,
+        );
+
+        let goal = op!("eq", succ(var("x")), succ(zero()));
+        assert!(system.verify(&goal).is_none());
+
+        let system = system.with_equality("eq");
+        let proof = system.verify(&goal).unwrap();
+        assert_eq!(proof.conclusion, op!("eq", succ(zero()), succ(zero())));
+        assert_eq!(proof.rule_label, "refl");
+        assert!(proof.premises.is_empty());
+        assert!(system.verify(&op!("eq", zero(), succ(zero()))).is_none());
 
-        return result;
+        let proof = system
+            .verify(&op!("pred", succ(succ(zero())), var("x")))
+            .unwrap();
+        assert_eq!(
+            proof.conclusion,
+            op!("pred", succ(succ(zero())), succ(zero()))
+        );
+        assert!(system.verify(&op!("pred", zero(), var("x"))).is_none());
     }
 
-    fn verify_recursion(
-        &self,
-        bin: &mut HashSet<String>,
-        substitutions: &UnificationTable,
-        judgement: &Judgement,
-        height: u16,
-    ) -> Option<(Derivation, UnificationTable)> {
-        if height > self.max_derivation_height {
-            return None;
-        }
+    #[test]
+    fn loop_check_scope() {
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", atom("zero"))),
+                Rule::new(
+                    "succ",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", op!("succ", var("n"))),
+                ),
+                Rule::new("deep", vec![atom("deep")], atom("goal")),
+                Rule::new(
+                    "wrap",
+                    vec![op!("nat", op!("succ", atom("zero")))],
+                    atom("deep"),
+                ),
+                Rule::new(
+                    "shallow",
+                    vec![op!("nat", op!("succ", atom("zero")))],
+                    atom("goal"),
+                ),
+            ],
+            2,
+        );
 
-        let normalized_judgement = judgement
-            .apply_substitution(substitutions)
-            .rename_variables(&mut HashMap::<String, String>::new(), &|state, symbol| {
-                if let Some(new_symbol) = state.get(&symbol) {
-                    new_symbol.clone()
-                } else {
-                    let counter = state.len() + 1;
-                    let new_symbol = format!("x{}", counter);
-                    state.insert(symbol, new_symbol.clone());
-                    new_symbol.clone()
-                }
-            }).to_string();
+        // Through `deep`, nat(succ(zero())) runs out of height, which is only
+        // remembered for goals as high
+        let proof = system.verify(&atom("goal")).unwrap();
+        assert_eq!(proof.rule_label, "shallow");
 
-        if bin.contains(&normalized_judgement) {
-            return None;
-        }
+        let system = system.with_loop_check(LoopCheck::PerBranch);
+        let proof = system.verify(&atom("goal")).unwrap();
+        assert_eq!(proof.rule_label, "shallow");
 
-        let paths = self.get_possible_derivation_paths(substitutions, judgement);
+        // Cycles are still cut on a single branch
+        let cyclic = FormalSystem::new(
+            vec![
+                Rule::new("loop", vec![atom("p")], atom("p")),
+                Rule::taut("base", atom("p")),
+            ],
+            8,
+        )
+        .with_loop_check(LoopCheck::PerBranch);
+        assert_eq!(cyclic.verify(&atom("p")).unwrap().rule_label, "base");
+    }
 
-        for (substitutions, rule) in &paths {
-            for premises in rule.premises.iter().permutations(rule.premises.len()) {
-                let mut premises_proofs: Vec<Derivation> = vec![];
-                let mut substitutions = substitutions.clone();
-                let mut valid: bool = true;
+    #[test]
+    fn sorted_variables() {
+        let rules = vec![
+            Rule::taut("zero", op!("nat", atom("zero"))),
+            Rule::taut("wf", op!("wf", var("t"))).with_sort("t", "Tree"),
+            Rule::new("pick", vec![op!("nat", var("x"))], op!("pick", var("x")))
+                .with_sort("x", "Tree"),
+        ];
+        let untyped = FormalSystem::new(rules, 8);
+        let typed = untyped.clone().with_sort_inference(|term| match term {
+            Judgement::Operator { predicate, .. } => match predicate.as_str() {
+                "zero" | "succ" => Some(String::from("Nat")),
+                "empty" | "node" => Some(String::from("Tree")),
+                _ => None,
+            },
+            _ => None,
+        });
 
-                for premise in &premises {
-                    match self.verify_recursion(
-                        bin,
-                        &substitutions,
-                        &premise,
-                        height + 1,
-                    ) {
-                        Some((proof, new_substitutions)) => {
-                            substitutions.extend(new_substitutions);
-                            premises_proofs.push(proof);
-                        }
-                        None => {
-                            /*premises_proofs.push(Derivation {
-                                premises: vec![],
-                                conclusion: judgement.clone(),
-                                rule_label: String::from("abs"),
-                            });
-                            */
-                            valid = false;
-                            break;
-                        }
-                    }
-                }
+        assert!(untyped.verify(&op!("wf", atom("zero"))).is_some());
+        assert!(typed.verify(&op!("wf", atom("zero"))).is_none());
+        assert!(typed.verify(&op!("wf", atom("empty"))).is_some());
+        assert!(typed.verify(&op!("wf", atom("unknown"))).is_some());
+        assert!(typed.verify(&op!("wf", var("y"))).is_some());
 
-                if valid {
-                    let proof = Derivation {
-                        premises: premises_proofs.clone(),
-                        conclusion: judgement.clone(),
-                        rule_label: rule.name.clone(),
-                    };
+        // The sort is checked again once a premise binds the variable
+        assert!(untyped.verify(&op!("pick", var("y"))).is_some());
+        assert!(typed.verify(&op!("pick", var("y"))).is_none());
+    }
 
-                    return Some((proof, substitutions));
-                }
-            }
-        }
+    #[test]
+    fn prefixed_systems() {
+        let nat = FormalSystem::new(nat_rules(), 8);
+        let binary = FormalSystem::new(
+            vec![
+                Rule::taut("empty", op!("nat", atom("empty"))),
+                Rule::new(
+                    "bit",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", op!("bit", var("n"))),
+                ),
+            ],
+            8,
+        );
 
-        bin.insert(normalized_judgement);
+        let peano = nat.with_prefix("peano");
+        assert!(peano
+            .verify(&op!("peano::nat", op!("peano::succ", atom("peano::zero"))))
+            .is_some());
+        assert!(peano
+            .verify(&op!("nat", op!("succ", atom("zero"))))
+            .is_none());
 
-        None
+        let merged = peano.merge(binary.with_prefix("binary"));
+        assert!(merged
+            .verify(&op!("peano::nat", op!("peano::succ", atom("peano::zero"))))
+            .is_some());
+        assert!(merged
+            .verify(&op!(
+                "binary::nat",
+                op!("binary::bit", atom("binary::empty"))
+            ))
+            .is_some());
+        assert!(merged
+            .verify(&op!(
+                "peano::nat",
+                op!("binary::bit", atom("binary::empty"))
+            ))
+            .is_none());
     }
-}
 
-pub fn var(name: &str) -> Judgement {
-    Judgement::variable(name)
-}
+    #[test]
+    fn predicate_heights() {
+        let system = FormalSystem::new(rules(&[nat_rules, max_rules, hgt_rules]), 8);
+        let tall = op!(
+            "hgt",
+            node(node(node(empty(), empty()), empty()), empty()),
+            succ(succ(succ(zero())))
+        );
+        let deep_nat = op!("nat", succ(succ(zero())));
 
-pub fn atom(name: &str) -> Judgement {
-    Judgement::operator(name, vec![])
-}
+        assert!(system.verify(&tall).is_some());
+        assert!(system.verify(&deep_nat).is_some());
 
-fn next_name(name: &str) -> String {
-    let mut base = String::new();
-    let mut number = String::new();
-    let mut number_found = false;
+        let bounded = system.clone().with_predicate_heights(HashMap::from([
+            (String::from("hgt"), 2),
+            (String::from("nat"), 1),
+        ]));
+        assert!(bounded.verify(&tall).is_none());
+        assert!(bounded.verify(&deep_nat).is_none());
+        assert!(bounded.verify(&op!("nat", succ(zero()))).is_some());
 
-    let name_plus_one = String::from(name) + "1";
+        // The height of the whole derivation stays bounded
+        let shallow = system
+            .with_max_derivation_height(2)
+            .with_predicate_heights(HashMap::from([(String::from("hgt"), 3)]));
+        assert!(shallow.verify(&tall).is_none());
+        assert!(shallow.verify(&deep_nat).is_some());
 
-    for c in name.chars() {
-        if number_found {
-            if c.is_digit(10) {
-                number.push(c);
-            } else {
-                return name_plus_one;
-            }
-        } else {
-            if c.is_digit(10) {
-                number_found = true;
-                number.push(c);
-            } else {
-                base.push(c);
-            }
-        }
-    }
+        // Limits follow their predicates into prefixed and merged systems,
+        // the looser one winning when both systems bound the same predicate
+        let prefixed = bounded.with_prefix("ns");
+        let tall = tall.map_predicates(&|predicate| format!("ns::{}", predicate));
+        let deep_nat = deep_nat.map_predicates(&|predicate| format!("ns::{}", predicate));
+        assert!(prefixed.verify(&tall).is_none());
+        assert!(prefixed.verify(&deep_nat).is_none());
 
-    if number_found {
-        if let Ok(number) = u32::from_str_radix(number.as_str(), 10) {
-            return format!("{}{}", base, number + 1);
-        }
+        let merged = prefixed.clone().merge(FormalSystem::new(vec![], 8));
+        assert!(merged.verify(&tall).is_none());
+        assert!(merged.verify(&deep_nat).is_none());
+        let looser = FormalSystem::new(vec![], 8)
+            .with_predicate_heights(HashMap::from([(String::from("ns::nat"), 2)]));
+        let merged = prefixed.merge(looser);
+        assert!(merged.verify(&tall).is_none());
+        assert!(merged.verify(&deep_nat).is_some());
     }
 
-    return name_plus_one;
-}
+    #[test]
+    fn nearest_provable_goal() {
+        let system = FormalSystem::new(rules(&[max_rules, hgt_rules]), 8);
+        let tree = node(empty(), node(empty(), empty()));
+        let wrong = op!("hgt", tree.clone(), succ(zero()));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(system.verify(&wrong).is_none());
+        assert_eq!(
+            system.nearest_provable(&wrong, &[1]),
+            Some(op!("hgt", tree.clone(), succ(succ(zero()))))
+        );
+        // Nothing to vary, nothing to suggest
+        assert_eq!(system.nearest_provable(&wrong, &[]), None);
+        // Positions the goal doesn't have are not an error
+        assert_eq!(system.nearest_provable(&wrong, &[2]), None);
+        assert_eq!(system.nearest_provable(&var("x"), &[0]), None);
+        assert_eq!(
+            system.nearest_provable(&op!("hgt", tree, var("x")), &[1]),
+            Some(op!(
+                "hgt",
+                node(empty(), node(empty(), empty())),
+                succ(succ(zero()))
+            ))
+        );
 
-    #[test]
-    fn var_unification() {
-        let x = var("x");
-        let y = var("y");
+        // The closest solution wins over the first one found
+        let numbers = FormalSystem::new(
+            vec![
+                Rule::taut("far", op!("p", succ(succ(succ(zero()))))),
+                Rule::taut("near", op!("p", succ(succ(zero())))),
+            ],
+            8,
+        );
+        assert_eq!(
+            numbers.verify(&op!("p", var("x"))).unwrap().rule_label,
+            "far"
+        );
+        assert_eq!(
+            numbers.nearest_provable(&op!("p", succ(zero())), &[0]),
+            Some(op!("p", succ(succ(zero()))))
+        );
+    }
 
-        let unification = x.unify(&y);
-        assert!(unification.is_ok());
-        let unification = unification.unwrap();
+    #[test]
+    fn renamed_apart() {
+        let a = op!("max", var("n"), var("n1"), op!("f", var("m"), var("xs")));
+        let b = op!(
+            "max",
+            var("n"),
+            var("m"),
+            Judgement::Rest(String::from("xs"))
+        );
+        let renamed = a.rename_apart(&b);
 
-        let unified_left = x.apply_substitution(&unification);
-        let unified_right = y.apply_substitution(&unification);
+        assert!(renamed.get_variables().is_disjoint(&b.get_variables()));
+        assert!(renamed.is_variant_of(&a));
+        // Variables that did not clash keep their names
+        assert!(renamed.get_variables().contains("n1"));
+        assert_eq!(a.rename_apart(&atom("zero")), a);
 
-        assert_eq!(unified_left, unified_right);
-        assert_eq!(unified_left, var("x"));
-        assert_eq!(unified_right, var("x"));
+        // Rules are renamed the same way, apart from a set of names
+        let rule = Rule::new("r", vec![op!("p", var("n1"))], a.clone());
+        let taken = b.get_variables();
+        let renamed = rename_apart(&rule, &taken);
+        assert!(renamed.get_variables().is_disjoint(&taken));
+        assert_eq!(renamed.get_variables().len(), rule.get_variables().len());
+        assert!(renamed.conclusion.is_variant_of(&a));
     }
 
     #[test]
-    fn operator_unification() {
-        let left = op!("succ", var("n"));
-        let right = op!("succ", op!("succ", var("m")));
-
-        let unification = left.unify(&right);
-        assert!(unification.is_ok());
-        let unification = unification.unwrap();
+    fn verify_with_borrowed_rules() {
+        let rules = rules(&[nat_rules, sum_rules]);
+        let system = FormalSystem::new(rules.clone(), 4);
+        let two = op!("succ", op!("succ", atom("zero")));
 
-        let unified_left = left.apply_substitution(&unification);
-        let unified_right = right.apply_substitution(&unification);
+        for goal in [
+            op!("nat", two.clone()),
+            op!("nat", var("x")),
+            op!("sum", two.clone(), two.clone(), var("p")),
+            op!("sum", atom("zero"), two.clone(), atom("zero")),
+            op!("nat", op!("succ", op!("succ", op!("succ", two.clone())))),
+        ] {
+            assert_eq!(
+                verify_with(&rules, 4, &goal).map(|proof| proof.to_string_tree()),
+                system.verify(&goal).map(|proof| proof.to_string_tree())
+            );
+        }
+    }
 
-        assert_eq!(unified_left, unified_right);
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_system() {
+        let nat = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", atom("zero"))),
+                Rule::new(
+                    "succ",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", op!("succ", var("n"))),
+                )
+                .with_premise_label(0, "i")
+                .with_max_uses(8),
+            ],
+            6,
+        );
 
-        let n_substitution = unification.get("n".into());
-        assert!(n_substitution.is_some());
+        let saved = serde_json::to_string(&nat).unwrap();
+        let loaded: FormalSystem = serde_json::from_str(&saved).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), saved);
+        assert_eq!(loaded.max_derivation_height, 6);
+        assert_eq!(loaded.axioms.len(), 2);
+        for (rule, loaded) in zip(&nat.axioms, &loaded.axioms) {
+            assert_eq!(loaded.name, rule.name);
+            assert_eq!(loaded.premises, rule.premises);
+            assert_eq!(loaded.conclusion, rule.conclusion);
+        }
+        // Constants stay operators without subjects, apart from variables
+        assert_eq!(loaded.axioms[0].conclusion, op!("nat", atom("zero")));
+        assert_ne!(loaded.axioms[0].conclusion, op!("nat", var("zero")));
+        assert_eq!(loaded.axioms[1].premise_label(0), Some("i"));
+        assert_eq!(loaded.axioms[1].max_uses, Some(8));
 
-        let n_substitution = n_substitution.unwrap();
-        assert_eq!(n_substitution.clone(), op!("succ", var("m")));
+        let goal = op!("nat", op!("succ", op!("succ", atom("zero"))));
+        assert_eq!(
+            loaded.verify(&goal).unwrap().to_string_tree(),
+            nat.verify(&goal).unwrap().to_string_tree()
+        );
     }
 
     #[test]
-    fn infinite_unification() {
-        let left = var("n");
-        let right = op!("succ", var("n"));
+    fn existential_witness() {
+        let system = FormalSystem::new(sum_rules(), 8);
+        let three = succ(succ(succ(zero())));
 
-        let unification = left.unify(&right);
-        assert!(unification.is_err());
+        assert_eq!(
+            system.exists("x", &op!("sum", succ(zero()), var("x"), three.clone())),
+            Some(succ(succ(zero())))
+        );
+        assert_eq!(
+            system.exists("x", &op!("sum", three.clone(), var("x"), succ(zero()))),
+            None
+        );
+        // Any value will do for a variable the derivation leaves open
+        assert!(matches!(
+            system.exists("y", &op!("sum", var("y"), zero(), var("z"))),
+            Some(Judgement::Variable(..))
+        ));
+        // A variable that isn't in the goal has no witness
+        assert_eq!(
+            system.exists("w", &op!("sum", succ(zero()), var("x"), three.clone())),
+            None
+        );
+
+        let goal = op!("sum", succ(zero()), var("x"), three.clone());
+        let (proof, bindings) = system.verify_with_bindings(&goal).unwrap();
+        assert_eq!(
+            bindings,
+            UnificationTable::from([(String::from("x"), succ(succ(zero())))])
+        );
+        assert_eq!(goal.apply_substitution(&bindings), proof.conclusion);
+        let (_, bindings) = system
+            .verify_with_bindings(&op!("sum", var("x"), var("y"), var("x")))
+            .unwrap();
+        assert_eq!(
+            bindings,
+            UnificationTable::from([(String::from("y"), zero())])
+        );
     }
 
     #[test]
-    fn nat_formal_system() {
-        fn zero() -> Judgement {
-            atom("zero")
-        }
-        fn succ(n: Judgement) -> Judgement {
-            op!("succ", n)
-        }
-        fn empty() -> Judgement {
-            atom("empty")
-        }
-        fn node(t1: Judgement, t2: Judgement) -> Judgement {
-            op!("node", t1, t2)
-        }
-
+    fn display_string() {
         let nat = FormalSystem::new(
             vec![
+                Rule::taut("zero", op!("nat", atom("zero"))),
                 Rule::new(
                     "succ",
                     vec![op!("nat", var("n"))],
-                    op!("nat", succ(var("n"))),
-                ),
-                Rule::taut("zero", op!("nat", zero())),
-                Rule::new(
-                    "tree",
-                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
-                    op!("tree", op!("node", var("a1"), var("a2"))),
+                    op!("nat", op!("succ", var("n"))),
                 ),
-                Rule::taut("empty", op!("tree", atom("empty"))),
-                Rule::taut("s1", op!("sum", var("n"), zero(), var("n"))),
-                Rule::new(
-                    "s2",
-                    vec![op!("sum", var("n"), var("m"), var("p"))],
-                    op!("sum", var("n"), succ(var("m")), succ(var("p"))),
-                ),
-                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
-                Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
-                Rule::new(
-                    "max3",
-                    vec![op!("max", var("n"), var("m"), var("p"))],
-                    op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
-                ),
-                Rule::taut("h1", op!("hgt", atom("empty"), zero())),
                 Rule::new(
-                    "h2",
-                    vec![
-                        op!("hgt", var("t1"), var("n1")),
-                        op!("hgt", var("t2"), var("n2")),
-                        op!("max", var("n1"), var("n2"), var("n")),
-                    ],
-                    op!("hgt", op!("node", var("t1"), var("t2")), succ(var("n"))),
-                ),
+                    "add",
+                    vec![op!("nat", var("n")), op!("nat", var("m"))],
+                    op!("nat", op!("add", var("n"), var("m"))),
+                )
+                .with_premise_label(1, "r"),
             ],
-            8,
+            4,
         );
 
-        assert!(nat.verify(&op!("nat", atom("zero"))).is_some());
-        assert!(nat.verify(&op!("sum", zero(), zero(), zero())).is_some());
-        assert!(!nat
-            .verify(&op!("sum", zero(), succ(zero()), zero()))
-            .is_some());
-        assert!(nat
-            .verify(&op!(
-                "max",
-                succ(zero()),
-                succ(succ(zero())),
-                succ(succ(zero()))
-            ))
-            .is_some());
-        assert!(nat
-            .verify(&op!(
-                "max",
-                succ(zero()),
-                succ(succ(zero())),
-                succ(succ(zero()))
-            ))
-            .is_some());
-        assert!(nat
-            .verify(&op!("hgt", node(empty(), empty()), succ(zero())))
-            .is_some());
-        assert!(nat
-            .verify(&op!(
-                "hgt",
-                node(empty(), node(empty(), empty())),
-                succ(succ(zero()))
-            ))
-            .is_some());
-        assert!(!nat
-            .verify(&op!(
-                "hgt",
-                node(empty(), node(empty(), empty())),
-                succ(zero())
-            ))
-            .is_some());
-        assert!(nat
-            .verify(&op!("hgt", node(empty(), node(empty(), empty())), var("x")))
-            .is_some());
+        assert_eq!(
+            nat.to_display_string(),
+            "1.\n----------- zero\nnat(zero())\n\n\
+             2.\nnat(n)\n------------ succ\nnat(succ(n))\n\n\
+             3.\nnat(n)    (r): nat(m)\n--------------------- add\nnat(add(n, m))"
+        );
+        // Each rule still reads back as itself
+        for axiom in &nat.axioms {
+            let parsed = parser::rule(&format!("{}\n", axiom.to_bar_string()))
+                .unwrap()
+                .1;
+            assert_eq!(parsed.to_string(), axiom.to_string());
+            assert_eq!(parsed.name, axiom.name);
+        }
+    }
+
+    #[test]
+    fn subproofs() {
+        let system = FormalSystem::new(rules(&[max_rules, hgt_rules]), 8);
+        let proof = system
+            .verify(&op!("hgt", node(empty(), node(empty(), empty())), var("h")))
+            .unwrap();
+
+        assert_eq!(proof.subproof_at(&[]).unwrap().rule_label, "h2");
+        let max = proof.subproof_at(&[2]).unwrap();
+        assert_eq!(
+            max.conclusion,
+            op!("max", zero(), succ(zero()), succ(zero()))
+        );
+        let nested = proof.subproof_at(&[1, 2]).unwrap();
+        assert_eq!(nested.conclusion, op!("max", zero(), zero(), zero()));
+        assert_eq!(nested.rule_label, "max1");
+        assert!(proof.subproof_at(&[1, 2, 0]).is_none());
+        assert!(proof.subproof_at(&[3]).is_none());
+    }
+
+    // Unifiers of random terms make both sides equal
+    #[test]
+    fn random_unifiers() {
+        fn term(rng: &mut enumerate::Rng, depth: usize) -> Judgement {
+            match rng.below(if depth == 0 { 4 } else { 7 }) {
+                0 => var("x"),
+                1 => var("y"),
+                2 => var("z"),
+                3 => atom("a"),
+                4 => op!("g", term(rng, depth - 1)),
+                _ => op!("f", term(rng, depth - 1), term(rng, depth - 1)),
+            }
+        }
+
+        let mut rng = enumerate::Rng::seeded(7);
+        for _ in 0..20000 {
+            let left = term(&mut rng, 4);
+            let right = term(&mut rng, 4);
+            if let Ok(substitutions) = left.unify(&right) {
+                assert_eq!(
+                    left.apply_substitution(&substitutions),
+                    right.apply_substitution(&substitutions),
+                    "{} and {}",
+                    left,
+                    right
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::space0;
+use nom::combinator::map;
+use nom::multi::separated_list0;
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::IResult;
+
+use super::parser::{nesting, parse_complete, symbol, ws, ParseError};
+use super::Judgement;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Infix {
+    // Operators of higher precedence bind tighter
+    precedence: u8,
+    associativity: Associativity,
+}
+
+// Binary predicates written between their subjects, as in `a + b` for
+// `+(a, b)`. The same registry reads and writes them, so that a judgement
+// parsed with it prints back the way it was written
+#[derive(Debug, Clone, Default)]
+pub struct OperatorRegistry {
+    infix: HashMap<String, Infix>,
+}
+
+impl OperatorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_infix(
+        mut self,
+        operator: &str,
+        precedence: u8,
+        associativity: Associativity,
+    ) -> Self {
+        self.infix.insert(
+            String::from(operator),
+            Infix {
+                precedence,
+                associativity,
+            },
+        );
+        self
+    }
+
+    pub fn parse(&self, input: &str) -> Result<Judgement, ParseError> {
+        parse_complete(
+            |input| self.expression(input, 0, 0),
+            input,
+            "expected a judgement",
+        )
+    }
+
+    pub fn display<'a>(&'a self, judgement: &'a Judgement) -> WithOperators<'a> {
+        WithOperators {
+            registry: self,
+            judgement,
+        }
+    }
+
+    // Operands are read by precedence climbing: the right operand of an
+    // operator only takes in operators binding tighter than `min_precedence`.
+    // `depth` counts the enclosing expressions
+    fn expression<'a>(
+        &self,
+        input: &'a str,
+        min_precedence: u16,
+        depth: usize,
+    ) -> IResult<&'a str, Judgement> {
+        nesting(input, depth)?;
+        let (mut input, mut left) = self.operand(input, depth)?;
+        while let Ok((rest, (operator, infix))) = self.operator(input) {
+            let precedence = u16::from(infix.precedence);
+            if precedence < min_precedence {
+                break;
+            }
+            let next = match infix.associativity {
+                Associativity::Left => precedence + 1,
+                Associativity::Right => precedence,
+            };
+            let (rest, right) = self.expression(rest, next, depth + 1)?;
+            left = Judgement::operator(operator, vec![left, right]);
+            input = rest;
+        }
+        Ok((input, left))
+    }
+
+    fn operand<'a>(&self, input: &'a str, depth: usize) -> IResult<&'a str, Judgement> {
+        let subject = |input| self.expression(input, 0, depth + 1);
+        alt((
+            delimited(ws(tag("(")), subject, ws(tag(")"))),
+            map(
+                pair(
+                    preceded(space0, symbol),
+                    delimited(
+                        pair(ws(tag("(")), space0),
+                        separated_list0(ws(tag(",")), subject),
+                        pair(space0, tag(")")),
+                    ),
+                ),
+                |(predicate, subjects)| Judgement::operator(&predicate, subjects),
+            ),
+            map(ws(terminated(symbol, tag("..."))), Judgement::Rest),
+            map(ws(symbol), |symbol| Judgement::Variable(symbol, None)),
+        ))(input)
+    }
+
+    // The longest registered operator at the start of `input`, so that `<=`
+    // is not read as `<`
+    fn operator<'a, 'r>(&'r self, input: &'a str) -> IResult<&'a str, (&'r str, Infix)> {
+        let input = input.trim_start_matches([' ', '\t']);
+        self.infix
+            .iter()
+            .filter(|(operator, _)| input.starts_with(operator.as_str()))
+            .max_by_key(|(operator, _)| operator.len())
+            .map(|(operator, infix)| (&input[operator.len()..], (operator.as_str(), *infix)))
+            .ok_or(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Tag,
+            )))
+    }
+
+    // Writes `judgement` within an operator of precedence `context`, which it
+    // is parenthesized against if it binds more loosely. Contexts go one past
+    // the highest precedence, hence the wider type
+    fn write(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        judgement: &Judgement,
+        context: u16,
+    ) -> std::fmt::Result {
+        let Judgement::Operator {
+            predicate,
+            subjects,
+        } = judgement
+        else {
+            return Display::fmt(judgement, f);
+        };
+
+        match (self.infix.get(predicate), subjects.as_slice()) {
+            (Some(infix), [left, right]) => {
+                let precedence = u16::from(infix.precedence);
+                let (left_context, right_context) = match infix.associativity {
+                    Associativity::Left => (precedence, precedence + 1),
+                    Associativity::Right => (precedence + 1, precedence),
+                };
+                let parenthesized = precedence < context;
+                if parenthesized {
+                    f.write_str("(")?;
+                }
+                self.write(f, left, left_context)?;
+                write!(f, " {} ", predicate)?;
+                self.write(f, right, right_context)?;
+                if parenthesized {
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+            _ => {
+                f.write_str(predicate)?;
+                f.write_str("(")?;
+                for (i, subject) in subjects.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.write(f, subject, 0)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+// A judgement printed with the infix operators of a registry
+pub struct WithOperators<'a> {
+    registry: &'a OperatorRegistry,
+    judgement: &'a Judgement,
+}
+
+impl Display for WithOperators<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.registry.write(f, self.judgement, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atom, op, var};
+
+    fn arithmetic() -> OperatorRegistry {
+        OperatorRegistry::new()
+            .with_infix("+", 1, Associativity::Left)
+            .with_infix("*", 2, Associativity::Left)
+            .with_infix("->", 0, Associativity::Right)
+    }
+
+    #[test]
+    fn infix_round_trip() {
+        let registry = arithmetic();
+
+        let judgement = registry.parse("a + b * c").unwrap();
+        assert_eq!(judgement, op!("+", var("a"), op!("*", var("b"), var("c"))));
+        assert_eq!(registry.display(&judgement).to_string(), "a + b * c");
+
+        for input in [
+            "(a + b) * c",
+            "a + b + c",
+            "a + (b + c)",
+            "a -> b -> c",
+            "(a -> b) -> c",
+            "nat(succ(n) + zero())",
+            "ctx(a, xs...) -> b",
+        ] {
+            let judgement = registry.parse(input).unwrap();
+            assert_eq!(registry.display(&judgement).to_string(), input);
+        }
+
+        assert_eq!(
+            registry.parse("a + b + c").unwrap(),
+            op!("+", op!("+", var("a"), var("b")), var("c"))
+        );
+        assert_eq!(
+            registry.parse("a -> b -> c").unwrap(),
+            op!("->", var("a"), op!("->", var("b"), var("c")))
+        );
+        // Operators keep their prefix form outside the registry
+        assert_eq!(op!("+", atom("zero"), var("n")).to_string(), "+(zero(), n)");
+        assert_eq!(
+            registry
+                .display(&op!("+", atom("zero"), var("n")))
+                .to_string(),
+            "zero() + n"
+        );
+        assert!(registry.parse("a + ").is_err());
+    }
+
+    #[test]
+    fn highest_precedence() {
+        let registry = arithmetic()
+            .with_infix("^", u8::MAX, Associativity::Right)
+            .with_infix(".", u8::MAX, Associativity::Left);
+
+        for input in [
+            "a ^ b ^ c",
+            "(a ^ b) ^ c",
+            "a . b . c",
+            "a . (b . c)",
+            "a * b ^ c",
+        ] {
+            let judgement = registry.parse(input).unwrap();
+            assert_eq!(registry.display(&judgement).to_string(), input);
+        }
+        assert_eq!(
+            registry.parse("a . b . c").unwrap(),
+            op!(".", op!(".", var("a"), var("b")), var("c"))
+        );
+    }
+}
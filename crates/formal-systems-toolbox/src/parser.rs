@@ -0,0 +1,1191 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::{
+    digit1, line_ending, multispace0, multispace1, satisfy, space0, space1,
+};
+use nom::combinator::{eof, map, map_res, opt, recognize, verify};
+use nom::multi::{many0, many1, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+
+use super::enumerate::{list, numeral};
+use super::{FormalSystem, Judgement, Rule, Span, DEFAULT_MAX_DERIVATION_HEIGHT};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub(crate) fn at(input: &str, remaining: &str, message: &str) -> Self {
+        let consumed = &input[..input.len() - remaining.len()];
+        let line = consumed.matches('\n').count() + 1;
+        let column = consumed.len() - consumed.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        Self {
+            line,
+            column,
+            message: String::from(message),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Whatever can appear on its own in a rules file or at a prompt
+#[derive(Debug, Clone)]
+pub enum Ast {
+    Rule(Box<Rule>),
+    Judgement(Judgement),
+}
+
+pub fn ws<'a, O>(
+    inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    delimited(space0, inner, space0)
+}
+
+pub fn symbol(input: &str) -> IResult<&str, String> {
+    alt((
+        quoted('\''),
+        quoted('"'),
+        map(
+            recognize(pair(
+                satisfy(|c| c.is_alphabetic()),
+                take_while(|c: char| c.is_alphanumeric() || c == '\''),
+            )),
+            String::from,
+        ),
+    ))(input)
+}
+
+// Any name between `quote`s, where a backslash escapes the character after it
+fn quoted(quote: char) -> impl FnMut(&str) -> IResult<&str, String> {
+    move |input| {
+        let error = || nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char));
+        let mut chars = input.strip_prefix(quote).ok_or_else(error)?.char_indices();
+        let mut name = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => name.push(chars.next().ok_or_else(error)?.1),
+                c if c == quote => return Ok((&input[quote.len_utf8() + i + 1..], name)),
+                c => name.push(c),
+            }
+        }
+        Err(error())
+    }
+}
+
+// Whether `name` must be quoted to be read back as a single symbol. Reserved
+// predicates and operators made only of punctuation, like `+` or `!=`, are
+// always written as they are
+pub(crate) fn needs_quotes(name: &str) -> bool {
+    let mut chars = name.chars();
+    let bare = chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphanumeric() || c == '\'');
+    name.chars().any(char::is_alphanumeric) && !bare
+}
+
+pub(crate) fn write_symbol(f: &mut impl std::fmt::Write, name: &str) -> std::fmt::Result {
+    if !needs_quotes(name) {
+        return f.write_str(name);
+    }
+    f.write_str("'")?;
+    for c in name.chars() {
+        if c == '\'' || c == '\\' {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    f.write_str("'")
+}
+
+// Judgements nested deeper are rejected while parsing, which recurses once per
+// level and would otherwise overflow the stack on hostile input. Each item of a
+// list literal nests one level deeper than the previous one
+pub(crate) const MAX_NESTING: usize = 256;
+
+// Fails for good at `input`, a judgement nested `depth` levels deep, when that
+// is too deep
+pub(crate) fn nesting(input: &str, depth: usize) -> Result<(), nom::Err<nom::error::Error<&str>>> {
+    if depth > MAX_NESTING {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    Ok(())
+}
+
+pub fn predicate(input: &str) -> IResult<&str, Judgement> {
+    nested_predicate(0)(input)
+}
+
+fn nested_predicate(depth: usize) -> impl FnMut(&str) -> IResult<&str, Judgement> {
+    move |input| {
+        let (input, predicate) = preceded(space0, symbol)(input)?;
+        let (rest, _) = pair(ws(tag("(")), space0)(input)?;
+        nesting(input.trim_start_matches([' ', '\t']), depth + 1)?;
+        let (rest, subjects) = terminated(
+            separated_list0(ws(tag(",")), nested_judgement(depth + 1)),
+            pair(space0, tag(")")),
+        )(rest)?;
+        Ok((rest, Judgement::operator(&predicate, subjects)))
+    }
+}
+
+// Besides predicates and variables, a judgement may be a decimal numeral or a
+// bracketed list. These always stand for the encodings `numeral` and `list`
+// build: `2` is `succ(succ(zero()))` and `[a, b]` is `cons(a, cons(b, nil()))`,
+// so `succ`, `zero`, `cons` and `nil` are reserved for them. Neither form was
+// valid before, since symbols start with a letter or a quote, so no other
+// input changes meaning; systems encoding numbers or lists with other names
+// should just not use the literals
+pub fn judgement(input: &str) -> IResult<&str, Judgement> {
+    nested_judgement(0)(input)
+}
+
+fn nested_judgement(depth: usize) -> impl FnMut(&str) -> IResult<&str, Judgement> {
+    move |input| {
+        alt((
+            nested_predicate(depth),
+            // As written by `Judgement::collapsed`
+            map_res(digit1, |digits: &str| match digits.parse() {
+                Ok(n) if n <= MAX_NESTING => Ok(numeral(n)),
+                _ => Err(()),
+            }),
+            map(nested_list(depth), list),
+            map(terminated(symbol, tag("...")), Judgement::Rest),
+            map(symbol, |symbol| Judgement::Variable(symbol, None)),
+        ))(input)
+    }
+}
+
+// The items of a list literal, each nested one level deeper than the previous
+fn nested_list(depth: usize) -> impl FnMut(&str) -> IResult<&str, Vec<Judgement>> {
+    move |input| {
+        let (mut input, _) = pair(tag("["), space0)(input)?;
+        let mut items: Vec<Judgement> = vec![];
+        loop {
+            let item = if items.is_empty() {
+                input
+            } else {
+                match ws(tag(","))(input) {
+                    Ok((rest, _)) => rest,
+                    Err(_) => break,
+                }
+            };
+            let depth = depth + items.len() + 1;
+            nesting(item, depth)?;
+            match nested_judgement(depth)(item) {
+                Ok((rest, judgement)) => {
+                    input = rest;
+                    items.push(judgement);
+                }
+                Err(nom::Err::Error(_)) if items.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let (input, _) = pair(space0, tag("]"))(input)?;
+        Ok((input, items))
+    }
+}
+
+// Premises on the same line are separated by a tab or at least four spaces
+pub fn judgement_separator(input: &str) -> IResult<&str, ()> {
+    map(
+        verify(space1, |separator: &str| {
+            separator.contains('\t') || separator.len() >= 4
+        }),
+        |_| (),
+    )(input)
+}
+
+// A name in parentheses before a premise, as in `(i): nat(n)`
+pub fn premise_label(input: &str) -> IResult<&str, String> {
+    map(
+        terminated(
+            delimited(
+                tag("("),
+                ws(take_while1(|c: char| c.is_alphanumeric() || c == '\'')),
+                tag(")"),
+            ),
+            pair(ws(tag(":")), space0),
+        ),
+        String::from,
+    )(input)
+}
+
+pub fn premises(input: &str) -> IResult<&str, Vec<(Option<String>, Judgement)>> {
+    delimited(
+        space0,
+        separated_list1(judgement_separator, pair(opt(premise_label), judgement)),
+        pair(space0, line_ending),
+    )(input)
+}
+
+// A line of dashes, optionally followed by the rule name
+pub fn rule_bar(input: &str) -> IResult<&str, String> {
+    map(
+        delimited(
+            pair(space0, take_while1(|c| c == '-')),
+            opt(preceded(space1, symbol)),
+            pair(space0, line_ending),
+        ),
+        |name| name.unwrap_or_default(),
+    )(input)
+}
+
+// A line above a rule giving it an attribute, as in `@category "arithmetic"`
+pub fn attribute(input: &str) -> IResult<&str, (String, String)> {
+    delimited(
+        pair(space0, tag("@")),
+        pair(
+            map(
+                take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+                String::from,
+            ),
+            preceded(space1, symbol),
+        ),
+        pair(space0, line_ending),
+    )(input)
+}
+
+pub fn rule(input: &str) -> IResult<&str, Rule> {
+    map(
+        tuple((
+            many0(attribute),
+            opt(premises),
+            rule_bar,
+            preceded(space0, judgement),
+            space0,
+        )),
+        |(attributes, premises, name, conclusion, _)| {
+            let (labels, premises): (Vec<Option<String>>, Vec<Judgement>) =
+                premises.unwrap_or_default().into_iter().unzip();
+            labels
+                .into_iter()
+                .enumerate()
+                .fold(
+                    Rule::new(&name, premises, conclusion),
+                    |rule, (position, label)| match label {
+                        Some(label) => rule.with_premise_label(position, &label),
+                        None => rule,
+                    },
+                )
+                .with_attributes(attributes.into_iter().collect())
+        },
+    )(input)
+}
+
+// A rule if a bar follows, a standalone judgement otherwise
+pub fn parse_item(input: &str) -> IResult<&str, Ast> {
+    alt((
+        map(rule, |rule| Ast::Rule(Box::new(rule))),
+        map(ws(judgement), Ast::Judgement),
+    ))(input)
+}
+
+fn blank_line(input: &str) -> IResult<&str, ()> {
+    map(pair(space0, line_ending), |_| ())(input)
+}
+
+// A rule together with the span where it starts within `file`
+fn located_rule<'a>(file: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, Rule> {
+    move |input| {
+        let start = input.trim_start_matches([' ', '\t']);
+        let (input, rule) = rule(input)?;
+        let ParseError { line, column, .. } = ParseError::at(file, start, "");
+        Ok((input, rule.with_span(Span { line, column })))
+    }
+}
+
+// Rules are located taking `input` to be the whole file
+pub fn rules(input: &str) -> IResult<&str, Vec<Rule>> {
+    delimited(
+        many0(blank_line),
+        separated_list0(pair(line_ending, many1(blank_line)), located_rule(input)),
+        pair(many0(alt((blank_line, map(space1, |_| ())))), eof),
+    )(input)
+}
+
+// The error of a parse of `input` that failed, with `message` unless it failed
+// for nesting too deeply
+fn parse_error(input: &str, error: nom::Err<nom::error::Error<&str>>, message: &str) -> ParseError {
+    match error {
+        nom::Err::Error(e) | nom::Err::Failure(e) => {
+            let message = match e.code {
+                nom::error::ErrorKind::TooLarge => "nested too deeply",
+                _ => message,
+            };
+            ParseError::at(input, e.input, message)
+        }
+        nom::Err::Incomplete(_) => ParseError::at(input, "", message),
+    }
+}
+
+pub(crate) fn parse_complete<'a, O>(
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+    input: &'a str,
+    message: &str,
+) -> Result<O, ParseError> {
+    match terminated(&mut parser, pair(ws(opt(line_ending)), eof))(input) {
+        Ok((_, result)) => Ok(result),
+        Err(error) => Err(parse_error(input, error, message)),
+    }
+}
+
+// An atom of an s-expression runs until whitespace or a parenthesis
+fn sexp_atom(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| !c.is_whitespace() && c != '(' && c != ')')(input)
+}
+
+// `(succ (zero))` stands for `succ(zero())`. Bare atoms are variables, or rest
+// variables when ending in `...`, unless they are listed among `constants`
+pub fn sexp<'a>(
+    constants: &'a HashSet<String>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Judgement> {
+    nested_sexp(constants, 0)
+}
+
+fn nested_sexp<'a>(
+    constants: &'a HashSet<String>,
+    depth: usize,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Judgement> {
+    move |input| {
+        if input.starts_with('(') {
+            nesting(input, depth + 1)?;
+        }
+        alt((
+            map(
+                delimited(
+                    pair(tag("("), multispace0),
+                    pair(
+                        sexp_atom,
+                        many0(preceded(multispace1, nested_sexp(constants, depth + 1))),
+                    ),
+                    pair(multispace0, tag(")")),
+                ),
+                |(predicate, subjects)| Judgement::operator(predicate, subjects),
+            ),
+            map(sexp_atom, |atom| match atom.strip_suffix("...") {
+                _ if constants.contains(atom) => Judgement::operator(atom, vec![]),
+                Some(symbol) if !symbol.is_empty() => Judgement::Rest(String::from(symbol)),
+                _ => Judgement::variable(atom),
+            }),
+        ))(input)
+    }
+}
+
+impl Judgement {
+    pub fn to_sexp(&self) -> String {
+        match self {
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => {
+                let mut sexp = format!("({}", predicate);
+                for subject in subjects {
+                    sexp.push(' ');
+                    sexp.push_str(&subject.to_sexp());
+                }
+                sexp + ")"
+            }
+            Judgement::Variable(symbol, _) => symbol.clone(),
+            Judgement::Rest(symbol) => format!("{}...", symbol),
+            Judgement::Premise(premise, subjects) => {
+                let mut sexp = format!("({}", premise.symbol());
+                for subject in subjects {
+                    sexp.push(' ');
+                    sexp.push_str(&subject.to_sexp());
+                }
+                sexp + ")"
+            }
+        }
+    }
+
+    pub fn from_sexp(input: &str) -> Result<Judgement, ParseError> {
+        Self::from_sexp_with_constants(input, &HashSet::new())
+    }
+
+    pub fn from_sexp_with_constants(
+        input: &str,
+        constants: &HashSet<String>,
+    ) -> Result<Judgement, ParseError> {
+        parse_complete(
+            delimited(multispace0, sexp(constants), multispace0),
+            input,
+            "expected an s-expression",
+        )
+    }
+}
+
+impl FromStr for Judgement {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_complete(ws(judgement), s, "expected a judgement")
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_complete(preceded(many0(blank_line), rule), s, "expected a rule")
+    }
+}
+
+/// Parses a whole rules file, where rules are separated by blank lines.
+///
+/// ```
+/// use formal_systems_toolbox::*;
+///
+/// let nat = FormalSystem::try_from(
+///     "
+/// ------ zero
+/// nat(zero())
+///
+/// nat(n)
+/// ------------ succ
+/// nat(succ(n))
+/// ",
+/// )
+/// .unwrap()
+/// .with_max_derivation_height(4);
+///
+/// assert!(nat.verify(&"nat(succ(succ(zero())))".parse().unwrap()).is_some());
+/// ```
+impl TryFrom<&str> for FormalSystem {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let axioms = parse_complete(rules, value, "expected a rule")?;
+        Ok(FormalSystem::new(axioms, DEFAULT_MAX_DERIVATION_HEIGHT))
+    }
+}
+
+// Declared arities of predicates, as in `succ/1`. Parsing against a signature
+// rejects applications to another number of subjects, which would otherwise
+// only show up as goals that fail to unify with any rule
+#[derive(Debug, Clone, Default)]
+pub struct Signature {
+    arities: HashMap<String, usize>,
+}
+
+impl Signature {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_arity(mut self, predicate: &str, arity: usize) -> Self {
+        self.arities.insert(String::from(predicate), arity);
+        self
+    }
+
+    pub fn arity(&self, predicate: &str) -> Option<usize> {
+        self.arities.get(predicate).copied()
+    }
+
+    pub fn parse_judgement(&self, input: &str) -> Result<Judgement, ParseError> {
+        let judgement = input.parse()?;
+        self.check(input, [&judgement])?;
+        Ok(judgement)
+    }
+
+    pub fn parse_system(&self, input: &str) -> Result<FormalSystem, ParseError> {
+        let system = FormalSystem::try_from(input)?;
+        let judgements = system
+            .axioms
+            .iter()
+            .flat_map(|axiom| axiom.premises.iter().chain([&axiom.conclusion]));
+        self.check(input, judgements)?;
+        Ok(system)
+    }
+
+    // Points at the first application in `input` of a declared predicate to
+    // the wrong number of subjects, found among the `judgements` parsed from it
+    fn check<'j>(
+        &self,
+        input: &str,
+        judgements: impl IntoIterator<Item = &'j Judgement>,
+    ) -> Result<(), ParseError> {
+        let mut pending: Vec<&Judgement> = judgements.into_iter().collect();
+        pending.reverse();
+        while let Some(judgement) = pending.pop() {
+            let Judgement::Operator {
+                predicate,
+                subjects,
+            } = judgement
+            else {
+                continue;
+            };
+            match self.arity(predicate) {
+                Some(arity) if arity != subjects.len() => {
+                    let remaining = application(input, predicate, subjects.len()).unwrap_or(input);
+                    return Err(ParseError::at(
+                        input,
+                        remaining,
+                        &format!(
+                            "{}/{} applied to {} subjects",
+                            predicate,
+                            arity,
+                            subjects.len()
+                        ),
+                    ));
+                }
+                _ => pending.extend(subjects.iter().rev().map(|subject| subject.as_ref())),
+            }
+        }
+        Ok(())
+    }
+}
+
+// The text from the first application of `predicate` to `count` subjects, in
+// one pass that counts the commas directly inside each pair of brackets
+fn application<'a>(input: &'a str, predicate: &str, count: usize) -> Option<&'a str> {
+    struct Open {
+        start: usize,
+        name: Option<String>,
+        commas: usize,
+        empty: bool,
+    }
+
+    let mut open: Vec<Open> = vec![];
+    let mut name: Option<(usize, String)> = None;
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_alphabetic() || c == '\'' || c == '"' {
+            if let Some(parent) = open.last_mut() {
+                parent.empty = false;
+            }
+            let mut symbol = String::new();
+            if c == '\'' || c == '"' {
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        '\\' => symbol.extend(chars.next().map(|(_, escaped)| escaped)),
+                        next if next == c => break,
+                        next => symbol.push(next),
+                    }
+                }
+            } else {
+                symbol.push(c);
+                while let Some(&(_, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '\'') {
+                        break;
+                    }
+                    symbol.push(next);
+                    chars.next();
+                }
+            }
+            name = Some((i, symbol));
+            continue;
+        }
+        match c {
+            '(' | '[' => {
+                if let Some(parent) = open.last_mut() {
+                    parent.empty = false;
+                }
+                let (start, name) = match name.take() {
+                    Some((start, symbol)) if c == '(' => (start, Some(symbol)),
+                    _ => (i, None),
+                };
+                open.push(Open {
+                    start,
+                    name,
+                    commas: 0,
+                    empty: true,
+                });
+            }
+            ')' | ']' => {
+                name = None;
+                let closed = open.pop()?;
+                let subjects = if closed.empty { 0 } else { closed.commas + 1 };
+                if closed.name.as_deref() == Some(predicate) && subjects == count {
+                    return Some(&input[closed.start..]);
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                name = None;
+                if let Some(parent) = open.last_mut() {
+                    parent.empty = false;
+                    if c == ',' {
+                        parent.commas += 1;
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+// How bare identifiers, written without parentheses, are read. By default
+// they are all variables; following Prolog, only those starting with an
+// uppercase letter are, the others being constants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamingConvention {
+    #[default]
+    Bare,
+    Prolog,
+}
+
+impl NamingConvention {
+    pub fn parse_judgement(self, input: &str) -> Result<Judgement, ParseError> {
+        Ok(self.apply(&input.parse()?))
+    }
+
+    pub fn parse_system(self, input: &str) -> Result<FormalSystem, ParseError> {
+        let mut system = FormalSystem::try_from(input)?;
+        for axiom in &mut system.axioms {
+            axiom.conclusion = self.apply(&axiom.conclusion);
+            for premise in &mut axiom.premises {
+                *premise = self.apply(premise);
+            }
+        }
+        Ok(system)
+    }
+
+    fn apply(self, judgement: &Judgement) -> Judgement {
+        match judgement {
+            Judgement::Variable(symbol, _)
+                if self == NamingConvention::Prolog && !symbol.starts_with(char::is_uppercase) =>
+            {
+                Judgement::operator(symbol, vec![])
+            }
+            Judgement::Operator {
+                predicate,
+                subjects,
+            } => Judgement::operator(
+                predicate,
+                subjects.iter().map(|subject| self.apply(subject)).collect(),
+            ),
+            Judgement::Premise(premise, subjects) => Judgement::Premise(
+                *premise,
+                subjects
+                    .iter()
+                    .map(|subject| Arc::new(self.apply(subject)))
+                    .collect(),
+            ),
+            _ => judgement.clone(),
+        }
+    }
+}
+
+// A rules file kept parsed while it is edited. Rules are separated by blank
+// lines, so an edit only requires parsing again the rules it touches, along
+// with those it joins to them by removing the blank lines in between
+#[derive(Debug, Clone)]
+pub struct IncrementalRules {
+    source: String,
+    // Each rule with the lines it spans in `source`, in order
+    rules: Vec<(Range<usize>, Rule)>,
+}
+
+impl IncrementalRules {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let rules = parse_blocks(source, 0..source.len())?;
+        Ok(Self {
+            source: String::from(source),
+            rules,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|(_, rule)| rule)
+    }
+
+    pub fn to_system(&self) -> FormalSystem {
+        FormalSystem::new(
+            self.rules().cloned().collect(),
+            DEFAULT_MAX_DERIVATION_HEIGHT,
+        )
+    }
+
+    // Replaces the bytes of the source in `range` with `replacement`, and
+    // returns how many rules had to be parsed again. On error the previous
+    // source and rules are kept
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Result<usize, ParseError> {
+        let mut source = self.source.clone();
+        source.replace_range(range.clone(), replacement);
+        let mut region = surrounding_rules(&source, range.start, range.start + replacement.len());
+        // The text after the region is unchanged, only moved
+        let moved = |position: usize| position + replacement.len() - range.len();
+        let mut old_end = region.end + range.len() - replacement.len();
+
+        // Rules the edit split apart with blank lines are parsed again whole
+        let first = self
+            .rules
+            .partition_point(|(block, _)| block.end < region.start);
+        let last = self
+            .rules
+            .partition_point(|(block, _)| block.start <= old_end);
+        if let Some((block, _)) = self.rules[first..last].first() {
+            region.start = region.start.min(block.start);
+        }
+        if let Some((block, _)) = self.rules[first..last].last() {
+            old_end = old_end.max(block.end);
+            region.end = moved(old_end);
+        }
+
+        let reparsed = parse_blocks(&source, region.clone())?;
+        let count = reparsed.len();
+        let lines = source[..region.end].matches('\n').count() as isize
+            - self.source[..old_end].matches('\n').count() as isize;
+        let following = self
+            .rules
+            .split_off(last)
+            .into_iter()
+            .map(|(block, mut rule)| {
+                if let Some(span) = &mut rule.span {
+                    span.line = (span.line as isize + lines) as usize;
+                }
+                (moved(block.start)..moved(block.end), rule)
+            });
+        self.rules.truncate(first);
+        self.rules.extend(reparsed);
+        self.rules.extend(following);
+        self.source = source;
+        Ok(count)
+    }
+}
+
+// The lines from `start` to `end`, extended to the blank lines around them
+fn surrounding_rules(source: &str, start: usize, end: usize) -> Range<usize> {
+    let line_start = |i: usize| source[..i].rfind('\n').map_or(0, |j| j + 1);
+    let line_end = |i: usize| source[i..].find('\n').map_or(source.len(), |j| i + j);
+    let blank = |start: usize| source[start..line_end(start)].trim().is_empty();
+
+    let mut start = line_start(start);
+    while start > 0 && !blank(start) && !blank(line_start(start - 1)) {
+        start = line_start(start - 1);
+    }
+    let mut end = line_end(end);
+    while end < source.len() && !blank(line_start(end)) && !blank(end + 1) {
+        end = line_end(end + 1);
+    }
+    start..end
+}
+
+// Parses each run of non-blank lines in `region` of `source` as a rule
+fn parse_blocks(
+    source: &str,
+    region: Range<usize>,
+) -> Result<Vec<(Range<usize>, Rule)>, ParseError> {
+    let mut blocks: Vec<Range<usize>> = vec![];
+    let mut position = region.start;
+    for line in source[region.clone()].split_inclusive('\n') {
+        let end = position + line.trim_end_matches(['\n', '\r']).len();
+        if !line.trim().is_empty() {
+            match blocks.last_mut() {
+                Some(block)
+                    if source[block.end..position].trim().is_empty()
+                        && source[block.end..position].matches('\n').count() == 1 =>
+                {
+                    block.end = end
+                }
+                _ => blocks.push(position..end),
+            }
+        }
+        position += line.len();
+    }
+
+    blocks
+        .into_iter()
+        .map(|block| {
+            let input = &source[block.start..];
+            let (rest, rule) = located_rule(source)(input).map_err(|error| match error {
+                nom::Err::Failure(_) => parse_error(source, error, "expected a rule"),
+                _ => ParseError::at(source, input, "expected a rule"),
+            })?;
+            let consumed = source.len() - rest.len();
+            if consumed < block.end && !source[consumed..block.end].trim().is_empty() {
+                return Err(ParseError::at(source, rest, "expected a rule"));
+            }
+            Ok((block, rule))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atom, op, rest, var};
+
+    #[test]
+    fn parse_identifier() {
+        assert_eq!(symbol("x1"), Ok(("", String::from("x1"))));
+        assert_eq!(symbol("x'"), Ok(("", String::from("x'"))));
+        assert!(symbol("1x").is_err());
+        assert_eq!(symbol("'is a'"), Ok(("", String::from("is a"))));
+        assert_eq!(
+            symbol("\"foo-bar\"(x)"),
+            Ok(("(x)", String::from("foo-bar")))
+        );
+        assert_eq!(symbol(r"'it\'s'"), Ok(("", String::from("it's"))));
+        assert!(symbol("'open").is_err());
+    }
+
+    #[test]
+    fn quoted_names_round_trip() {
+        let judgement: Judgement = "'has type'(e, t)".parse().unwrap();
+        assert_eq!(judgement, op!("has type", var("e"), var("t")));
+        assert_eq!(judgement.to_string(), "'has type'(e, t)");
+
+        for input in [
+            "'foo-bar'('my var', xs...)",
+            r"'don\'t care'(zero())",
+            "'peano::nat'(n)",
+        ] {
+            assert_eq!(input.parse::<Judgement>().unwrap().to_string(), input);
+        }
+        assert_eq!(
+            "\"foo-bar\"(x)".parse::<Judgement>().unwrap().to_string(),
+            "'foo-bar'(x)"
+        );
+    }
+
+    #[test]
+    fn parse_judgement() {
+        assert_eq!(
+            "sum(n, succ(zero()), p)".parse::<Judgement>(),
+            Ok(op!("sum", var("n"), op!("succ", atom("zero")), var("p")))
+        );
+        assert_eq!("n".parse::<Judgement>(), Ok(var("n")));
+        assert_eq!(
+            "ctx(a(), xs...)".parse::<Judgement>(),
+            Ok(op!("ctx", atom("a"), rest("xs")))
+        );
+        assert!("sum(n,".parse::<Judgement>().is_err());
+    }
+
+    #[test]
+    fn signatures() {
+        let signature = Signature::new().with_arity("succ", 1).with_arity("nat", 1);
+
+        assert_eq!(
+            signature.parse_judgement("nat(succ(a, b))"),
+            Err(ParseError {
+                line: 1,
+                column: 5,
+                message: String::from("succ/1 applied to 2 subjects"),
+            })
+        );
+        assert_eq!(
+            signature.parse_judgement("nat(succ(zero()))"),
+            Ok(op!("nat", op!("succ", atom("zero"))))
+        );
+        // Undeclared predicates take any number of subjects
+        assert!(signature.parse_judgement("sum(a, b, c)").is_ok());
+        // Numerals and quoted names are located by the text they were read from
+        let error = signature
+            .parse_judgement("f(3, 'g'(x), succ(a, [b, c]))")
+            .unwrap_err();
+        assert_eq!((error.line, error.column), (1, 14));
+        let error = signature
+            .parse_judgement("f(succ(a), 'succ' (a, b))")
+            .unwrap_err();
+        assert_eq!((error.line, error.column), (1, 12));
+        assert!(signature.parse_judgement("f(succ(2), [succ(x)])").is_ok());
+
+        let error = signature
+            .parse_system(
+                "------ zero\nnat(zero())\n\nnat(n)\n------------ succ\nnat(succ(n, n))\n",
+            )
+            .err()
+            .unwrap();
+        assert_eq!((error.line, error.column), (6, 5));
+        assert!(signature
+            .parse_system("------ zero\nnat(zero())\n\nnat(n)\n------------ succ\nnat(succ(n))\n")
+            .is_ok());
+    }
+
+    #[test]
+    fn naming_conventions() {
+        let input = "foo(X, bar, Baz(qux), Xs...)";
+        assert_eq!(
+            NamingConvention::Prolog.parse_judgement(input),
+            Ok(op!(
+                "foo",
+                var("X"),
+                atom("bar"),
+                op!("Baz", atom("qux")),
+                rest("Xs")
+            ))
+        );
+        assert_eq!(
+            NamingConvention::default().parse_judgement(input),
+            input.parse::<Judgement>()
+        );
+
+        let system = NamingConvention::Prolog
+            .parse_system("nat(N)\n------------ succ\nnat(succ(N))\n\n------ zero\nnat(zero)\n")
+            .unwrap();
+        assert!(system
+            .verify(
+                &NamingConvention::Prolog
+                    .parse_judgement("nat(succ(zero))")
+                    .unwrap()
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn deeply_nested_input() {
+        let nested = |depth: usize| "f(".repeat(depth) + "x" + &")".repeat(depth);
+        assert!(nested(MAX_NESTING).parse::<Judgement>().is_ok());
+
+        let error = nested(100_000).parse::<Judgement>().unwrap_err();
+        assert_eq!(error.column, 2 * MAX_NESTING + 2);
+        assert_eq!(error.message, "nested too deeply");
+        let rules = format!("------ deep\n{}\n", nested(100_000));
+        assert!(FormalSystem::try_from(rules.as_str()).is_err());
+        assert!(IncrementalRules::parse(&rules).is_err());
+
+        // Long lists and large numerals are deep terms too
+        let items = vec!["a"; 100_000].join(", ");
+        assert!(format!("[{}]", items).parse::<Judgement>().is_err());
+        assert!("f(100000)".parse::<Judgement>().is_err());
+        assert!("f(256, 'a(b', ']')".parse::<Judgement>().is_ok());
+        assert!(format!("'{}'", "(".repeat(1000))
+            .parse::<Judgement>()
+            .is_ok());
+    }
+
+    #[test]
+    fn deeply_nested_bare_parsers() {
+        let deep = "f(".repeat(200_000);
+        assert!(predicate(&deep).is_err());
+        assert!(judgement(&deep).is_err());
+        assert!(rule(&format!("{}\n------ r\nx\n", deep)).is_err());
+        let constants = HashSet::new();
+        let sexps = "(f ".repeat(200_000);
+        assert!(sexp(&constants)(&sexps).is_err());
+        let operators = crate::operators::OperatorRegistry::new();
+        assert!(operators.parse(&"(".repeat(200_000)).is_err());
+    }
+
+    #[test]
+    fn parse_whitespace() {
+        let expected = op!("succ", var("zero"));
+        assert_eq!(predicate("succ(zero)"), Ok(("", expected.clone())));
+        assert_eq!(predicate("  succ ( zero )"), Ok(("", expected.clone())));
+        assert_eq!("  succ ( zero )  ".parse::<Judgement>(), Ok(expected));
+        assert_eq!(
+            " sum ( n ,succ( zero( ) ),p ) ".parse::<Judgement>(),
+            Ok(op!("sum", var("n"), op!("succ", atom("zero")), var("p")))
+        );
+    }
+
+    #[test]
+    fn sexps() {
+        let judgement = op!(
+            "sum",
+            var("n"),
+            op!("succ", atom("zero")),
+            op!("ctx", atom("a"), rest("xs"))
+        );
+        let sexp = judgement.to_sexp();
+        assert_eq!(sexp, "(sum n (succ (zero)) (ctx (a) xs...))");
+        assert_eq!(Judgement::from_sexp(&sexp), Ok(judgement.clone()));
+        assert_eq!(
+            Judgement::from_sexp("\n ( sum\tn\n  (succ (zero) )\n(ctx (a) xs...)) "),
+            Ok(judgement)
+        );
+
+        let constants = HashSet::from([String::from("zero")]);
+        assert_eq!(
+            Judgement::from_sexp_with_constants("(+ (succ zero) m)", &constants),
+            Ok(op!("+", op!("succ", atom("zero")), var("m")))
+        );
+
+        assert!(Judgement::from_sexp("(succ (zero)").is_err());
+        assert!(Judgement::from_sexp("()").is_err());
+        assert_eq!(Judgement::from_sexp("(a) (b)").unwrap_err().column, 5);
+    }
+
+    #[test]
+    fn parse_rule() {
+        let rule: Rule = "sum(n, m, p)\n------------- s2\nsum(n, succ(m), succ(p))"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.name, "s2");
+        assert_eq!(
+            rule.premises,
+            vec![op!("sum", var("n"), var("m"), var("p"))]
+        );
+
+        let rule: Rule = "tree(a1)    tree(a2)\n-------------------- tree\ntree(node(a1, a2))"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.premises.len(), 2);
+
+        let rule: Rule = "--------- zero\nnat(zero())".parse().unwrap();
+        assert!(rule.premises.is_empty());
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let system = FormalSystem::try_from(
+            "
+@category \"arithmetic\"
+@cite \"Peano, 1889\"
+------ zero
+nat(zero())
+
+nat(n)
+------------ succ
+nat(succ(n))
+",
+        )
+        .unwrap();
+        let zero = &system.axioms[0];
+        assert_eq!(zero.attribute("category"), Some("arithmetic"));
+        assert_eq!(zero.attribute("cite"), Some("Peano, 1889"));
+        assert_eq!(zero.attribute("difficulty"), None);
+        assert!(system.axioms[1].attributes().is_empty());
+        assert!(system.verify(&op!("nat", atom("zero"))).is_some());
+
+        let printed = zero.to_bar_string();
+        assert_eq!(
+            printed,
+            "@category \"arithmetic\"\n@cite \"Peano, 1889\"\n----------- zero\nnat(zero())"
+        );
+        let reparsed: Rule = printed.parse().unwrap();
+        assert_eq!(reparsed.attributes(), zero.attributes());
+    }
+
+    #[test]
+    fn parse_premise_labels() {
+        let rule: Rule = "(i): sum(n, m, p)    (ii): nat(m)\n---------------------- s2\nsum(n, succ(m), succ(p))"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.premises,
+            vec![
+                op!("sum", var("n"), var("m"), var("p")),
+                op!("nat", var("m"))
+            ]
+        );
+        assert_eq!(rule.premise_label(0), Some("i"));
+        assert_eq!(rule.premise_label(1), Some("ii"));
+        assert_eq!(
+            rule.to_string(),
+            "((i): sum(n, m, p), (ii): nat(m))->sum(n, succ(m), succ(p))"
+        );
+
+        let rule: Rule = "nat(n)    ( h ) : tree(t)\n------ pair\npair(n, t)"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.premise_label(0), None);
+        assert_eq!(rule.premise_label(1), Some("h"));
+    }
+
+    #[test]
+    fn parse_items() {
+        let (_, item) = parse_item("nat(n)\n------------ succ\nnat(succ(n))").unwrap();
+        assert!(matches!(item, Ast::Rule(rule) if rule.name == "succ"));
+
+        let (_, item) = parse_item("nat(succ(zero()))").unwrap();
+        assert!(matches!(
+            item,
+            Ast::Judgement(judgement) if judgement == op!("nat", op!("succ", atom("zero")))
+        ));
+    }
+
+    #[test]
+    fn parse_formal_system() {
+        let nat = FormalSystem::try_from(
+            "
+------ zero
+nat(zero())
+
+nat(n)
+------------ succ
+nat(succ(n))
+",
+        )
+        .unwrap();
+        assert_eq!(nat.axioms.len(), 2);
+        assert_eq!(nat.max_derivation_height, DEFAULT_MAX_DERIVATION_HEIGHT);
+
+        let error = FormalSystem::try_from("------ zero\nnat(zero(\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn incremental_parsing() {
+        let source = "------ zero\nnat(zero())\n\nnat(n)\n------------ succ\nnat(succ(n))\n\n\
+                      ------ empty\ntree(empty())\n";
+        let summary = |rules: Vec<&Rule>| -> Vec<(String, String, Option<Span>)> {
+            rules
+                .into_iter()
+                .map(|rule| (rule.name.clone(), rule.to_string(), rule.span()))
+                .collect()
+        };
+        let reparsed = |document: &IncrementalRules| {
+            summary(
+                parse_complete(rules, document.source(), "expected a rule")
+                    .unwrap()
+                    .iter()
+                    .collect(),
+            )
+        };
+        let mut document = IncrementalRules::parse(source).unwrap();
+        assert_eq!(document.rules().count(), 3);
+
+        // Renaming the second rule
+        let start = source.find("succ\n").unwrap();
+        assert_eq!(document.edit(start..start + 4, "s"), Ok(1));
+        assert_eq!(document.rules().nth(1).unwrap().name, "s");
+        assert_eq!(summary(document.rules().collect()), reparsed(&document));
+
+        // Adding a premise, then a rule which moves the last one down
+        let start = document.source().find("nat(n)").unwrap();
+        assert_eq!(document.edit(start..start, "nat(m)    "), Ok(1));
+        let start = document.source().find("\n\n------ empty").unwrap() + 1;
+        assert_eq!(
+            document.edit(start..start, "\n------ one\nnat(one())\n"),
+            Ok(1)
+        );
+        assert_eq!(document.rules().count(), 4);
+        assert_eq!(document.rules().nth(3).unwrap().span().unwrap().line, 11);
+        assert_eq!(summary(document.rules().collect()), reparsed(&document));
+
+        // Failed edits leave the rules as they were
+        let start = document.source().find("------------ s").unwrap();
+        assert_eq!(
+            document.edit(start..start, "\n"),
+            Err(ParseError {
+                line: 4,
+                column: 1,
+                message: String::from("expected a rule"),
+            })
+        );
+        assert_eq!(summary(document.rules().collect()), reparsed(&document));
+
+        // Without the blank line between them, two rules are parsed together
+        let end = document.source().find("------ empty").unwrap();
+        assert!(document.edit(end - 1..end, "").is_err());
+        assert_eq!(document.to_system().axioms.len(), 4);
+    }
+}
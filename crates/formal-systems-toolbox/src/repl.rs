@@ -0,0 +1,178 @@
+use std::io::{BufRead, Write};
+
+use super::{FormalSystem, Judgement};
+
+const HELP: &str = "\
+:load <file>        load the rules in <file>, replacing the current ones
+:rules              list the loaded rules
+:height <n>         set the maximum derivation height
+:query <judgement>  search for a derivation (same as typing the judgement)
+:help               show this message
+:quit               leave";
+
+pub enum Response {
+    Output(String),
+    Quit,
+}
+
+pub struct Repl {
+    system: FormalSystem,
+}
+
+impl Repl {
+    pub fn new(system: FormalSystem) -> Self {
+        Self { system }
+    }
+
+    pub fn system(&self) -> &FormalSystem {
+        &self.system
+    }
+
+    pub fn eval(&mut self, line: &str) -> Response {
+        let line = line.trim();
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+
+        let output = match command {
+            "" => String::new(),
+            ":quit" | ":q" => return Response::Quit,
+            ":help" => String::from(HELP),
+            ":load" => self.load(argument),
+            ":rules" => self.rules(),
+            ":height" => match argument.parse::<u16>() {
+                Ok(height) => {
+                    self.system.max_derivation_height = height;
+                    format!("Maximum derivation height set to {}", height)
+                }
+                Err(_) => format!("Invalid height: {}", argument),
+            },
+            ":query" => self.query(argument),
+            _ if command.starts_with(':') => {
+                format!("Unknown command {}, type :help for a list", command)
+            }
+            _ => self.query(line),
+        };
+        Response::Output(output)
+    }
+
+    fn load(&mut self, path: &str) -> String {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => return format!("Could not read {}: {}", path, e),
+        };
+
+        match FormalSystem::try_from(source.as_str()) {
+            Ok(system) => {
+                self.system = system.with_max_derivation_height(self.system.max_derivation_height);
+                format!("Loaded {} rules from {}", self.system.axioms.len(), path)
+            }
+            Err(e) => format!("{}:{}", path, e),
+        }
+    }
+
+    fn rules(&self) -> String {
+        self.system
+            .axioms
+            .iter()
+            .map(|axiom| format!("{}: {}", axiom.name, axiom))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn query(&self, input: &str) -> String {
+        let judgement: Judgement = match input.parse() {
+            Ok(judgement) => judgement,
+            Err(e) => return format!("Parse error at {}", e),
+        };
+
+        match self.system.verify(&judgement) {
+            Some(proof) => proof.to_string_tree(),
+            None => format!(
+                "No derivation of {} within height {}",
+                judgement, self.system.max_derivation_height
+            ),
+        }
+    }
+}
+
+// Reads commands and queries line by line until `:quit` or the end of input
+pub fn repl(
+    system: FormalSystem,
+    input: impl BufRead,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut repl = Repl::new(system);
+
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match repl.eval(&line?) {
+            Response::Output(text) if text.is_empty() => {}
+            Response::Output(text) => writeln!(output, "{}", text)?,
+            Response::Quit => return Ok(()),
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::nat_rules;
+
+    fn nat() -> FormalSystem {
+        FormalSystem::new(nat_rules(), 4)
+    }
+
+    fn output(response: Response) -> String {
+        match response {
+            Response::Output(text) => text,
+            Response::Quit => panic!("unexpected quit"),
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::new(nat());
+
+        assert_eq!(
+            output(repl.eval(":rules")),
+            "zero: ()->nat(zero())\nsucc: (nat(n))->nat(succ(n))"
+        );
+        assert!(output(repl.eval("nat(succ(zero()))")).contains("succ"));
+        assert_eq!(
+            output(repl.eval(":query nat(succ(succ(zero())))")),
+            repl.system()
+                .verify(&"nat(succ(succ(zero())))".parse().unwrap())
+                .unwrap()
+                .to_string_tree()
+        );
+
+        assert_eq!(
+            output(repl.eval(":height 1")),
+            "Maximum derivation height set to 1"
+        );
+        assert_eq!(
+            output(repl.eval("nat(succ(succ(succ(zero()))))")),
+            "No derivation of nat(succ(succ(succ(zero())))) within height 1"
+        );
+        assert!(output(repl.eval("nat(")).starts_with("Parse error"));
+        assert!(output(repl.eval(":load /nonexistent")).starts_with("Could not read"));
+        assert!(matches!(repl.eval(":quit"), Response::Quit));
+    }
+
+    #[test]
+    fn session() {
+        let input = "nat(zero())\n:height x\n:quit\nnat(zero())\n";
+        let mut written: Vec<u8> = vec![];
+        repl(nat(), input.as_bytes(), &mut written).unwrap();
+
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(written.matches("zero---").count(), 1);
+        assert!(written.contains("Invalid height: x"));
+    }
+}
@@ -0,0 +1,704 @@
+use std::fmt::Display;
+
+use super::engine::{Search, Trace};
+use super::{Derivation, FormalSystem, Judgement, Provenance, Symbol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Outcome {
+    Proved,
+    Unprovable,
+}
+
+// A goal the search gave up on, written with its variables renamed to x1, x2..
+// in order of appearance
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FailedGoal {
+    pub goal: String,
+    pub height: u16,
+    // Rules whose conclusion unified with the goal, none of which led to a proof
+    pub rules: Vec<String>,
+}
+
+// The value a variable ended up with in a derivation, and the rule whose
+// instantiation introduced the variable. Variables of the goal have none
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Binding {
+    pub variable: String,
+    pub value: Judgement,
+    pub provenance: Option<Provenance>,
+}
+
+// A goal that failed for unifying with a negative fact of the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Contradiction<S = String> {
+    pub goal: Judgement<S>,
+    pub fact: Judgement<S>,
+}
+
+impl<S: Symbol> Display for Contradiction<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} unifies with the negative fact {}",
+            self.goal, self.fact
+        )
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VerifyReport {
+    pub goal: Judgement,
+    pub outcome: Outcome,
+    // Height of the deepest goal the search opened, over every branch it
+    // explored and not only in the derivation found
+    pub height_reached: u16,
+    // Whether some goal was given up for exceeding a height bound, so that a
+    // larger bound may change the outcome
+    pub height_limited: bool,
+    // On failure, the deepest goals that could not be proved
+    pub frontier: Vec<FailedGoal>,
+    // On failure, the goals abandoned by the loop check, normalized as in
+    // `frontier`. One of them may have been provable through another branch
+    pub looped: Vec<String>,
+    // On failure, the goals that unified with a negative fact, with the fact
+    pub contradicted: Vec<Contradiction>,
+    pub derivation: Option<Derivation>,
+    // On success, every variable bound along the way, sorted by name
+    pub bindings: Vec<Binding>,
+}
+
+// A subterm in which the goals of `FormalSystem::contrast` differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub path: Vec<usize>,
+    pub failing: Judgement,
+    pub succeeding: Judgement,
+    // Whether the failing goal holds once this subterm alone is replaced by
+    // the one of the succeeding goal
+    pub responsible: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContrastReport {
+    pub failing_proved: bool,
+    pub succeeding_proved: bool,
+    pub differences: Vec<Difference>,
+    // For each subject of the failing goal, the nearest provable goal that
+    // only changes that subject, if there is one
+    pub repairs: Vec<(usize, Judgement)>,
+}
+
+// Why `verify_explained` found no derivation, with the goal where the search
+// gave up. A goal given up at the height bound is reported first, as a larger
+// bound may be all it takes, then one abandoned as a loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    HeightExceeded(Judgement),
+    // The deepest goal for which no rule led to a derivation
+    NoMatchingRule(Judgement),
+    Cycle(Judgement),
+}
+
+impl Display for ProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProofError::HeightExceeded(goal) => {
+                write!(f, "Height limit exceeded while proving {}", goal)
+            }
+            ProofError::NoMatchingRule(goal) => write!(f, "No rule proves {}", goal),
+            ProofError::Cycle(goal) => write!(f, "Cycle detected at {}", goal),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+pub enum Trivalent {
+    Proved(Derivation),
+    // No derivation exists at all, whatever the height bound
+    Refuted,
+    // No derivation was found, but one may exist
+    Unknown,
+}
+
+// How far the search got on a goal it could not prove: the derivations of the
+// enclosing goals around the deepest failure. Goals left unproved appear as
+// steps labelled `open` without premises, and are listed in `open_goals`
+// starting from the one that failed
+#[derive(Clone)]
+pub struct PartialDerivation<S = String> {
+    pub derivation: Derivation<S>,
+    pub open_goals: Vec<Judgement<S>>,
+}
+
+impl FormalSystem {
+    pub fn verify_report(&self, goal: &Judgement) -> VerifyReport {
+        let mut search = Search::new(None);
+        search.trace = Some(Trace::default());
+        // Ground premises would otherwise be proved with bindings of their own
+        search.ground_fast_path = false;
+        let derivation = self.search(&mut search, goal);
+        let trace = search.trace.unwrap_or_default();
+
+        let mut bindings: Vec<Binding> = trace
+            .substitutions
+            .iter()
+            .map(|(variable, value)| Binding {
+                variable: variable.clone(),
+                value: value.apply_substitution(&trace.substitutions),
+                provenance: trace
+                    .provenance
+                    .get(variable)
+                    .map(|provenance| provenance.as_ref().clone()),
+            })
+            .collect();
+        bindings.sort_by(|left, right| left.variable.cmp(&right.variable));
+
+        VerifyReport {
+            goal: goal.clone(),
+            outcome: if derivation.is_some() {
+                Outcome::Proved
+            } else {
+                Outcome::Unprovable
+            },
+            height_reached: trace.height_reached,
+            height_limited: search.cut_off,
+            frontier: if derivation.is_some() {
+                vec![]
+            } else {
+                trace.frontier
+            },
+            looped: if derivation.is_some() {
+                vec![]
+            } else {
+                trace.looped
+            },
+            contradicted: if derivation.is_some() {
+                vec![]
+            } else {
+                trace.contradicted
+            },
+            derivation,
+            bindings,
+        }
+    }
+
+    // Like `verify`, telling on failure where and why the search gave up
+    pub fn verify_explained(&self, goal: &Judgement) -> Result<Derivation, ProofError> {
+        let mut search = Search::new(None);
+        search.trace = Some(Trace::default());
+        if let Some(derivation) = self.search(&mut search, goal) {
+            return Ok(derivation);
+        }
+
+        let trace = search.trace.unwrap_or_default();
+        if let Some(cut) = trace.first_cut {
+            return Err(ProofError::HeightExceeded(cut));
+        }
+        if let Some(looped) = trace.first_loop {
+            return Err(ProofError::Cycle(looped));
+        }
+        Err(ProofError::NoMatchingRule(
+            trace
+                .partial
+                .and_then(|partial| partial.open_goals.into_iter().next())
+                .unwrap_or_else(|| goal.clone()),
+        ))
+    }
+
+    // Explains why `failing` does not hold where `succeeding` does by trying
+    // each of their differences on its own, and each subject of `failing`
+    pub fn contrast(&self, failing: &Judgement, succeeding: &Judgement) -> ContrastReport {
+        let subject = |judgement: &Judgement, path: &[usize]| {
+            path.iter()
+                .try_fold(judgement, |judgement, &i| match judgement {
+                    Judgement::Operator { subjects, .. } => subjects.get(i).map(AsRef::as_ref),
+                    _ => None,
+                })
+                .cloned()
+                .unwrap()
+        };
+        let differences = failing
+            .differences(succeeding)
+            .into_iter()
+            .map(|path| {
+                let replacement = subject(succeeding, &path);
+                Difference {
+                    responsible: self
+                        .verify(&failing.with_subject_at(&path, replacement.clone()))
+                        .is_some(),
+                    failing: subject(failing, &path),
+                    succeeding: replacement,
+                    path,
+                }
+            })
+            .collect();
+        let arity = match failing {
+            Judgement::Operator { subjects, .. } => subjects.len(),
+            _ => 0,
+        };
+
+        ContrastReport {
+            failing_proved: self.verify(failing).is_some(),
+            succeeding_proved: self.verify(succeeding).is_some(),
+            differences,
+            repairs: (0..arity)
+                .filter_map(|i| Some((i, self.nearest_provable(failing, &[i])?)))
+                .collect(),
+        }
+    }
+
+    // A ground goal is refuted when the search fails without giving up on any
+    // goal because of the height bound. This is only sound if every rule
+    // variable occurs in its conclusion: then all premises are ground too, and
+    // committing to the first derivation of a premise loses no alternatives
+    pub fn prove_or_refute(&self, goal: &Judgement) -> Trivalent {
+        let mut search = Search::new(None);
+        match self.search(&mut search, goal) {
+            Some(derivation) => Trivalent::Proved(derivation),
+            None if !search.cut_off && goal.is_ground() && self.preserves_groundness() => {
+                Trivalent::Refuted
+            }
+            None => Trivalent::Unknown,
+        }
+    }
+
+    // The partial derivation is returned by value like the derivation it
+    // stands for; it is built once per call, so its size costs nothing
+    #[allow(clippy::result_large_err)]
+    pub fn verify_partial(&self, goal: &Judgement) -> Result<Derivation, PartialDerivation> {
+        let mut search = Search::new(None);
+        search.trace = Some(Trace::default());
+        if let Some(derivation) = self.search(&mut search, goal) {
+            return Ok(derivation);
+        }
+
+        Err(search
+            .trace
+            .and_then(|trace| trace.partial)
+            .unwrap_or_else(|| PartialDerivation {
+                derivation: Derivation {
+                    premises: vec![],
+                    conclusion: goal.clone(),
+                    rule_label: String::from("open"),
+                    is_axiom: false,
+                    discharge: None,
+                    bindings: vec![],
+                },
+                open_goals: vec![goal.clone()],
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{nat_rules, rules, succ, sum_rules, zero};
+    use crate::{atom, op, var, LoopCheck, Rule};
+
+    fn system() -> FormalSystem {
+        FormalSystem::new(rules(&[nat_rules, sum_rules]), 8)
+    }
+
+    #[test]
+    fn reports() {
+        let system = system();
+
+        let goal = op!("nat", succ(succ(zero())));
+        let report = system.verify_report(&goal);
+        assert_eq!(report.outcome, Outcome::Proved);
+        assert_eq!(report.height_reached, 2);
+        assert!(report.frontier.is_empty());
+        assert_eq!(
+            report.derivation.map(|proof| proof.to_string_tree()),
+            system.verify(&goal).map(|proof| proof.to_string_tree())
+        );
+
+        let report = system.verify_report(&op!("sum", succ(zero()), succ(zero()), succ(zero())));
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(report.height_reached, 1);
+        assert_eq!(
+            report.frontier,
+            vec![FailedGoal {
+                goal: String::from("sum(succ(zero()), zero(), zero())"),
+                height: 1,
+                rules: vec![],
+            }]
+        );
+        assert!(report.derivation.is_none());
+
+        let report = system.verify_report(&op!("nat", var("x"), zero()));
+        assert_eq!(report.height_reached, 0);
+        assert_eq!(report.frontier[0].goal, "nat(x1, zero())");
+    }
+
+    #[test]
+    fn height_reached() {
+        let system = system();
+
+        // Proving `sum` first tries `s1` at every height, `s2` going deeper
+        let goal = op!("sum", zero(), succ(succ(succ(zero()))), var("p"));
+        let report = system.verify_report(&goal);
+        let proof = report.derivation.unwrap();
+        assert_eq!(proof.height(), 4);
+        assert_eq!(report.height_reached, 3);
+        assert!(!report.height_limited);
+
+        // A failure at the bound says that raising it may help
+        let goal = op!("nat", op!("pair", zero(), zero()));
+        let report = FormalSystem::new(
+            vec![
+                Rule::new(
+                    "wrap",
+                    vec![op!("nat", succ(var("n")))],
+                    op!("nat", var("n")),
+                ),
+                Rule::taut("zero", op!("nat", zero())),
+            ],
+            3,
+        )
+        .verify_report(&goal);
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(report.height_reached, 3);
+        assert!(report.height_limited);
+    }
+
+    #[test]
+    fn contrasts() {
+        let system = system();
+        let report = system.contrast(
+            &op!("sum", zero(), succ(zero()), zero()),
+            &op!("sum", zero(), zero(), zero()),
+        );
+
+        assert!(!report.failing_proved);
+        assert!(report.succeeding_proved);
+        assert_eq!(
+            report.differences,
+            vec![Difference {
+                path: vec![1],
+                failing: succ(zero()),
+                succeeding: zero(),
+                responsible: true,
+            }]
+        );
+        // Either the second subject or the third is wrong
+        assert_eq!(
+            report.repairs,
+            vec![
+                (1, op!("sum", zero(), zero(), zero())),
+                (2, op!("sum", zero(), succ(zero()), succ(zero()))),
+            ]
+        );
+
+        // Neither difference is enough on its own
+        let report = system.contrast(
+            &op!("sum", zero(), succ(zero()), zero()),
+            &op!("sum", zero(), succ(succ(zero())), succ(succ(zero()))),
+        );
+        assert_eq!(
+            report
+                .differences
+                .iter()
+                .map(|difference| (difference.path.clone(), difference.responsible))
+                .collect::<Vec<_>>(),
+            vec![(vec![1, 0], false), (vec![2], false)]
+        );
+    }
+
+    #[test]
+    fn looped_goals() {
+        let cyclic = FormalSystem::new(
+            vec![
+                Rule::new("loop", vec![op!("p", var("x"))], op!("p", var("x"))),
+                Rule::new("q1", vec![op!("p", var("y"))], atom("q")),
+                Rule::new("q2", vec![op!("p", var("z"))], atom("q")),
+            ],
+            8,
+        );
+        // The second attempt at `p` is cut short by the failure of the first
+        let report = cyclic.verify_report(&atom("q"));
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(report.looped, vec![String::from("p(x1)")]);
+
+        let report = cyclic
+            .with_loop_check(LoopCheck::PerBranch)
+            .verify_report(&atom("q"));
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(report.looped, vec![String::from("p(x1)")]);
+
+        let report = system().verify_report(&op!("nat", succ(zero())));
+        assert!(report.looped.is_empty());
+    }
+
+    #[test]
+    fn negative_facts() {
+        let two = succ(succ(zero()));
+        let fact = op!("sum", var("n"), var("n"), two.clone());
+        let goal = op!("sum", succ(zero()), succ(zero()), two.clone());
+        let rules = system();
+        assert!(rules.verify(&goal).is_some());
+
+        // Provable by the rules, but declared false
+        let system = rules
+            .clone()
+            .with_negative_facts(std::slice::from_ref(&fact));
+        assert!(system.verify(&goal).is_none());
+        let report = system.verify_report(&goal);
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(
+            report.contradicted,
+            vec![Contradiction {
+                goal: goal.clone(),
+                fact: fact.clone(),
+            }]
+        );
+        assert_eq!(
+            report.contradicted[0].to_string(),
+            "sum(succ(zero()), succ(zero()), succ(succ(zero()))) unifies with the negative fact \
+             sum(n, n, succ(succ(zero())))"
+        );
+        assert!(matches!(system.prove_or_refute(&goal), Trivalent::Refuted));
+        // Goals the fact doesn't cover are unaffected
+        assert!(system
+            .verify(&op!("sum", zero(), succ(zero()), succ(zero())))
+            .is_some());
+
+        // Premises are pruned the same way
+        let three = op!("sum", succ(zero()), succ(succ(zero())), succ(two.clone()));
+        assert!(rules.verify(&three).is_some());
+        let report = system.verify_report(&three);
+        assert_eq!(report.outcome, Outcome::Unprovable);
+        assert_eq!(report.contradicted[0].goal, goal);
+    }
+
+    #[test]
+    fn explained_failures() {
+        let system = system();
+        assert!(system.verify_explained(&op!("nat", succ(zero()))).is_ok());
+
+        let error = system
+            .verify_explained(&op!("sum", succ(zero()), succ(zero()), zero()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            ProofError::NoMatchingRule(op!("sum", succ(zero()), succ(zero()), zero()))
+        );
+        assert_eq!(
+            error.to_string(),
+            "No rule proves sum(succ(zero()), succ(zero()), zero())"
+        );
+        // The deepest goal is reported, not the one asked
+        assert_eq!(
+            system
+                .verify_explained(&op!("nat", succ(succ(atom("one")))))
+                .err(),
+            Some(ProofError::NoMatchingRule(op!("nat", atom("one"))))
+        );
+
+        let deep = (0..10).fold(zero(), |n, _| succ(n));
+        assert!(matches!(
+            system.verify_explained(&op!("nat", deep)),
+            Err(ProofError::HeightExceeded(goal)) if goal.to_string().starts_with("nat(")
+        ));
+
+        let cyclic = FormalSystem::new(
+            vec![Rule::new(
+                "loop",
+                vec![op!("p", var("x"))],
+                op!("p", var("x")),
+            )],
+            8,
+        )
+        .with_loop_check(LoopCheck::PerBranch);
+        assert!(matches!(
+            cyclic.verify_explained(&op!("p", zero())),
+            Err(ProofError::Cycle(goal)) if goal == op!("p", zero())
+        ));
+    }
+
+    #[test]
+    fn binding_provenance() {
+        let system = system();
+        let report = system.verify_report(&op!("sum", succ(zero()), var("x"), succ(succ(zero()))));
+
+        let bindings: Vec<String> = report
+            .bindings
+            .iter()
+            .map(|binding| {
+                format!(
+                    "{} = {} from {:?}",
+                    binding.variable,
+                    binding.value,
+                    binding
+                        .provenance
+                        .as_ref()
+                        .map(|provenance| (provenance.rule.as_str(), provenance.position))
+                )
+            })
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                "m = zero() from Some((\"s2\", 3))",
+                "n = succ(zero()) from Some((\"s2\", 3))",
+                "n1 = succ(zero()) from Some((\"s1\", 2))",
+                "p = succ(zero()) from Some((\"s2\", 3))",
+                "x = succ(zero()) from None",
+            ]
+        );
+        assert!(system
+            .verify_report(&op!("nat", atom("one")))
+            .bindings
+            .is_empty());
+    }
+
+    #[test]
+    fn refutations() {
+        let system = system();
+
+        assert!(matches!(
+            system.prove_or_refute(&op!("sum", zero(), succ(zero()), zero())),
+            Trivalent::Refuted
+        ));
+        assert!(matches!(
+            system.prove_or_refute(&op!("sum", succ(zero()), succ(succ(zero())), succ(zero()))),
+            Trivalent::Refuted
+        ));
+        assert!(matches!(
+            system.prove_or_refute(&op!("nat", succ(zero()))),
+            Trivalent::Proved(_)
+        ));
+        // Open goals are never refuted
+        assert!(matches!(
+            system.prove_or_refute(&op!("sum", zero(), succ(var("x")), zero())),
+            Trivalent::Unknown
+        ));
+
+        // The bound stops the search before the derivation is complete
+        let numeral = (0..10).fold(zero(), |n, _| succ(n));
+        assert!(matches!(
+            system.prove_or_refute(&op!("nat", numeral.clone())),
+            Trivalent::Unknown
+        ));
+        assert!(matches!(
+            system
+                .clone()
+                .with_max_derivation_height(12)
+                .prove_or_refute(&op!("nat", numeral)),
+            Trivalent::Proved(_)
+        ));
+
+        // `lt` leaves `m` open in its premise, so a failure proves nothing
+        let mut axioms = system.axioms.clone();
+        axioms.push(Rule::new(
+            "lt",
+            vec![op!("sum", var("n"), succ(var("m")), var("p"))],
+            op!("lt", var("n"), var("p")),
+        ));
+        assert!(matches!(
+            FormalSystem::new(axioms, 8).prove_or_refute(&op!("lt", succ(zero()), zero())),
+            Trivalent::Unknown
+        ));
+    }
+
+    #[test]
+    fn partial_derivations() {
+        let system = system();
+        let one = || succ(zero());
+
+        assert!(system.verify_partial(&op!("nat", one())).is_ok());
+
+        let partial = system
+            .verify_partial(&op!("sum", one(), succ(succ(one())), succ(one())))
+            .err()
+            .unwrap();
+        let steps: Vec<String> = partial
+            .derivation
+            .proven_goals()
+            .map(|(goal, rule)| format!("{} {}", rule, goal))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                "open sum(succ(zero()), succ(zero()), zero())",
+                "s2 sum(succ(zero()), succ(succ(zero())), succ(zero()))",
+                "s2 sum(succ(zero()), succ(succ(succ(zero()))), succ(succ(zero())))",
+            ]
+        );
+        assert_eq!(partial.open_goals, vec![op!("sum", one(), one(), zero())]);
+
+        let partial = system
+            .verify_partial(&op!("sum", one(), one(), var("x"), zero()))
+            .err()
+            .unwrap();
+        assert_eq!(partial.derivation.rule_names(), ["open".to_string()].into());
+    }
+
+    #[test]
+    fn open_premises() {
+        let tree = FormalSystem::new(
+            vec![
+                Rule::taut("empty", op!("tree", atom("empty"))),
+                Rule::new(
+                    "node",
+                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+                    op!("tree", op!("node", var("a1"), var("a2"))),
+                ),
+            ],
+            8,
+        );
+        let node = |t1, t2| op!("node", t1, t2);
+        let partial = tree
+            .verify_partial(&op!(
+                "tree",
+                node(atom("empty"), node(atom("leaf"), atom("empty")))
+            ))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            partial.derivation.to_string_tree(),
+            concat!(
+                "\n",
+                "                              open--------------  open---------------\n",
+                "                                   tree(leaf())        tree(empty()) \n",
+                "    empty---------------  node---------------------------------------\n",
+                "          tree(empty())             tree(node(leaf(), empty()))      \n",
+                "node-----------------------------------------------------------------\n",
+                "               tree(node(empty(), node(leaf(), empty())))            \n",
+            )
+        );
+        assert_eq!(
+            partial.open_goals,
+            vec![op!("tree", atom("leaf")), op!("tree", atom("empty"))]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_report() {
+        let report = system().verify_report(&op!("nat", succ(atom("one"))));
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json["outcome"], "unprovable");
+        assert_eq!(json["goal"]["Operator"]["predicate"], "nat");
+        assert_eq!(
+            json["frontier"],
+            serde_json::json!([{"goal": "nat(one())", "height": 1, "rules": []}])
+        );
+        assert!(json["derivation"].is_null());
+
+        let report = system().verify_report(&op!("nat", zero()));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["derivation"]["rule_label"], "zero");
+        assert_eq!(json["derivation"]["is_axiom"], true);
+    }
+}
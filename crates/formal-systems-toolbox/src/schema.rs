@@ -0,0 +1,277 @@
+// The JSON Schema of judgements and derivations as serialized with serde, for
+// clients generating their types from it. It is written by hand and has to be
+// kept in step with the serde attributes of the types it describes
+const SCHEMA: &str = r##"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "Derivation",
+  "$ref": "#/$defs/Derivation",
+  "$defs": {
+    "Provenance": {
+      "type": "object",
+      "properties": {
+        "rule": { "type": "string" },
+        "position": { "type": "integer", "minimum": 0 }
+      },
+      "required": ["rule", "position"],
+      "additionalProperties": false
+    },
+    "Judgement": {
+      "oneOf": [
+        {
+          "type": "object",
+          "properties": {
+            "Operator": {
+              "type": "object",
+              "properties": {
+                "predicate": { "type": "string" },
+                "subjects": {
+                  "type": "array",
+                  "items": { "$ref": "#/$defs/Judgement" }
+                }
+              },
+              "required": ["predicate", "subjects"],
+              "additionalProperties": false
+            }
+          },
+          "required": ["Operator"],
+          "additionalProperties": false
+        },
+        {
+          "type": "object",
+          "properties": {
+            "Variable": {
+              "type": "array",
+              "prefixItems": [
+                { "type": "string" },
+                { "oneOf": [{ "type": "null" }, { "$ref": "#/$defs/Provenance" }] }
+              ],
+              "minItems": 2,
+              "maxItems": 2
+            }
+          },
+          "required": ["Variable"],
+          "additionalProperties": false
+        },
+        {
+          "type": "object",
+          "properties": {
+            "Rest": { "type": "string" }
+          },
+          "required": ["Rest"],
+          "additionalProperties": false
+        },
+        {
+          "type": "object",
+          "properties": {
+            "Premise": {
+              "type": "array",
+              "prefixItems": [
+                { "$ref": "#/$defs/Premise" },
+                {
+                  "type": "array",
+                  "items": { "$ref": "#/$defs/Judgement" }
+                }
+              ],
+              "minItems": 2,
+              "maxItems": 2
+            }
+          },
+          "required": ["Premise"],
+          "additionalProperties": false
+        }
+      ]
+    },
+    "Premise": {
+      "type": "string",
+      "enum": ["Disequality", "OneOf"]
+    },
+    "Derivation": {
+      "type": "object",
+      "properties": {
+        "premises": {
+          "type": "array",
+          "items": { "$ref": "#/$defs/Derivation" }
+        },
+        "conclusion": { "$ref": "#/$defs/Judgement" },
+        "rule_label": { "type": "string" },
+        "is_axiom": { "type": "boolean" },
+        "discharge": { "type": "integer", "minimum": 0 },
+        "bindings": {
+          "type": "array",
+          "items": {
+            "type": "array",
+            "prefixItems": [{ "type": "string" }, { "$ref": "#/$defs/Judgement" }],
+            "minItems": 2,
+            "maxItems": 2
+          }
+        }
+      },
+      "required": ["premises", "conclusion", "rule_label", "is_axiom"],
+      "additionalProperties": false
+    }
+  }
+}
+"##;
+
+// A derivation is the root of the schema, judgements and the other types it
+// is made of are found under `$defs`
+pub fn schema() -> &'static str {
+    SCHEMA
+}
+
+// With the `schemars` feature, schemas derived from the types themselves, which
+// follow their serde attributes without being kept in step by hand
+#[cfg(feature = "schemars")]
+pub fn judgement_schema() -> schemars::Schema {
+    schemars::schema_for!(super::Judgement)
+}
+
+#[cfg(feature = "schemars")]
+pub fn rule_schema() -> schemars::Schema {
+    schemars::schema_for!(super::Rule)
+}
+
+#[cfg(feature = "schemars")]
+pub fn derivation_schema() -> schemars::Schema {
+    schemars::schema_for!(super::Derivation)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::fixtures::nat_rules;
+    use crate::{atom, op, var, FormalSystem, Judgement, Rule};
+
+    // Checks `value` against the keywords the schema uses
+    fn validates(root: &Value, schema: &Value, value: &Value) -> bool {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return validates(root, &root["$defs"][name], value);
+        }
+        if let Some(alternatives) = schema["oneOf"].as_array() {
+            return alternatives
+                .iter()
+                .filter(|alternative| validates(root, alternative, value))
+                .count()
+                == 1;
+        }
+        let typed = match schema["type"].as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("boolean") => value.is_boolean(),
+            Some("null") => value.is_null(),
+            Some("integer") => value.is_u64(),
+            _ => true,
+        };
+        if !typed {
+            return false;
+        }
+
+        if let Some(object) = value.as_object() {
+            let properties = &schema["properties"];
+            let required = schema["required"].as_array().cloned().unwrap_or_default();
+            return required
+                .iter()
+                .all(|key| object.contains_key(key.as_str().unwrap()))
+                && object.iter().all(|(key, field)| {
+                    properties
+                        .get(key)
+                        .is_some_and(|property| validates(root, property, field))
+                });
+        }
+        if let Some(items) = value.as_array() {
+            let fixed = schema["prefixItems"].as_array();
+            let length = |keyword: &str| schema[keyword].as_u64().map(|n| n as usize);
+            return length("minItems").is_none_or(|min| items.len() >= min)
+                && length("maxItems").is_none_or(|max| items.len() <= max)
+                && items.iter().enumerate().all(|(i, item)| match fixed {
+                    Some(fixed) => validates(root, &fixed[i], item),
+                    None => validates(root, &schema["items"], item),
+                });
+        }
+        true
+    }
+
+    #[test]
+    fn serialized_derivations_follow_the_schema() {
+        let schema: Value = serde_json::from_str(schema()).unwrap();
+        let system = FormalSystem::new(nat_rules(), 4);
+
+        let proof = system
+            .verify(&op!("nat", op!("succ", atom("zero"))))
+            .unwrap();
+        let json = serde_json::to_value(&proof).unwrap();
+        assert!(validates(&schema, &schema, &json));
+
+        let proof = system.verify_annotated(&op!("nat", var("x"))).unwrap();
+        let json = serde_json::to_value(&proof).unwrap();
+        assert!(validates(&schema, &schema, &json));
+
+        let judgement = serde_json::to_value(Judgement::Rest(String::from("xs"))).unwrap();
+        assert!(validates(
+            &schema,
+            &schema["$defs"]["Judgement"],
+            &judgement
+        ));
+        assert!(!validates(&schema, &schema, &judgement));
+
+        let premise = serde_json::to_value(Judgement::diseq(var("x"), atom("zero"))).unwrap();
+        assert!(validates(&schema, &schema["$defs"]["Judgement"], &premise));
+    }
+
+    // Each payload validates against the derived schema of its type and
+    // deserializes back to the same value
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn derived_schemas() {
+        fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(
+            schema: schemars::Schema,
+            value: &T,
+        ) {
+            let validator = jsonschema::validator_for(&schema.to_value()).unwrap();
+            let json = serde_json::to_value(value).unwrap();
+            assert!(validator.is_valid(&json), "{}", json);
+            let read: T = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(serde_json::to_value(read).unwrap(), json);
+        }
+
+        let system = FormalSystem::try_from(
+            "
+------ zero
+nat(zero)
+
+nat(n)
+-------- succ
+nat(succ(n))
+",
+        )
+        .unwrap();
+        let proof = system.verify_annotated(&op!("nat", var("x"))).unwrap();
+        round_trip(derivation_schema(), &proof);
+        round_trip(
+            judgement_schema(),
+            &op!("ctx", atom("a"), crate::rest("xs")),
+        );
+        round_trip(
+            judgement_schema(),
+            &Judgement::diseq(var("x"), atom("zero")),
+        );
+        let rule = Rule::new(
+            "succ",
+            vec![op!("nat", var("n"))],
+            op!("nat", op!("succ", var("n"))),
+        )
+        .with_sort("n", "nat")
+        .with_premise_label(0, "h")
+        .with_max_uses(2)
+        .committed();
+        round_trip(rule_schema(), &rule);
+        round_trip(rule_schema(), &system.axioms[1]);
+
+        let validator = jsonschema::validator_for(&rule_schema().to_value()).unwrap();
+        assert!(!validator.is_valid(&serde_json::json!({ "name": "r", "premises": [] })));
+    }
+}
@@ -0,0 +1,676 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::iter::once;
+
+use super::rename_apart;
+use super::{Derivation, FormalSystem, Judgement, Rule, Span, UnificationTable, UnifyError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    // The conclusion unifies with any goal, so the rule proves everything
+    VariableConclusion { rule: String },
+    // Several rules share a name, making derivation labels ambiguous
+    DuplicateRuleName { rule: String, positions: Vec<usize> },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::VariableConclusion { rule } => {
+                write!(f, "Rule {} concludes a bare variable", rule)
+            }
+            ValidationError::DuplicateRuleName { rule, positions } => write!(
+                f,
+                "Rule name {} is used by the rules at positions {}",
+                rule,
+                positions
+                    .iter()
+                    .map(|position| position.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// A step of a derivation that the rule it cites does not justify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerivationError {
+    UnknownRule {
+        rule: String,
+    },
+    // The rule does not unify with the step; `span` locates the rule when it
+    // was parsed from a file
+    Misapplied {
+        rule: String,
+        span: Option<Span>,
+        error: UnifyError,
+    },
+    // The step unifies with the rule, but a disequality or constraint premise
+    // of the rule fails for it
+    SideCondition {
+        rule: String,
+        span: Option<Span>,
+        premise: Judgement,
+    },
+    // The step concludes a judgement unifying with a negative fact
+    Contradicted {
+        rule: String,
+        fact: Judgement,
+    },
+    // The step binds a variable of the rule to a term of another sort
+    IllSorted {
+        rule: String,
+        span: Option<Span>,
+        variable: String,
+        sort: String,
+    },
+}
+
+impl Display for DerivationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DerivationError::UnknownRule { rule } => write!(f, "No rule is named {}", rule),
+            DerivationError::Misapplied {
+                rule,
+                span: Some(span),
+                error,
+            } => write!(f, "Rule {} at line {}: {}", rule, span.line, error),
+            DerivationError::Misapplied {
+                rule,
+                span: None,
+                error,
+            } => write!(f, "Rule {}: {}", rule, error),
+            DerivationError::SideCondition { rule, premise, .. } => {
+                write!(f, "Rule {}: {} does not hold", rule, premise)
+            }
+            DerivationError::Contradicted { rule, fact } => {
+                write!(f, "Rule {}: its conclusion contradicts {}", rule, fact)
+            }
+            DerivationError::IllSorted {
+                rule,
+                variable,
+                sort,
+                ..
+            } => write!(f, "Rule {}: {} is not of sort {}", rule, variable, sort),
+        }
+    }
+}
+
+impl std::error::Error for DerivationError {}
+
+impl FormalSystem {
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors: Vec<ValidationError> = vec![];
+
+        for axiom in &self.axioms {
+            if !matches!(axiom.conclusion, Judgement::Operator { .. }) {
+                errors.push(ValidationError::VariableConclusion {
+                    rule: axiom.name.clone(),
+                });
+            }
+        }
+
+        let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (position, axiom) in self.axioms.iter().enumerate() {
+            positions.entry(&axiom.name).or_default().push(position);
+        }
+        for axiom in &self.axioms {
+            if let Some(positions) = positions.remove(axiom.name.as_str()) {
+                if positions.len() > 1 {
+                    errors.push(ValidationError::DuplicateRuleName {
+                        rule: axiom.name.clone(),
+                        positions,
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Checks every step of `derivation` against the rule it cites, as when the
+    // rules it was found with have since been edited, side conditions,
+    // declared sorts and negative facts included
+    pub fn check_derivation(&self, derivation: &Derivation) -> Result<(), DerivationError> {
+        let mut steps = vec![derivation];
+        while let Some(step) = steps.pop() {
+            self.check_step(step)?;
+            steps.extend(&step.premises);
+        }
+        Ok(())
+    }
+
+    fn check_step(&self, step: &Derivation) -> Result<(), DerivationError> {
+        if let Some(fact) = self.negative_facts.iter().find(|fact| {
+            fact.rename_apart(&step.conclusion)
+                .unify(&step.conclusion)
+                .is_ok()
+        }) {
+            return Err(DerivationError::Contradicted {
+                rule: step.rule_label.clone(),
+                fact: fact.clone(),
+            });
+        }
+
+        if let Judgement::Operator {
+            predicate,
+            subjects,
+        } = &step.conclusion
+        {
+            if step.rule_label == "refl" && self.equality.contains(predicate) && subjects.len() == 2
+            {
+                return subjects[0]
+                    .unify(&subjects[1])
+                    .map(|_| ())
+                    .map_err(|error| DerivationError::Misapplied {
+                        rule: step.rule_label.clone(),
+                        span: None,
+                        error,
+                    });
+            }
+        }
+
+        let taken: HashSet<String> = once(&step.conclusion)
+            .chain(step.premises.iter().map(|premise| &premise.conclusion))
+            .flat_map(Judgement::get_variables)
+            .collect();
+        let proved: Vec<&Judgement> = step
+            .premises
+            .iter()
+            .map(|premise| &premise.conclusion)
+            .collect();
+        let mut first_error: Option<DerivationError> = None;
+
+        for axiom in self
+            .axioms
+            .iter()
+            .filter(|axiom| axiom.name == step.rule_label)
+            .flat_map(Rule::alternatives)
+        {
+            let rule = rename_apart(&axiom, &taken);
+            let (side_conditions, premises): (Vec<&Judgement>, Vec<&Judgement>) =
+                rule.premises.iter().partition(|premise| {
+                    premise.as_disequality().is_some() || self.is_constraint(premise)
+                });
+
+            // The search may have proved the premises in any order, so each
+            // premise of the rule is paired with a proved one that unifies
+            // with it. Pairs are only tried among the judgements a premise
+            // unifies with on its own, once these admit a pairing of all of
+            // them, backtracking on the bindings they share
+            let mut error: Option<DerivationError> = None;
+            let matched = premises.len() == proved.len()
+                && self
+                    .unifiers(&rule.conclusion, &step.conclusion, &UnificationTable::new())
+                    .into_iter()
+                    .any(|substitutions| {
+                        let compatible: Vec<Vec<usize>> = premises
+                            .iter()
+                            .map(|premise| {
+                                (0..proved.len())
+                                    .filter(|&i| {
+                                        !self
+                                            .unifiers(premise, proved[i], &substitutions)
+                                            .is_empty()
+                                    })
+                                    .collect()
+                            })
+                            .collect();
+                        perfect_matching(&compatible, proved.len())
+                            && self.assign(
+                                &premises,
+                                &compatible,
+                                &proved,
+                                &mut vec![false; proved.len()],
+                                substitutions,
+                                &mut |substitutions| match self.side_conditions(
+                                    &rule,
+                                    &side_conditions,
+                                    substitutions,
+                                ) {
+                                    Ok(()) => true,
+                                    Err(side) => {
+                                        error.get_or_insert(DerivationError::from_side(
+                                            side, &rule, axiom.span,
+                                        ));
+                                        false
+                                    }
+                                },
+                            )
+                    });
+            if matched {
+                return Ok(());
+            }
+
+            // Otherwise the error reported is the one for the order of the rule
+            if error.is_none() {
+                let stated = Judgement::operator(
+                    &rule.name,
+                    once(rule.conclusion.clone())
+                        .chain(premises.iter().map(|&premise| premise.clone()))
+                        .collect(),
+                );
+                let applied = Judgement::operator(
+                    &rule.name,
+                    once(step.conclusion.clone())
+                        .chain(proved.iter().map(|&premise| premise.clone()))
+                        .collect(),
+                );
+                error = stated
+                    .unify(&applied)
+                    .err()
+                    .map(|error| DerivationError::Misapplied {
+                        rule: rule.name.clone(),
+                        span: axiom.span,
+                        error,
+                    });
+            }
+            if let Some(error) = error {
+                first_error.get_or_insert(error);
+            }
+        }
+
+        Err(first_error.unwrap_or(DerivationError::UnknownRule {
+            rule: step.rule_label.clone(),
+        }))
+    }
+
+    fn unifiers(
+        &self,
+        left: &Judgement,
+        right: &Judgement,
+        substitutions: &UnificationTable,
+    ) -> Vec<UnificationTable> {
+        if self.commutative.is_empty() {
+            left.would_unify(right, substitutions).into_iter().collect()
+        } else {
+            left.unify_modulo_with_substitution(right, substitutions, &self.commutative)
+        }
+    }
+
+    // Pairs `premises` in order with unused `proved` judgements among the
+    // `compatible` ones, calling `complete` on the bindings of each full
+    // pairing until it accepts one
+    fn assign(
+        &self,
+        premises: &[&Judgement],
+        compatible: &[Vec<usize>],
+        proved: &[&Judgement],
+        used: &mut Vec<bool>,
+        substitutions: UnificationTable,
+        complete: &mut dyn FnMut(&UnificationTable) -> bool,
+    ) -> bool {
+        let Some((premise, rest)) = premises.split_first() else {
+            return complete(&substitutions);
+        };
+        for &i in &compatible[0] {
+            if used[i] {
+                continue;
+            }
+            used[i] = true;
+            let found = self
+                .unifiers(premise, proved[i], &substitutions)
+                .into_iter()
+                .any(|substitutions| {
+                    self.assign(
+                        rest,
+                        &compatible[1..],
+                        proved,
+                        used,
+                        substitutions,
+                        complete,
+                    )
+                });
+            used[i] = false;
+            if found {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn side_conditions(
+        &self,
+        rule: &Rule,
+        side_conditions: &[&Judgement],
+        substitutions: &UnificationTable,
+    ) -> Result<(), SideError> {
+        for premise in side_conditions {
+            let holds = match premise.as_disequality() {
+                Some((left, right)) => {
+                    self.disequalities_hold(&[(left.clone(), right.clone())], substitutions)
+                }
+                None => self.constraints_hold(&[(*premise).clone()], substitutions),
+            };
+            if !holds {
+                return Err(SideError::Premise((*premise).clone()));
+            }
+        }
+
+        if let Some(sort_of) = &self.sort_of {
+            for (variable, sort) in &rule.sorts {
+                let Some(binding) = substitutions.get(variable) else {
+                    continue;
+                };
+                let term = binding.apply_substitution(substitutions);
+                if matches!(term, Judgement::Operator { .. })
+                    && sort_of(&term).is_some_and(|term_sort| term_sort != *sort)
+                {
+                    return Err(SideError::Sort(variable.clone(), sort.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether each row of `compatible` can be given a distinct column, by
+// augmenting paths
+fn perfect_matching(compatible: &[Vec<usize>], columns: usize) -> bool {
+    fn augment(
+        row: usize,
+        compatible: &[Vec<usize>],
+        owner: &mut [Option<usize>],
+        visited: &mut [bool],
+    ) -> bool {
+        for &column in &compatible[row] {
+            if visited[column] {
+                continue;
+            }
+            visited[column] = true;
+            let free = match owner[column] {
+                None => true,
+                Some(other) => augment(other, compatible, owner, visited),
+            };
+            if free {
+                owner[column] = Some(row);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owner: Vec<Option<usize>> = vec![None; columns];
+    (0..compatible.len()).all(|row| augment(row, compatible, &mut owner, &mut vec![false; columns]))
+}
+
+enum SideError {
+    Premise(Judgement),
+    Sort(String, String),
+}
+
+impl DerivationError {
+    fn from_side(error: SideError, rule: &Rule, span: Option<Span>) -> Self {
+        match error {
+            SideError::Premise(premise) => DerivationError::SideCondition {
+                rule: rule.name.clone(),
+                span,
+                premise,
+            },
+            SideError::Sort(variable, sort) => DerivationError::IllSorted {
+                rule: rule.name.clone(),
+                span,
+                variable,
+                sort,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::nat_rules;
+    use crate::{atom, op, var, Rule};
+
+    #[test]
+    fn variable_conclusion() {
+        let nat = nat_rules();
+        assert_eq!(FormalSystem::new(nat.clone(), 8).validate(), Ok(()));
+
+        let mut degenerate = nat;
+        degenerate.push(Rule::taut("anything", var("x")));
+        assert_eq!(
+            FormalSystem::new(degenerate, 8).validate(),
+            Err(vec![ValidationError::VariableConclusion {
+                rule: String::from("anything")
+            }])
+        );
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("zero", op!("nat", atom("zero"))),
+                Rule::new(
+                    "succ",
+                    vec![op!("nat", var("n"))],
+                    op!("nat", op!("succ", var("n"))),
+                ),
+                Rule::new(
+                    "succ",
+                    vec![op!("even", var("n"))],
+                    op!("odd", op!("succ", var("n"))),
+                ),
+            ],
+            8,
+        );
+
+        let errors = system.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ValidationError::DuplicateRuleName {
+                rule: String::from("succ"),
+                positions: vec![1, 2]
+            }]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "Rule name succ is used by the rules at positions 1, 2"
+        );
+    }
+
+    #[test]
+    fn derivation_checks() {
+        let height = "
+----------------- h1
+hgt(empty(), zero())
+
+------------------------ max1
+max(n, zero(), n)
+
+hgt(t1, n1)    hgt(t2, n2)    max(n1, n2, n)
+-------------------------------------------- h2
+hgt(node(t1, t2), succ(n))
+";
+        let system = FormalSystem::try_from(height).unwrap();
+        let goal = op!(
+            "hgt",
+            op!("node", atom("empty"), atom("empty")),
+            op!("succ", atom("zero"))
+        );
+        let proof = system.verify(&goal).unwrap();
+        assert_eq!(system.check_derivation(&proof), Ok(()));
+
+        // The conclusion of h2 lost its successor
+        let edited = FormalSystem::try_from(
+            height
+                .replace("hgt(node(t1, t2), succ(n))", "hgt(node(t1, t2), n)")
+                .as_str(),
+        )
+        .unwrap();
+        assert_eq!(edited.axioms[2].span(), Some(Span { line: 8, column: 1 }));
+        let error = edited.check_derivation(&proof).unwrap_err();
+        assert_eq!(
+            error,
+            DerivationError::Misapplied {
+                rule: String::from("h2"),
+                span: Some(Span { line: 8, column: 1 }),
+                error: UnifyError::DifferentPredicates {
+                    left: String::from("zero"),
+                    right: String::from("succ"),
+                },
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Rule h2 at line 8: Different predicates: zero != succ"
+        );
+
+        let assumed = system
+            .verify_under(
+                &[op!("hgt", atom("leaf"), atom("zero"))],
+                &op!("hgt", atom("leaf"), var("n")),
+            )
+            .unwrap();
+        assert_eq!(
+            system.check_derivation(&assumed),
+            Err(DerivationError::UnknownRule {
+                rule: String::from("hyp")
+            })
+        );
+    }
+
+    fn step(rule: &str, conclusion: Judgement, premises: Vec<Derivation>) -> Derivation {
+        Derivation {
+            is_axiom: premises.is_empty(),
+            premises,
+            conclusion,
+            rule_label: String::from(rule),
+            discharge: None,
+            bindings: vec![],
+        }
+    }
+
+    #[test]
+    fn side_conditions_and_sorts() {
+        let colors = FormalSystem::new(
+            vec![
+                Rule::taut("red", op!("color", atom("red"))),
+                Rule::taut("green", op!("color", atom("green"))),
+                Rule::new(
+                    "pair",
+                    vec![
+                        Judgement::diseq(var("x"), var("y")),
+                        op!("color", var("x")),
+                        op!("color", var("y")),
+                    ],
+                    op!("pair", var("x"), var("y")),
+                ),
+            ],
+            8,
+        );
+        let proof = colors
+            .verify(&op!("pair", atom("red"), atom("green")))
+            .unwrap();
+        assert_eq!(colors.check_derivation(&proof), Ok(()));
+
+        // Both premises prove the same color
+        let red = || step("red", op!("color", atom("red")), vec![]);
+        let same = step(
+            "pair",
+            op!("pair", atom("red"), atom("red")),
+            vec![red(), red()],
+        );
+        assert_eq!(
+            colors.check_derivation(&same),
+            Err(DerivationError::SideCondition {
+                rule: String::from("pair"),
+                span: None,
+                premise: Judgement::diseq(var("x"), var("y")),
+            })
+        );
+
+        let boxes = FormalSystem::new(
+            vec![
+                Rule::taut("any", op!("any", var("x"))),
+                Rule::new("wrap", vec![op!("any", var("n"))], op!("box", var("n")))
+                    .with_sort("n", "nat"),
+            ],
+            8,
+        )
+        .with_sort_inference(|term| match term {
+            Judgement::Operator { predicate, .. } if predicate == "zero" => {
+                Some(String::from("nat"))
+            }
+            Judgement::Operator { .. } => Some(String::from("tree")),
+            _ => None,
+        });
+        let proof = boxes.verify(&op!("box", atom("zero"))).unwrap();
+        assert_eq!(boxes.check_derivation(&proof), Ok(()));
+        let tree = step(
+            "wrap",
+            op!("box", atom("empty")),
+            vec![step("any", op!("any", atom("empty")), vec![])],
+        );
+        assert_eq!(
+            boxes.check_derivation(&tree),
+            Err(DerivationError::IllSorted {
+                rule: String::from("wrap"),
+                span: None,
+                variable: String::from("n"),
+                sort: String::from("nat"),
+            })
+        );
+    }
+
+    #[test]
+    fn premises_in_any_order() {
+        let count = 12;
+        let variables: Vec<Judgement> = (0..count).map(|i| var(&format!("x{}", i))).collect();
+        let system = FormalSystem::new(
+            vec![
+                Rule::taut("p", op!("p", var("x"))),
+                Rule::new(
+                    "all",
+                    variables.iter().map(|x| op!("p", x.clone())).collect(),
+                    Judgement::operator("all", variables.clone()),
+                ),
+            ],
+            8,
+        );
+        let values: Vec<Judgement> = (0..count).map(|i| atom(&format!("a{}", i))).collect();
+        let leaf = |value: &Judgement| step("p", op!("p", value.clone()), vec![]);
+        let mut proof = step(
+            "all",
+            Judgement::operator("all", values.clone()),
+            values.iter().map(leaf).collect(),
+        );
+        proof.premises.reverse();
+        assert_eq!(system.check_derivation(&proof), Ok(()));
+
+        proof.premises[0] = leaf(&atom("b"));
+        assert!(system.check_derivation(&proof).is_err());
+
+        // Premises that all unify with each other are paired quickly too, even
+        // when one of them cannot be paired at all
+        let anything = FormalSystem::new(
+            vec![Rule::new(
+                "all",
+                variables.iter().map(|x| op!("p", x.clone())).collect(),
+                atom("all"),
+            )],
+            8,
+        );
+        let mut proof = step("all", atom("all"), values.iter().map(leaf).collect());
+        assert_eq!(
+            anything.check_derivation(&proof),
+            Err(DerivationError::UnknownRule {
+                rule: String::from("p")
+            })
+        );
+        proof.premises[count - 1] = step("q", op!("q", atom("a")), vec![]);
+        assert!(matches!(
+            anything.check_derivation(&proof),
+            Err(DerivationError::Misapplied { .. })
+        ));
+    }
+}
@@ -15,46 +15,43 @@ fn main() {
     }
 
     {
-        let nat = FormalSystem::new(
-            vec![
-                Rule::taut("zero", op!("nat", zero())),
-                Rule::new(
-                    "succ",
-                    vec![op!("nat", var("n"))],
-                    op!("nat", succ(var("n"))),
-                ),
-                Rule::new(
-                    "tree",
-                    vec![op!("tree", var("a1")), op!("tree", var("a2"))],
-                    op!("tree", op!("node", var("a1"), var("a2"))),
-                ),
-                Rule::taut("empty", op!("tree", atom("empty"))),
-                Rule::taut("s1", op!("sum", var("n"), zero(), var("n"))),
-                Rule::new(
-                    "s2",
-                    vec![op!("sum", var("n"), var("m"), var("p"))],
-                    op!("sum", var("n"), succ(var("m")), succ(var("p"))),
-                ),
-                Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
-                Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
-                Rule::new(
-                    "max3",
-                    vec![op!("max", var("n"), var("m"), var("p"))],
-                    op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
-                ),
-                Rule::taut("h1", op!("hgt", atom("empty"), zero())),
-                Rule::new(
-                    "h2",
-                    vec![
-                        op!("hgt", var("t1"), var("n1")),
-                        op!("hgt", var("t2"), var("n2")),
-                        op!("max", var("n1"), var("n2"), var("n")),
-                    ],
-                    op!("hgt", op!("node", var("t1"), var("t2")), succ(var("n"))),
-                ),
-            ],
-            8,
-        );
+        let nat = FormalSystem::from_rules(vec![
+            Rule::taut("zero", op!("nat", zero())),
+            Rule::new(
+                "succ",
+                vec![op!("nat", var("n"))],
+                op!("nat", succ(var("n"))),
+            ),
+            Rule::new(
+                "tree",
+                vec![op!("tree", var("a1")), op!("tree", var("a2"))],
+                op!("tree", op!("node", var("a1"), var("a2"))),
+            ),
+            Rule::taut("empty", op!("tree", atom("empty"))),
+            Rule::taut("s1", op!("sum", var("n"), zero(), var("n"))),
+            Rule::new(
+                "s2",
+                vec![op!("sum", var("n"), var("m"), var("p"))],
+                op!("sum", var("n"), succ(var("m")), succ(var("p"))),
+            ),
+            Rule::taut("max1", op!("max", var("n"), zero(), var("n"))),
+            Rule::taut("max2", op!("max", zero(), var("n"), var("n"))),
+            Rule::new(
+                "max3",
+                vec![op!("max", var("n"), var("m"), var("p"))],
+                op!("max", succ(var("n")), succ(var("m")), succ(var("p"))),
+            ),
+            Rule::taut("h1", op!("hgt", atom("empty"), zero())),
+            Rule::new(
+                "h2",
+                vec![
+                    op!("hgt", var("t1"), var("n1")),
+                    op!("hgt", var("t2"), var("n2")),
+                    op!("max", var("n1"), var("n2"), var("n")),
+                ],
+                op!("hgt", op!("node", var("t1"), var("t2")), succ(var("n"))),
+            ),
+        ]);
 
         let proof = nat.verify(&op!("nat", succ(zero()))).unwrap();
         println!("Prova:{}", proof.to_string_tree());
@@ -125,6 +122,5 @@ fn main() {
             .verify(&op!("hgt", var("x"), succ(succ(zero()))))
             .unwrap();
         println!("Const:{}", proof.to_string_tree());
-
     }
 }